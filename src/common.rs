@@ -9,10 +9,13 @@ pub mod extract;
 pub mod info;
 pub mod error;
 
-const MAX_SIZE_BYTES: u64 = 48 * 1024 * 1024;
+pub(crate) const MAX_SIZE_BYTES: u64 = 48 * 1024 * 1024;
 // const BUFFER_SIZE: usize = 16 * 1024; // 16KiB Buffering
 
-pub const SUPPORTED_EXTENSIONS: &[&str] = &["it", "xm", "s3m", "mod", "umx", "mptm"];
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "it", "xm", "s3m", "mod", "umx", "uax", "mptm", "its", "iti", "xi", "s3i", "pat", "sf2", "mdz",
+    "s3z", "xmz", "itz", "xrns", "xrni", "mdgz", "s3gz", "xmgz", "itgz", "oxm", "mo3",
+];
 
 pub use extract::extract;
 
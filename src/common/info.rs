@@ -1,7 +1,9 @@
 use std::borrow::Cow;
 use std::fs::{self, read_dir};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use crate::interface::TrackerInfo;
 use crate::{load_module, Error};
 
 use super::error::{no_filename, not_empty, too_large};
@@ -17,6 +19,14 @@ pub struct Info {
     pub format: String,
     pub total_samples: usize,
     pub total_sample_size: usize,
+    /// Song message, or text hidden in the sample names
+    pub comments: String,
+    /// Tracker software that created the module, if it could be detected
+    pub created_with: Option<TrackerInfo>,
+    /// How long the song plays for, if the format stores one
+    pub duration: Option<Duration>,
+    /// The song repeats itself instead of ending
+    pub loops: bool,
 }
 
 impl Info {
@@ -30,12 +40,17 @@ impl Info {
 
         let module = load_module(&mut fs::File::open(file)?)?;
         let total_sample_size: usize = module.samples().iter().map(|m| m.length as usize).sum();
+        let duration = module.duration();
 
         let info = Info {
             name: module.name().into(),
             format: module.format().into(),
             total_samples: module.total_samples(),
             total_sample_size: total_sample_size / 1000,
+            comments: module.comments().into_owned(),
+            created_with: module.created_with(),
+            duration: duration.map(|d| d.length),
+            loops: duration.is_some_and(|d| d.loops),
         };
 
         Ok(info)
@@ -1,10 +1,13 @@
 //! XMODITS Digital Signal Processing module
 //! 
+pub mod adpcm;
 pub mod deltadecode;
+pub mod flac;
 pub mod frames;
 pub mod pcm;
 pub mod resampler;
 pub mod sample;
+pub mod vorbis;
 
 pub use resampler::{resample, resample_raw};
 pub use sample::{RawSample, SampleBuffer};
@@ -0,0 +1,72 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::borrow::Cow;
+
+use crate::interface::module::GenericTracker;
+use crate::interface::sample::{PcmType, Sample};
+use crate::interface::Error;
+
+/// ModPlug ADPCM samples start with a table of 16 signed deltas.
+pub const ADPCM4_TABLE_SIZE: usize = 16;
+
+/// Size of a ModPlug ADPCM sample in bytes, given its decoded length.
+pub fn adpcm4_len(length: u32) -> u32 {
+    ADPCM4_TABLE_SIZE as u32 + length.div_ceil(2)
+}
+
+/// Decode ModPlug's 4 bit ADPCM into 8 bit signed pcm.
+///
+/// Each nibble (low nibble first) indexes the delta table.
+/// Returns ``None`` if the buffer is too small to contain the table.
+pub fn decode_adpcm4(buf: &[u8], length: usize) -> Option<Vec<u8>> {
+    let table = buf.get(..ADPCM4_TABLE_SIZE)?;
+    let data = &buf[ADPCM4_TABLE_SIZE..];
+
+    let mut pcm: Vec<u8> = Vec::with_capacity(length);
+    let mut value: u8 = 0;
+
+    for byte in data.iter().take(length.div_ceil(2)) {
+        for nibble in [byte & 0x0F, byte >> 4] {
+            value = value.wrapping_add(table[nibble as usize]);
+            pcm.push(value);
+        }
+    }
+
+    pcm.truncate(length);
+    Some(pcm)
+}
+
+/// Obtain a sample's pcm, decoding it if it's ADPCM compressed.
+pub(crate) fn read_pcm<'a>(
+    inner: &'a GenericTracker,
+    smp: &Sample,
+) -> Result<Cow<'a, [u8]>, Error> {
+    match smp.pcm_type {
+        PcmType::ADPCM4 => decode_adpcm4(inner.get_slice_trailing(smp)?, smp.length as usize)
+            .map(Cow::Owned)
+            .ok_or_else(|| Error::bad_sample(smp)),
+        _ => Ok(inner.get_slice(smp)?.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adpcm4() {
+        let mut buf: Vec<u8> = (0..16u8).map(|i| (i as i8 - 8) as u8).collect();
+        buf.extend_from_slice(&[0x9A, 0x07, 0x0F]);
+
+        // deltas: +2, +1, -1, -8, +7, -8
+        let pcm = decode_adpcm4(&buf, 5).unwrap();
+        assert_eq!(pcm, [2, 3, 2, (-6i8) as u8, 1]);
+        assert_eq!(adpcm4_len(5), 16 + 3);
+        assert!(decode_adpcm4(&buf[..8], 5).is_none());
+    }
+}
@@ -0,0 +1,50 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::interface::{Error, Sample};
+
+/// Decode a FLAC stream into planar little endian PCM matching the sample's bit depth.
+#[cfg(feature = "flac")]
+pub fn decode_flac(buf: &[u8], smp: &Sample) -> Result<Vec<u8>, Error> {
+    use crate::interface::sample::Depth;
+
+    let flac_error = |_| Error::invalid("Could not decode FLAC stream");
+
+    let mut reader = claxon::FlacReader::new(std::io::Cursor::new(buf)).map_err(flac_error)?;
+    let channels = smp.channels() as usize;
+    let mut planes: Vec<Vec<i32>> = vec![Vec::with_capacity(smp.length_frames()); channels];
+
+    let mut blocks = reader.blocks();
+    let mut buffer = Vec::new();
+
+    while let Some(block) = blocks.read_next_or_eof(buffer).map_err(flac_error)? {
+        for (ch, plane) in planes.iter_mut().enumerate() {
+            plane.extend_from_slice(block.channel(ch as u32));
+        }
+        buffer = block.into_buffer();
+    }
+
+    let mut pcm: Vec<u8> = Vec::with_capacity(smp.length as usize);
+
+    for sample in planes.iter().flatten() {
+        match smp.depth {
+            Depth::I8 | Depth::U8 => pcm.push(*sample as i8 as u8),
+            Depth::I16 | Depth::U16 => pcm.extend_from_slice(&(*sample as i16).to_le_bytes()),
+            Depth::I24 => pcm.extend_from_slice(&sample.to_le_bytes()[..3]),
+            Depth::I32 => pcm.extend_from_slice(&sample.to_le_bytes()),
+        }
+    }
+
+    Ok(pcm)
+}
+
+#[cfg(not(feature = "flac"))]
+pub fn decode_flac(_: &[u8], _: &Sample) -> Result<Vec<u8>, Error> {
+    Err(Error::unsupported(
+        "FLAC samples can only be decoded with the \"flac\" feature enabled",
+    ))
+}
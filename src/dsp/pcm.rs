@@ -111,6 +111,116 @@ fn _reduce_bit_depth_u16_to_u8(pcm_16_bit: &[u16]) -> Vec<u8> {
     pcm_16_bit.iter().map(quantize).collect()
 }
 
+/// Ensures the pcm only contains whole 24-bit samples
+///
+/// Any trailing bytes are discarded.
+#[inline]
+pub fn align_u24(pcm_24_bit: &mut Vec<u8>) {
+    let remainder = pcm_24_bit.len() % 3;
+
+    if remainder != 0 {
+        warn!("Unaligned 24-bit pcm detected!");
+        pcm_24_bit.truncate(pcm_24_bit.len() - remainder);
+    }
+}
+
+/// Reduce bit depth of 24 bit sample to 16 bit sample.
+/// The sign is preserved.
+#[inline]
+pub fn reduce_bit_depth_24_to_16(pcm_24_bit: &[u8]) -> Vec<u8> {
+    pcm_24_bit
+        .chunks_exact(3)
+        .flat_map(|sample| [sample[1], sample[2]])
+        .collect()
+}
+
+/// Reduce bit depth of 24 bit sample to 8 bit sample.
+/// The sign is preserved.
+#[inline]
+pub fn reduce_bit_depth_24_to_8(pcm_24_bit: &[u8]) -> Vec<u8> {
+    pcm_24_bit.chunks_exact(3).map(|sample| sample[2]).collect()
+}
+
+/// Convert packed 24-bit PCM samples to big endian.
+#[inline]
+pub fn to_be_24(mut pcm: Vec<u8>) -> Vec<u8> {
+    align_u24(&mut pcm);
+    pcm.chunks_exact_mut(3).for_each(|sample| sample.swap(0, 2));
+    pcm
+}
+
+/// Expand packed 24-bit samples into 32-bit samples.
+///
+/// The lowest byte is padded with zeros so that the samples use the full range of an ``i32``.
+#[inline]
+pub fn expand_24_to_32_bit(pcm_24_bit: &[u8]) -> Vec<i32> {
+    pcm_24_bit
+        .chunks_exact(3)
+        .map(|s| i32::from_le_bytes([0, s[0], s[1], s[2]]))
+        .collect()
+}
+
+/// Pack 32-bit samples into 24-bit samples by discarding the lowest byte.
+#[inline]
+pub fn pack_32_to_24_bit(pcm_32_bit: &[i32]) -> Vec<u8> {
+    pcm_32_bit
+        .iter()
+        .flat_map(|sample| {
+            let [_, a, b, c] = sample.to_le_bytes();
+            [a, b, c]
+        })
+        .collect()
+}
+
+/// Ensures the pcm only contains whole 32-bit samples
+///
+/// Any trailing bytes are discarded.
+#[inline]
+pub fn align_u32(pcm_32_bit: &mut Vec<u8>) {
+    let remainder = pcm_32_bit.len() % 4;
+
+    if remainder != 0 {
+        warn!("Unaligned 32-bit pcm detected!");
+        pcm_32_bit.truncate(pcm_32_bit.len() - remainder);
+    }
+}
+
+/// Reduce bit depth of 32 bit sample to 16 bit sample.
+/// The sign is preserved.
+#[inline]
+pub fn reduce_bit_depth_32_to_16(pcm_32_bit: &[u8]) -> Vec<u8> {
+    pcm_32_bit
+        .chunks_exact(4)
+        .flat_map(|sample| [sample[2], sample[3]])
+        .collect()
+}
+
+/// Reduce bit depth of 32 bit sample to 8 bit sample.
+/// The sign is preserved.
+#[inline]
+pub fn reduce_bit_depth_32_to_8(pcm_32_bit: &[u8]) -> Vec<u8> {
+    pcm_32_bit.chunks_exact(4).map(|sample| sample[3]).collect()
+}
+
+/// Convert 32-bit PCM samples to big endian.
+#[inline]
+pub fn to_be_32(mut pcm: Vec<u8>) -> Vec<u8> {
+    align_u32(&mut pcm);
+    pcm.chunks_exact_mut(4).for_each(|sample| sample.reverse());
+    pcm
+}
+
+/// Read little endian 32-bit samples.
+///
+/// The pcm isn't cast, since it may not be aligned to 4 bytes.
+#[inline]
+pub fn read_32_bit(pcm_32_bit: &[u8]) -> Vec<i32> {
+    pcm_32_bit
+        .chunks_exact(4)
+        .map(|s| i32::from_le_bytes([s[0], s[1], s[2], s[3]]))
+        .collect()
+}
+
 /// Interleave data.
 ///
 /// LLLLLRRRRR -> LRLRLRLRLR
@@ -165,6 +275,25 @@ fn _interleave_16_bit(pcm: &[u16]) -> Vec<u16> {
     interleave(pcm).collect()
 }
 
+/// Interleave packed 24 bit samples
+#[inline]
+pub fn interleave_24_bit(pcm: &[u8]) -> Vec<u8> {
+    let samples: Vec<[u8; 3]> = pcm.chunks_exact(3).map(|s| [s[0], s[1], s[2]]).collect();
+
+    interleave(&samples).flatten().collect()
+}
+
+/// Interleave 32 bit samples
+#[inline]
+pub fn interleave_32_bit(pcm: &[u8]) -> Vec<u8> {
+    let samples: Vec<[u8; 4]> = pcm
+        .chunks_exact(4)
+        .map(|s| [s[0], s[1], s[2], s[3]])
+        .collect();
+
+    interleave(&samples).flatten().collect()
+}
+
 #[inline]
 pub fn deinterleave_8_bit(pcm: &[u8]) -> (Vec<u8>, Vec<u8>) {
     let (l, r) = deinterleave(pcm);
@@ -207,6 +336,29 @@ mod tests {
         assert_eq!(_interleave_16_bit(&pcm), expected);
     }
 
+    #[test]
+    fn bit_depth_24() {
+        use super::{expand_24_to_32_bit, pack_32_to_24_bit, reduce_bit_depth_24_to_16};
+
+        let pcm: [u8; 6] = [0x01, 0x02, 0x03, 0xff, 0xff, 0xff];
+        assert_eq!(expand_24_to_32_bit(&pcm), [0x0302_0100, -256]);
+        assert_eq!(pack_32_to_24_bit(&expand_24_to_32_bit(&pcm)), pcm);
+        assert_eq!(reduce_bit_depth_24_to_16(&pcm), [0x02, 0x03, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn bit_depth_32() {
+        use super::{read_32_bit, reduce_bit_depth_32_to_16, to_be_32};
+
+        let pcm: [u8; 8] = [0x01, 0x02, 0x03, 0x04, 0x00, 0xff, 0xff, 0xff];
+        assert_eq!(read_32_bit(&pcm), [0x0403_0201, -256]);
+        assert_eq!(reduce_bit_depth_32_to_16(&pcm), [0x03, 0x04, 0xff, 0xff]);
+        assert_eq!(
+            to_be_32(pcm.to_vec()),
+            [0x04, 0x03, 0x02, 0x01, 0xff, 0xff, 0xff, 0x00]
+        );
+    }
+
     #[test]
     fn align_check() {
         let is_even = |usize| usize % 2 == 0;
@@ -3,6 +3,7 @@ use rubato::Resampler;
 // use crate::dsp::sample::{self};
 use crate::interface::sample::Depth;
 
+use super::pcm::pack_32_to_24_bit;
 use super::{RawSample, SampleBuffer};
 use super::sample::{convert_to_planar, convert_to_interleaved};
 
@@ -63,6 +64,8 @@ fn convert_planar(depth: Depth, sample_buffer: &SampleBuffer) -> Vec<u8> {
         Depth::U8 => convert_to_planar::<u8>(sample_buffer),
         Depth::I16 => convert_to_planar::<i16>(sample_buffer),
         Depth::U16 => convert_to_planar::<u16>(sample_buffer),
+        Depth::I24 => pack_i24(convert_to_planar::<i32>(sample_buffer)),
+        Depth::I32 => convert_to_planar::<i32>(sample_buffer),
     }
 }
 
@@ -72,9 +75,16 @@ fn convert_interleaved(depth: Depth, sample_buffer: &SampleBuffer) -> Vec<u8> {
         Depth::U8 => convert_to_interleaved::<u8>(sample_buffer),
         Depth::I16 => convert_to_interleaved::<i16>(sample_buffer),
         Depth::U16 => convert_to_interleaved::<u16>(sample_buffer),
+        Depth::I24 => pack_i24(convert_to_interleaved::<i32>(sample_buffer)),
+        Depth::I32 => convert_to_interleaved::<i32>(sample_buffer),
     }
 }
 
+/// 24-bit samples are converted to 32-bit first, since they are not a primitive type.
+fn pack_i24(pcm_32_bit: Vec<u8>) -> Vec<u8> {
+    pack_32_to_24_bit(bytemuck::cast_slice(&pcm_32_bit))
+}
+
 #[cfg(test)]
 mod test {
     use crate::dsp::{resampler::resample, frames::FramesIter, RawSample, SampleBuffer};
@@ -3,7 +3,10 @@ use crate::interface::sample::{Depth, Loop, LoopType, Sample};
 use bytemuck::{cast_slice, Pod};
 use dasp::sample::{FromSample, Sample as SampleConverter};
 
-use super::{pcm::align_u16, frames::SampleFrame};
+use super::{
+    frames::SampleFrame,
+    pcm::{align_u16, align_u24, align_u32, expand_24_to_32_bit, read_32_bit},
+};
 
 
 pub struct RawSample<'a> {
@@ -17,8 +20,11 @@ impl<'a> RawSample<'a> {
 
         assert!(!pcm.is_empty(), "raw sample cannot be empty");
 
-        if !smp.is_8_bit() {
-            align_u16(&mut pcm);
+        match smp.depth {
+            Depth::I8 | Depth::U8 => (),
+            Depth::I16 | Depth::U16 => align_u16(&mut pcm),
+            Depth::I24 => align_u24(&mut pcm),
+            Depth::I32 => align_u32(&mut pcm),
         }
 
         Self { smp, pcm }
@@ -50,6 +56,16 @@ pub struct SampleBuffer {
 }
 
 impl SampleBuffer {
+    /// Create a buffer from planar channels that don't loop
+    pub fn new(buf: Vec<Vec<f32>>, rate: u32) -> Self {
+        Self {
+            rate,
+            rate_original: rate,
+            loop_data: LoopData::default(),
+            buf,
+        }
+    }
+
     pub fn duration(&self) -> usize {
         let Some(chn) = self.buf.get(0) else {
             return 0;
@@ -112,6 +128,8 @@ fn convert_raw_sample(raw_smp: &RawSample) -> SampleBuffer {
         Depth::U8 => convert_buffer::<u8>(pcm, channels),
         Depth::I16 => convert_buffer::<i16>(align(pcm), channels),
         Depth::U16 => convert_buffer::<u16>(align(pcm), channels),
+        Depth::I24 => to_sample_buffer(&expand_24_to_32_bit(pcm), channels),
+        Depth::I32 => to_sample_buffer(&read_32_bit(pcm), channels),
     };
 
     SampleBuffer {
@@ -0,0 +1,66 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::interface::{Error, Sample};
+
+pub const MAGIC_OGGS: [u8; 4] = *b"OggS";
+
+/// Obtain the number of channels from the identification header of an Ogg Vorbis stream.
+///
+/// The header is the only packet in the first Ogg page.
+pub fn vorbis_channels(buf: &[u8]) -> Option<u16> {
+    if buf.get(..4)? != MAGIC_OGGS {
+        return None;
+    }
+
+    // The page header is 27 bytes long, followed by the segment table
+    let segments = *buf.get(26)? as usize;
+    let packet = buf.get(27 + segments..)?;
+
+    if packet.get(..7)? != b"\x01vorbis" {
+        return None;
+    }
+
+    Some(*packet.get(11)? as u16)
+}
+
+/// Decode an Ogg Vorbis stream into planar 16 bit little endian PCM.
+#[cfg(feature = "vorbis")]
+pub fn decode_vorbis(buf: &[u8], smp: &Sample) -> Result<Vec<u8>, Error> {
+    use lewton::inside_ogg::OggStreamReader;
+
+    let vorbis_error = |_| Error::invalid("Could not decode Ogg Vorbis stream");
+
+    let mut reader = OggStreamReader::new(std::io::Cursor::new(buf)).map_err(vorbis_error)?;
+    let channels = reader.ident_hdr.audio_channels as usize;
+    let frames = smp.length_frames();
+    let mut planes: Vec<Vec<i16>> = vec![Vec::with_capacity(frames); channels];
+
+    while let Some(packet) = reader.read_dec_packet_itl().map_err(vorbis_error)? {
+        for frame in packet.chunks_exact(channels) {
+            for (plane, sample) in planes.iter_mut().zip(frame) {
+                plane.push(*sample);
+            }
+        }
+    }
+
+    let mut pcm: Vec<u8> = Vec::with_capacity(smp.length as usize);
+
+    for plane in planes.iter().take(smp.channels() as usize) {
+        let plane = &plane[..plane.len().min(frames)];
+        pcm.extend(plane.iter().flat_map(|sample| sample.to_le_bytes()));
+    }
+
+    Ok(pcm)
+}
+
+#[cfg(not(feature = "vorbis"))]
+pub fn decode_vorbis(_: &[u8], _: &Sample) -> Result<Vec<u8>, Error> {
+    Err(Error::unsupported(
+        "Ogg Vorbis samples can only be decoded with the \"vorbis\" feature enabled",
+    ))
+}
@@ -9,7 +9,7 @@ use std::{borrow::Cow, io::Write};
 
 use super::helper::PCMFormatter;
 use crate::interface::audio::AudioTrait;
-use crate::interface::sample::{Channel, Depth, Sample};
+use crate::interface::sample::{Channel, Depth, Loop, LoopType, Sample};
 use crate::interface::Error;
 use bytemuck::cast_slice;
 use extended::Extended;
@@ -30,7 +30,8 @@ impl AudioTrait for Aiff {
         const FORM: [u8; 4] = *b"FORM";
         const AIFF: [u8; 4] = *b"AIFF";
         const COMM: [u8; 4] = *b"COMM";
-        // const MARK: [u8; 4] = *b"MARK";
+        const MARK: [u8; 4] = *b"MARK";
+        const INST: [u8; 4] = *b"INST";
         const SSND: [u8; 4] = *b"SSND";
 
         const OFFSET: [u8; 4] = 0_u32.to_be_bytes();
@@ -43,7 +44,27 @@ impl AudioTrait for Aiff {
         let sample_frames: u32 = (smp.length as u32 / smp.bytes() as u32) / channels as u32;
 
         let chunk_size: u32 = pcm.len() as u32 + 4 + 4; // pcm len, offset, block size
-        let aiff_chunk_size: u32 = 4 + 26 + 16 + pcm.len() as u32; // This will change if we include the instrument
+
+        // AIFF has a sustain loop & a release loop.
+        // The regular loop is used as the sustain loop if there isn't one,
+        // otherwise it's played after the note is released.
+        let (sustain_loop, release_loop) = match smp.sustain.filter(|l| !l.is_disabled()) {
+            Some(sustain) => (sustain, smp.looping),
+            None => (smp.looping, Loop::default()),
+        };
+        let loops: Vec<Loop> = [sustain_loop, release_loop]
+            .into_iter()
+            .filter(|l| !l.is_disabled())
+            .collect();
+
+        // Each marker is 8 bytes: id, position, empty name (padded)
+        let mark_chunk_size: u32 = 2 + 8 * 2 * loops.len() as u32;
+        let loop_chunks_size: u32 = match loops.is_empty() {
+            true => 0,
+            false => (8 + mark_chunk_size) + (8 + 20),
+        };
+
+        let aiff_chunk_size: u32 = 4 + 26 + loop_chunks_size + 16 + pcm.len() as u32;
 
         let mut write = |data: &[u8]| writer.write_all(data);
 
@@ -60,16 +81,46 @@ impl AudioTrait for Aiff {
         write(&sample_size.to_be_bytes())?;
         write(&sample_rate.to_be_bytes())?;
 
-        // // Marker chunk (loop information)
-        // write(&MARK)?;
-        // write(todo!())?; // chunk size
-        // write(todo!())?; // num markers
-        // write(todo!())?; // id
-        // write(todo!())?; // position (start?)
-        // write(todo!())?; // marker name
-        // write(todo!())?; // id
-        // write(todo!())?; // position (end?)
-        // write(todo!())?; // marker name
+        if !loops.is_empty() {
+            // Marker chunk (loop information), markers are numbered from 1
+            write(&MARK)?;
+            write(&mark_chunk_size.to_be_bytes())?;
+            write(&(loops.len() as u16 * 2).to_be_bytes())?;
+
+            for (id, looping) in (1_u16..).step_by(2).zip(&loops) {
+                write(&id.to_be_bytes())?;
+                write(&looping.start().to_be_bytes())?;
+                write(&[0, 0])?; // marker name
+                write(&(id + 1).to_be_bytes())?;
+                write(&looping.end().to_be_bytes())?;
+                write(&[0, 0])?; // marker name
+            }
+
+            // Instrument chunk, 20 bytes
+            write(&INST)?;
+            write(&20_u32.to_be_bytes())?;
+            write(&[60, 0, 0, 127, 1, 127])?; // base note, detune, note & velocity range
+            write(&0_i16.to_be_bytes())?; // gain
+
+            let mut markers = (1_u16..).step_by(2);
+
+            for looping in [sustain_loop, release_loop] {
+                let play_mode: u16 = match looping.kind() {
+                    LoopType::Off => 0,
+                    LoopType::Forward | LoopType::Backward => 1,
+                    LoopType::PingPong => 2,
+                };
+                let start = match looping.is_disabled() {
+                    true => 0,
+                    false => markers.next().unwrap(),
+                };
+                let end = start + (start != 0) as u16;
+
+                write(&play_mode.to_be_bytes())?;
+                write(&start.to_be_bytes())?;
+                write(&end.to_be_bytes())?;
+            }
+        }
 
         // sound data chunk, 16 bytes
         write(&SSND)?;
@@ -85,6 +136,8 @@ impl AudioTrait for Aiff {
             Depth::I16 => pcm.to_be_16(),
             Depth::U8 => pcm.flip_sign_8(),
             Depth::U16 => pcm.flip_sign_16().to_be_16(),
+            Depth::I24 => pcm.to_be_24(),
+            Depth::I32 => pcm.to_be_32(),
         };
 
         // Stereo samples are interleaved
@@ -92,6 +145,8 @@ impl AudioTrait for Aiff {
             Channel::Stereo { interleaved: false } => match smp.depth {
                 Depth::I8 | Depth::U8 => write(&pcm.interleave_8()),
                 Depth::I16 | Depth::U16 => write(cast_slice(&pcm.interleave_16())),
+                Depth::I24 => write(&pcm.interleave_24()),
+                Depth::I32 => write(&pcm.interleave_32()),
             },
             _ => write(&pcm),
         }?;
@@ -99,3 +154,38 @@ impl AudioTrait for Aiff {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sustain_and_release_loops() {
+        let sample = Sample {
+            length: 64,
+            rate: 8363,
+            depth: Depth::I8,
+            looping: Loop::new(0, 64, LoopType::Forward),
+            sustain: Some(Loop::new(8, 16, LoopType::PingPong)),
+            ..Default::default()
+        };
+
+        let mut aiff: Vec<u8> = Vec::new();
+        Aiff.write(&sample, [0u8; 64].as_slice().into(), &mut aiff).unwrap();
+
+        let u16_at = |i: usize| u16::from_be_bytes(aiff[i..i + 2].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_be_bytes(aiff[i..i + 4].try_into().unwrap());
+        assert_eq!(u32_at(4) as usize, aiff.len() - 8);
+
+        let mark = 12 + 26;
+        assert_eq!(&aiff[mark..mark + 4], b"MARK");
+        assert_eq!(u16_at(mark + 8), 4);
+        assert_eq!(u32_at(mark + 12), 8); // sustain loop start
+        assert_eq!(u32_at(mark + 20), 16); // sustain loop end
+
+        let inst = mark + 8 + u32_at(mark + 4) as usize;
+        assert_eq!(&aiff[inst..inst + 4], b"INST");
+        assert_eq!(&aiff[inst + 16..inst + 28], [0, 2, 0, 1, 0, 2, 0, 1, 0, 3, 0, 4]);
+        assert_eq!(&aiff[inst + 28..inst + 32], b"SSND");
+    }
+}
@@ -110,6 +110,8 @@ impl AudioTrait for Iff {
             Depth::U8 => write(&pcm.flip_sign_8()),
             Depth::I16 => write(&pcm.reduce_bit_depth_16_to_8()),
             Depth::U16 => write(&pcm.reduce_bit_depth_16_to_8().flip_sign_8()),
+            Depth::I24 => write(&pcm.reduce_bit_depth_24_to_8()),
+            Depth::I32 => write(&pcm.reduce_bit_depth_32_to_8()),
         }?;
 
         // write pad byte if length of pcm is odd
@@ -7,8 +7,9 @@
 
 use std::{borrow::Cow, io::Write};
 
+use super::helper::PCMFormatter;
 use crate::interface::audio::AudioTrait;
-use crate::interface::sample::LoopType;
+use crate::interface::sample::{Depth, Loop, LoopType};
 use crate::interface::{Error, Sample};
 use crate::parser::string::to_ascii_array;
 
@@ -36,23 +37,27 @@ impl AudioTrait for Its {
         const SAMPLE_PTR: u32 = 0x50;
         const SAMPLE_FLAG: u8 = 0b_0000_0001;
         const CVT: u8 = 0;
-        const ZERO_U32: [u8; 4] = 0_u32.to_le_bytes();
         const ZERO_U8: [u8; 1] = 0_u8.to_le_bytes();
-        const VOL: [u8; 1] = [64];
+        const PAN_ENABLED: u8 = 1 << 7;
 
         let filename: [u8; 12] = to_ascii_array(smp.filename.as_deref().unwrap_or_default());
         let name: [u8; 26] = to_ascii_array(smp.name());
 
+        let sustain = smp.sustain.unwrap_or_default();
+        let meta = &smp.meta;
+        let vibrato = &meta.vibrato;
+
+        // Impulse Tracker's panning ranges from 0 to 64
+        let dfp = match meta.panning {
+            Some(pan) => PAN_ENABLED | ((pan as u16 * 64 + 127) / 255) as u8,
+            None => 0,
+        };
+
         let flags = SAMPLE_FLAG
             | (!smp.is_8_bit() as u8) << 1
             | (smp.is_stereo() as u8) << 2 // TODO: impulse tracker does not support stereo samples
-            | (!smp.looping.is_disabled() as u8) << 4
-            // TODO: improve looping parameters, check assumptions
-            | match smp.looping.kind() {
-                LoopType::Backward => FLAG_SUSTAIN,
-                LoopType::PingPong => FLAG_PINGPONG,
-                _ => 0,
-            };
+            | loop_flags(&smp.looping, FLAG_LOOP, FLAG_PINGPONG)
+            | loop_flags(&sustain, FLAG_SUSTAIN, FLAG_PINGPONG_SUSTAIN);
 
         let cvt = CVT | (smp.depth.is_signed() as u8);
 
@@ -64,28 +69,44 @@ impl AudioTrait for Its {
         writer.write_all(&HEADER)?; // IMPS
         writer.write_all(&filename)?; // dos filename
         writer.write_all(&ZERO_U8)?; // zero
-        writer.write_all(&VOL)?; // global volume
+        writer.write_all(&[meta.global_volume.min(64)])?; // global volume
         writer.write_all(&[flags])?; // flags
-        writer.write_all(&VOL)?; // vol
+        writer.write_all(&[meta.volume.min(64)])?; // vol
         writer.write_all(&name)?; // name
         writer.write_all(&[cvt])?; // cvt
-        writer.write_all(&ZERO_U8)?; // dfp
+        writer.write_all(&[dfp])?; // dfp
         writer.write_all(&length.to_le_bytes())?; // length
         writer.write_all(&loop_start.to_le_bytes())?; // loop begin
         writer.write_all(&loop_end.to_le_bytes())?; // loop end
         writer.write_all(&c5speed.to_le_bytes())?; // c5speed
-        writer.write_all(&ZERO_U32)?; // susloopbegin
-        writer.write_all(&ZERO_U32)?; // susloopend
+        writer.write_all(&sustain.start().to_le_bytes())?; // susloopbegin
+        writer.write_all(&sustain.end().to_le_bytes())?; // susloopend
         writer.write_all(&SAMPLE_PTR.to_le_bytes())?; // sample pointer
-        writer.write_all(&ZERO_U8)?; // vis
-        writer.write_all(&ZERO_U8)?; // vid
-        writer.write_all(&ZERO_U8)?; // vir
-        writer.write_all(&ZERO_U8)?; // vit
+        writer.write_all(&[vibrato.speed])?; // vis
+        writer.write_all(&[vibrato.depth])?; // vid
+        writer.write_all(&[vibrato.sweep])?; // vir
+        writer.write_all(&[vibrato.waveform.to_it()])?; // vit
+
+        // Impulse Tracker samples can't be larger than 16 bits
+        let pcm = match smp.depth {
+            Depth::I24 => pcm.reduce_bit_depth_24_to_16(),
+            Depth::I32 => pcm.reduce_bit_depth_32_to_16(),
+            _ => pcm,
+        };
 
         Ok(writer.write_all(&pcm)?)
     }
 }
 
+/// Impulse Tracker can't play loops backwards, so they're exported as forward loops.
+fn loop_flags(looping: &Loop, enabled: u8, pingpong: u8) -> u8 {
+    match looping.kind() {
+        LoopType::Off => 0,
+        LoopType::PingPong => enabled | pingpong,
+        LoopType::Forward | LoopType::Backward => enabled,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
@@ -61,7 +61,7 @@ impl AudioTrait for S3i {
         writer.write_all(&length.to_le_bytes())?;
         writer.write_all(&loop_start.to_le_bytes())?;
         writer.write_all(&loop_end.to_le_bytes())?;
-        writer.write_all(&[64u8])?; // volume
+        writer.write_all(&[smp.meta.volume.min(64)])?; // volume
         writer.write_all(&[0u8])?; // dummy
         writer.write_all(&[0u8])?; // packed
         writer.write_all(&[flags])?; // flags
@@ -73,8 +73,15 @@ impl AudioTrait for S3i {
         writer.write_all(&name)?; // sample name
         writer.write_all(&SCRI)?; // scri (or scrs)
 
-        let pcm = match smp.is_signed() {
-            true => flip_sign(pcm, smp.depth),
+        // Scream Tracker 3 samples can't be larger than 16 bits
+        let (pcm, depth) = match smp.depth {
+            Depth::I24 => (pcm.reduce_bit_depth_24_to_16(), Depth::I16),
+            Depth::I32 => (pcm.reduce_bit_depth_32_to_16(), Depth::I16),
+            depth => (pcm, depth),
+        };
+
+        let pcm = match depth.is_signed() {
+            true => flip_sign(pcm, depth),
             false => pcm,
         };
 
@@ -10,7 +10,7 @@ use std::{borrow::Cow, io::Write};
 
 use super::helper::PCMFormatter;
 use crate::interface::audio::AudioTrait;
-use crate::interface::sample::{Channel, Depth, LoopType, Sample};
+use crate::interface::sample::{Channel, Depth, Loop, LoopType, Sample};
 use crate::interface::Error;
 
 #[derive(Clone, Copy)]
@@ -31,13 +31,21 @@ impl AudioTrait for Wav {
         const SMPL: [u8; 4] = *b"smpl";
         const WAV_SCS: [u8; 4] = 16_u32.to_le_bytes();
         const WAV_TYPE: [u8; 2] = 1_u16.to_le_bytes();
-        const SMPL_CHUNK_SIZE: u32 = 36 + 24;
+
+        // The sustain loop is written after the regular loop
+        let loops: Vec<Loop> = [Some(smp.looping), smp.sustain]
+            .into_iter()
+            .flatten()
+            .filter(|l| !l.is_disabled())
+            .collect();
+
+        let smpl_chunk_size: u32 = 36 + 24 * loops.len() as u32;
 
         // To avoid nasty bugs in future, explicitly cast the types.
         let mut size: u32 = HEADER_SIZE - 8 + pcm.len() as u32;
 
-        if !smp.looping.is_disabled() {
-            size += SMPL_CHUNK_SIZE;
+        if !loops.is_empty() {
+            size += 8 + smpl_chunk_size;
         }
 
         let pcm_len: u32 = pcm.len() as u32;
@@ -71,39 +79,32 @@ impl AudioTrait for Wav {
             on native systems, it will do nothing.
         */
         let pcm = match smp.depth {
-            Depth::U8 | Depth::I16 => pcm,
+            Depth::U8 | Depth::I16 | Depth::I24 | Depth::I32 => pcm,
             Depth::I8 => pcm.flip_sign_8(),
             Depth::U16 => pcm.flip_sign_16(),
         };
 
         match smp.channel {
-            Channel::Stereo { interleaved: false } => match smp.is_8_bit() {
-                true => write(&pcm.interleave_8()),
-                false => write(cast_slice(&pcm.interleave_16())),
+            Channel::Stereo { interleaved: false } => match smp.depth {
+                Depth::I8 | Depth::U8 => write(&pcm.interleave_8()),
+                Depth::I16 | Depth::U16 => write(cast_slice(&pcm.interleave_16())),
+                Depth::I24 => write(&pcm.interleave_24()),
+                Depth::I32 => write(&pcm.interleave_32()),
             },
             _ => write(&pcm),
         }?;
 
         // Write smpl chunk
-        if !smp.looping.is_disabled() {
+        if !loops.is_empty() {
             const ZERO: [u8; 4] = [0u8; 4];
 
             let period: u32 = (1_000_000_000.0 / frequency as f64).round() as u32;
             let midi_note: u32 = 60;
             let midi_pitch: u32 = 1;
-            let sample_loops: u32 = 1;
-
-            let loop_start: u32 = smp.looping.start();
-            let loop_end: u32 = smp.looping.end();
-            let loop_type: u32 = match smp.looping.kind() {
-                LoopType::Off => unreachable!(),
-                LoopType::Forward => 0,
-                LoopType::Backward => 2,
-                LoopType::PingPong => 1,
-            };
+            let sample_loops: u32 = loops.len() as u32;
 
             write(&SMPL)?;
-            write(&SMPL_CHUNK_SIZE.to_le_bytes())?;
+            write(&smpl_chunk_size.to_le_bytes())?;
             write(&ZERO)?; // manufacturer
             write(&ZERO)?; // product
             write(&period.to_le_bytes())?;
@@ -113,12 +114,24 @@ impl AudioTrait for Wav {
             write(&ZERO)?; // SMPTE offset
             write(&sample_loops.to_le_bytes())?;
             write(&ZERO)?; // sample data
-            write(&ZERO)?; // unique ID of loop
-            write(&loop_type.to_le_bytes())?;
-            write(&loop_start.to_le_bytes())?;
-            write(&loop_end.to_le_bytes())?;
-            write(&ZERO)?; // fraction
-            write(&ZERO)?; // repeats
+
+            for (id, looping) in loops.iter().enumerate() {
+                let loop_start: u32 = looping.start();
+                let loop_end: u32 = looping.end();
+                let loop_type: u32 = match looping.kind() {
+                    LoopType::Off => unreachable!(),
+                    LoopType::Forward => 0,
+                    LoopType::Backward => 2,
+                    LoopType::PingPong => 1,
+                };
+
+                write(&(id as u32).to_le_bytes())?; // unique ID of loop
+                write(&loop_type.to_le_bytes())?;
+                write(&loop_start.to_le_bytes())?;
+                write(&loop_end.to_le_bytes())?;
+                write(&ZERO)?; // fraction
+                write(&ZERO)?; // repeats
+            }
         }
 
         Ok(())
@@ -126,4 +139,34 @@ impl AudioTrait for Wav {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sustain_loop() {
+        let sample = Sample {
+            length: 64,
+            rate: 8363,
+            depth: Depth::U8,
+            looping: Loop::new(0, 64, LoopType::Forward),
+            sustain: Some(Loop::new(8, 16, LoopType::PingPong)),
+            ..Default::default()
+        };
+
+        let mut wav: Vec<u8> = Vec::new();
+        Wav.write(&sample, [0u8; 64].as_slice().into(), &mut wav).unwrap();
+
+        let u32_at = |i: usize| u32::from_le_bytes(wav[i..i + 4].try_into().unwrap());
+        assert_eq!(u32_at(4) as usize, wav.len() - 8);
+
+        let smpl = 44 + 64;
+        assert_eq!(&wav[smpl..smpl + 4], b"smpl");
+        assert_eq!(u32_at(smpl + 4), 36 + 24 * 2);
+        assert_eq!(u32_at(smpl + 36), 2);
+
+        let sustain = smpl + 44 + 24;
+        assert_eq!(u32_at(sustain + 4), 1); // ping pong
+        assert_eq!(u32_at(sustain + 8), 8);
+        assert_eq!(u32_at(sustain + 12), 16);
+    }
+}
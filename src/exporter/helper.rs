@@ -8,8 +8,10 @@
 use std::borrow::Cow;
 
 use crate::dsp::pcm::{
-    flip_sign_16_bit, flip_sign_8_bit, interleave_16_bit, interleave_8_bit,
-    reduce_bit_depth_16_to_8, to_be_16, to_le_16, deinterleave_8_bit, deinterleave_16_bit,
+    flip_sign_16_bit, flip_sign_8_bit, interleave_16_bit, interleave_24_bit, interleave_32_bit,
+    interleave_8_bit, reduce_bit_depth_16_to_8, reduce_bit_depth_24_to_16,
+    reduce_bit_depth_24_to_8, reduce_bit_depth_32_to_16, reduce_bit_depth_32_to_8, to_be_16,
+    to_be_24, to_be_32, to_le_16, deinterleave_8_bit, deinterleave_16_bit,
 };
 
 /// Helper trait to allow chaining operations.
@@ -38,6 +40,18 @@ pub trait PCMFormatter {
     ///
     /// Which interleaves them to: LRLRLRLR
     fn interleave_16(self) -> Vec<u16>;
+    /// Interleave packed 24-bit PCM.
+    ///
+    /// Assumes the samples are stored as: LLLLRRRR
+    ///
+    /// Which interleaves them to: LRLRLRLR
+    fn interleave_24(self) -> Vec<u8>;
+    /// Interleave 32-bit PCM.
+    ///
+    /// Assumes the samples are stored as: LLLLRRRR
+    ///
+    /// Which interleaves them to: LRLRLRLR
+    fn interleave_32(self) -> Vec<u8>;
     // /// denterleave 8-bit PCM.
     // ///
     // /// Assumes the samples are stored as: LRLRLRLR
@@ -52,6 +66,18 @@ pub trait PCMFormatter {
     // fn deinterleave_16(self) -> Vec<u16>;
     /// Convert 16-bit PCM samples to 8-bit.
     fn reduce_bit_depth_16_to_8(self) -> Self;
+    /// Convert packed 24-bit PCM samples to 16-bit.
+    fn reduce_bit_depth_24_to_16(self) -> Self;
+    /// Convert packed 24-bit PCM samples to 8-bit.
+    fn reduce_bit_depth_24_to_8(self) -> Self;
+    /// Convert packed 24-bit PCM samples to big endian.
+    fn to_be_24(self) -> Self;
+    /// Convert 32-bit PCM samples to 16-bit.
+    fn reduce_bit_depth_32_to_16(self) -> Self;
+    /// Convert 32-bit PCM samples to 8-bit.
+    fn reduce_bit_depth_32_to_8(self) -> Self;
+    /// Convert 32-bit PCM samples to big endian.
+    fn to_be_32(self) -> Self;
 }
 
 impl PCMFormatter for Cow<'_, [u8]> {
@@ -90,6 +116,38 @@ impl PCMFormatter for Cow<'_, [u8]> {
         reduce_bit_depth_16_to_8(self.into_owned()).into()
     }
 
+    fn interleave_24(self) -> Vec<u8> {
+        interleave_24_bit(&self)
+    }
+
+    fn reduce_bit_depth_24_to_16(self) -> Self {
+        reduce_bit_depth_24_to_16(&self).into()
+    }
+
+    fn reduce_bit_depth_24_to_8(self) -> Self {
+        reduce_bit_depth_24_to_8(&self).into()
+    }
+
+    fn to_be_24(self) -> Self {
+        to_be_24(self.into_owned()).into()
+    }
+
+    fn interleave_32(self) -> Vec<u8> {
+        interleave_32_bit(&self)
+    }
+
+    fn reduce_bit_depth_32_to_16(self) -> Self {
+        reduce_bit_depth_32_to_16(&self).into()
+    }
+
+    fn reduce_bit_depth_32_to_8(self) -> Self {
+        reduce_bit_depth_32_to_8(&self).into()
+    }
+
+    fn to_be_32(self) -> Self {
+        to_be_32(self.into_owned()).into()
+    }
+
     // fn deinterleave_8(self) -> Vec<u8> {
     //     deinterleave_8_bit(&self)
     // }
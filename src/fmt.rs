@@ -7,9 +7,20 @@
 
 pub mod fmt_it;
 pub mod fmt_it_compression;
+pub mod fmt_it_extensions;
+pub mod fmt_iti;
+pub mod fmt_its;
+pub mod fmt_mo3;
+pub mod fmt_mo3_compression;
 pub mod fmt_mod;
+pub mod fmt_pat;
+pub mod fmt_s3i;
 pub mod fmt_s3m;
+pub mod fmt_sf2;
 pub mod fmt_umx;
+pub mod fmt_xi;
 pub mod fmt_xm;
+pub mod fmt_zip;
 pub mod loader;
-pub use loader::{formats, Format};
+pub mod scan;
+pub use loader::{formats, Compression, Format};
@@ -6,14 +6,26 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use super::fmt_it_compression::{decompress_16_bit, decompress_8_bit};
+use super::fmt_it_extensions::read_extensions;
+use super::fmt_xm::delta_decode;
 use crate::interface::module::{GenericTracker, Module};
-use crate::interface::sample::{is_sample_valid, Channel, Depth, Loop, LoopType, PcmType, Sample};
-use crate::interface::Error;
+use crate::interface::sample::{
+    is_sample_valid, Channel, Depth, Loop, LoopType, PcmType, Sample, SampleMeta, Vibrato, Waveform,
+};
+use crate::interface::instrument::{
+    Envelope, EnvelopeLoop, EnvelopePoint, Instrument, Keymap, KeymapEntry, NewNoteAction,
+};
+use crate::interface::pattern::{
+    trim_channels, Cell, Effects, Note, Pattern, NOTE_MAX,
+};
+use crate::interface::song::{FrequencyMode, Song};
+use crate::interface::usage::{sample_usage, Instruments, SampleUsage};
+use crate::interface::{Error, TrackerInfo};
 use crate::parser::{
     bitflag::BitFlag,
-    bytes::magic_header,
+    bytes::{le_u16, magic_header},
     io::{is_magic, non_consume, read_exact_const, ByteReader, ReadSeek},
-    string::read_str,
+    string::{read_message, read_str, read_string},
 };
 use crate::{info, warn};
 use std::borrow::Cow;
@@ -25,9 +37,34 @@ const NAME: &str = "Impulse Tracker";
 /* Magic values */
 const MAGIC_IMPM: [u8; 4] = *b"IMPM";
 const MAGIC_IMPS: [u8; 4] = *b"IMPS";
+const MAGIC_IMPI: [u8; 4] = *b"IMPI";
 const MAGIC_ZIRCONIA: [u8; 8] = *b"ziRCONia";
 const MAGIC_IT215: u16 = 0x0215;
 
+/// Impulse Tracker patterns can store up to 64 channels
+const IT_CHANNELS: u8 = 64;
+
+/// Bit 2 of the song flags means instruments are used
+const SONG_INSTRUMENTS: u16 = 1 << 2;
+
+/// Bit 3 of the song flags means linear slides are used
+const SONG_LINEAR: u16 = 1 << 3;
+
+/// Bit 0 of the special field means a song message is attached
+const SPECIAL_MESSAGE: u16 = 1;
+
+/// Modules made with older versions use the old instrument format
+const OLD_INSTRUMENTS: u16 = 0x0200;
+/// Both instrument formats are 554 bytes
+const IT_INS_SIZE: usize = 554;
+/// Sample headers are 80 bytes
+const IT_SMP_SIZE: usize = 80;
+
+const ENV_ENABLED: u8 = 1;
+const ENV_LOOP: u8 = 1 << 1;
+const ENV_SUSTAIN: u8 = 1 << 2;
+const ENV_FILTER: u8 = 1 << 7;
+
 /* Sample flags */
 const FLAG_BITS_16: u8 = 1 << 1;
 const FLAG_STEREO: u8 = 1 << 2;
@@ -37,20 +74,29 @@ const FLAG_SUSTAIN: u8 = 1 << 5;
 const FLAG_PINGPONG: u8 = 1 << 6;
 const FLAG_PINGPONG_SUSTAIN: u8 = 1 << 7;
 
+/// Bit 7 of the default pan enables it
+const PAN_ENABLED: u8 = 1 << 7;
+
 /* Cvt flags */
 const CVT_SIGNED: u8 = 1; // IT 2.01 and below use unsigned samples
 const CVT_DELTA: u8 = 1 << 2; // off = PCM values, ON = Delta values
+const CVT_BITS_24: u8 = 1 << 4; // uncompressed 16 bit PCM is actually 24 bit
+const CVT_BITS_32: u8 = 1 << 5; // uncompressed 16 bit PCM is actually 32 bit
 
 const UNSUPPORTED: &str = "Impulse Tracker Module uses 'ziRCON' sample compression";
 const INVALID: &str = "Not a valid Impulse Tracker module";
-const DELTA_PCM: &str =
-    "This Impulse Tracker sample is stored as delta values. Samples may sound quiet.";
 
 /// Impulse Tracker module
 pub struct IT {
     inner: GenericTracker,
     samples: Box<[Sample]>,
     title: Box<str>,
+    comments: Box<str>,
+    tracker: Option<TrackerInfo>,
+    patterns: Box<[Pattern]>,
+    song: Song,
+    /// Only used if the module is in instrument mode
+    instruments: Option<Box<[Instrument]>>,
     source: Option<Box<Path>>,
     version: u16,
 }
@@ -70,22 +116,42 @@ impl Module for IT {
         NAME
     }
 
-    fn pcm(&self, smp: &Sample) -> Result<Cow<[u8]>, Error> {
-        let pcm = if smp.pcm_type.is_compressed() {
-            let compressed = self.inner.get_slice_trailing(smp)?;
-            let it215 = smp.pcm_type == PcmType::IT215;
-            decompress(smp)(
-                compressed,
-                smp.length_frames() as u32,
-                it215,
-                smp.is_stereo(),
-            )?
-            .into()
-        } else {
-            self.inner.get_slice(smp)?.into()
+    fn comments(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.comments)
+    }
+
+    fn created_with(&self) -> Option<TrackerInfo> {
+        self.tracker.clone()
+    }
+
+    fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    fn song(&self) -> Option<&Song> {
+        Some(&self.song)
+    }
+
+    fn instruments(&self) -> &[Instrument] {
+        self.instruments.as_deref().unwrap_or_default()
+    }
+
+    fn sample_usage(&self) -> Option<Box<[SampleUsage]>> {
+        let instruments = match &self.instruments {
+            Some(instruments) => Instruments::Keymaps(instruments),
+            None => Instruments::Samples,
         };
 
-        Ok(pcm)
+        Some(sample_usage(
+            &self.samples,
+            &self.patterns,
+            &self.song.orders,
+            instruments,
+        ))
+    }
+
+    fn pcm(&self, smp: &Sample) -> Result<Cow<[u8]>, Error> {
+        read_pcm(&self.inner, smp)
     }
 
     fn samples(&self) -> &[Sample] {
@@ -111,6 +177,32 @@ impl Module for IT {
     }
 }
 
+/// Obtain the pcm of an Impulse Tracker sample, decompressing it if needed.
+///
+/// This is shared with the standalone ``.its`` and ``.iti`` loaders.
+pub(crate) fn read_pcm<'a>(
+    inner: &'a GenericTracker,
+    smp: &Sample,
+) -> Result<Cow<'a, [u8]>, Error> {
+    let pcm = if smp.pcm_type.is_compressed() {
+        let compressed = inner.get_slice_trailing(smp)?;
+        let it215 = smp.pcm_type == PcmType::IT215;
+        decompress(smp)(
+            compressed,
+            smp.length_frames() as u32,
+            it215,
+            smp.is_stereo(),
+        )?
+        .into()
+    } else if smp.pcm_type == PcmType::DELTA {
+        delta_decode(smp, inner.get_owned_slice(smp)?).into()
+    } else {
+        inner.get_slice(smp)?.into()
+    };
+
+    Ok(pcm)
+}
+
 #[inline]
 fn decompress(smp: &Sample) -> impl Fn(&[u8], u32, bool, bool) -> Result<Vec<u8>, Error> {
     info!(
@@ -137,43 +229,385 @@ pub fn parse_(file: &mut impl ReadSeek) -> Result<IT, Error> {
     let ord_num = file.read_u16_le()?;
     let ins_num = file.read_u16_le()?;
     let smp_num = file.read_u16_le()?;
-    file.skip_bytes(4)?;
+    let pat_num = file.read_u16_le()?;
 
+    let created_with = file.read_u16_le()?;
     let version = file.read_u16_le()?;
-    file.set_seek_pos((0x00c0 + ord_num + (ins_num * 4)) as u64)?;
+    let flags = file.read_u16_le()?;
+
+    let special = file.read_u16_le()?;
+    let global_volume = file.read_u8()?;
+    file.skip_bytes(1)?; // mix volume
+    let speed = file.read_u8()?;
+    let tempo = file.read_u8()?;
+    file.skip_bytes(2)?; // separation, pitch wheel depth
+
+    let message_length = file.read_u16_le()?;
+    let message_offset = file.read_u32_le()?;
+    let reserved = file.read_u32_le()?;
+    let panning = read_exact_const::<{ IT_CHANNELS as usize }>(file)?;
+
+    file.set_seek_pos(0x00c0)?;
+    let orders = file.read_bytes(ord_num as usize)?.into();
+
+    let mut ins_ptrs: Vec<u32> = Vec::with_capacity(ins_num as usize);
+    for _ in 0..ins_num {
+        ins_ptrs.push(file.read_u32_le()?);
+    }
 
     let mut smp_ptrs: Vec<u32> = Vec::with_capacity(smp_num as usize);
     for _ in 0..smp_num {
         smp_ptrs.push(file.read_u32_le()?);
     }
 
-    let samples = build_samples(file, smp_ptrs)?.into();
-    let inner = file.load_to_memory()?.into();
+    let mut pat_ptrs: Vec<u32> = Vec::with_capacity(pat_num as usize);
+    for _ in 0..pat_num {
+        pat_ptrs.push(file.read_u32_le()?);
+    }
+
+    let headers_end = ins_ptrs
+        .iter()
+        .map(|ptr| *ptr as usize + IT_INS_SIZE)
+        .chain(smp_ptrs.iter().map(|ptr| *ptr as usize + IT_SMP_SIZE))
+        .max()
+        .unwrap_or_default();
+
+    let patterns = read_patterns(file, &pat_ptrs);
+    let instruments = match flags & SONG_INSTRUMENTS != 0 {
+        true => Some(read_instruments(file, &ins_ptrs, version < OLD_INSTRUMENTS).into()),
+        false => None,
+    };
+    let mut samples = build_samples(file, smp_ptrs)?;
+    let buf = file.load_to_memory()?;
+
+    let message_range = message_offset as usize..message_offset as usize + message_length as usize;
+    let comments = match buf.get(message_range) {
+        Some(message) if special & SPECIAL_MESSAGE != 0 => read_message(message),
+        _ => Default::default(),
+    };
+
+    // OpenMPT stores its extensions after the last sample
+    let extensions_start = samples
+        .iter()
+        .map(|smp| sample_end(&buf, smp))
+        .fold(headers_end, usize::max);
+
+    let extensions = read_extensions(&buf, extensions_start, ins_num);
+
+    if let Some(extensions) = &extensions {
+        info!("Found OpenMPT extensions");
+        extensions.apply(&mut samples);
+    }
+
+    let channels = patterns.iter().map(Pattern::channels).max().unwrap_or(0);
 
     Ok(IT {
         title,
-        inner,
-        samples,
+        inner: buf.into(),
+        samples: samples.into(),
+        comments,
+        tracker: tracker_info(created_with, reserved),
+        song: Song {
+            orders,
+            restart: 0,
+            speed,
+            tempo,
+            global_volume: global_volume.min(128),
+            channels,
+            panning: panning[..channels as usize].iter().map(|pan| it_panning(*pan)).collect(),
+            frequencies: match flags & SONG_LINEAR != 0 {
+                true => FrequencyMode::Linear,
+                false => FrequencyMode::Amiga,
+            },
+            effects: Effects::IT,
+        },
+        patterns: patterns.into(),
+        instruments,
         version,
         source: None,
     })
 }
-/*
-todo: saga_musix_-_gleaming.it
-compressed: true
-CVT_DELTA: true
 
-stereo samples: true - but in reality it's not,
-    and because of that it, fails to decompress the samples
+/// Channel panning ranges from 0 to 64, and 100 is surround.
+///
+/// Disabled channels have bit 7 set.
+fn it_panning(pan: u8) -> u8 {
+    const SURROUND: u8 = 100;
+
+    match pan & 0x7F {
+        SURROUND => 128,
+        pan => (pan.min(64) as u16 * 255 / 64) as u8,
+    }
+}
+
+/// Read the instrument headers.
+///
+/// Instruments aren't needed to extract samples,
+/// so a broken instrument is replaced with an empty one.
+fn read_instruments(file: &mut impl ReadSeek, ptrs: &[u32], old_format: bool) -> Vec<Instrument> {
+    ptrs.iter()
+        .map(|ptr| {
+            read_instrument(file, *ptr, old_format).unwrap_or_else(|_e| {
+                warn!("Could not read instrument: {}", _e);
+                Instrument::default()
+            })
+        })
+        .collect()
+}
+
+fn read_instrument(
+    file: &mut impl ReadSeek,
+    ptr: u32,
+    old_format: bool,
+) -> Result<Instrument, Error> {
+    file.set_seek_pos(ptr as u64)?;
+    let header = read_exact_const::<IT_INS_SIZE>(file)?;
+
+    if !magic_header(&MAGIC_IMPI, &header) {
+        return Err(Error::invalid("Not a valid Impulse Tracker instrument"));
+    }
+
+    let u16_at = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]);
+    let name = read_string(&header[0x20..0x3A]);
+    let keymap = read_keymap(&header[0x40..0x130]);
+
+    let instrument = match old_format {
+        true => Instrument {
+            name,
+            keymap,
+            volume_envelope: read_old_envelope(&header),
+            fadeout: u16_at(0x18).min(64) * 128,
+            nna: NewNoteAction::from_it(header[0x1A]),
+            ..Default::default()
+        },
+        false => Instrument {
+            name,
+            keymap,
+            volume_envelope: read_envelope(&header[0x130..], false),
+            panning_envelope: read_envelope(&header[0x182..], true),
+            pitch_envelope: read_envelope(&header[0x1D4..], true),
+            filter: header[0x1D4] & ENV_FILTER != 0,
+            fadeout: u16_at(0x14).min(256) * 64,
+            nna: NewNoteAction::from_it(header[0x11]),
+        },
+    };
 
-bacter_vs_saga_musix_-_ocean_paradise.it
-also CVT_DELTA
+    Ok(instrument)
+}
+
+/// Both the old and new instrument formats store 120 (note, sample) pairs at the same offset.
+fn read_keymap(table: &[u8]) -> Keymap {
+    Keymap::from_fn(|note| {
+        let (mapped, sample) = (table[note as usize * 2], table[note as usize * 2 + 1]);
+
+        KeymapEntry {
+            sample: (sample != 0 && mapped <= NOTE_MAX).then(|| sample as u16 - 1),
+            note: mapped.min(NOTE_MAX),
+        }
+    })
+}
+
+/// Envelope flags, loop points, then 25 (value, tick) nodes
+fn read_envelope(buf: &[u8], signed: bool) -> Envelope {
+    const NODES: u8 = 25;
+
+    let flags = buf[0];
+    let count = buf[1].min(NODES) as usize;
+    let points: Box<[EnvelopePoint]> = buf[6..]
+        .chunks_exact(3)
+        .take(count)
+        .map(|node| EnvelopePoint {
+            tick: u16::from_le_bytes([node[1], node[2]]),
+            value: match signed {
+                true => (node[0] as i8).clamp(-32, 32),
+                false => node[0].min(64) as i8,
+            },
+        })
+        .collect();
+
+    Envelope {
+        enabled: flags & ENV_ENABLED != 0,
+        looping: (flags & ENV_LOOP != 0)
+            .then(|| EnvelopeLoop::new(buf[2], buf[3], points.len()))
+            .flatten(),
+        sustain: (flags & ENV_SUSTAIN != 0)
+            .then(|| EnvelopeLoop::new(buf[4], buf[5], points.len()))
+            .flatten(),
+        points,
+    }
+}
+
+/// Instruments made before IT 2.00 only have a volume envelope.
+///
+/// The flags and loop points are in the header, the nodes are (tick, value) pairs ending with 0xFF.
+fn read_old_envelope(header: &[u8]) -> Envelope {
+    let points: Box<[EnvelopePoint]> = header[0x1F8..0x22A]
+        .chunks_exact(2)
+        .take_while(|node| node[0] != 0xFF)
+        .map(|node| EnvelopePoint {
+            tick: node[0] as u16,
+            value: node[1].min(64) as i8,
+        })
+        .collect();
+
+    let flags = header[0x11];
+
+    Envelope {
+        enabled: flags & ENV_ENABLED != 0,
+        looping: (flags & ENV_LOOP != 0)
+            .then(|| EnvelopeLoop::new(header[0x12], header[0x13], points.len()))
+            .flatten(),
+        sustain: (flags & ENV_SUSTAIN != 0)
+            .then(|| EnvelopeLoop::new(header[0x14], header[0x15], points.len()))
+            .flatten(),
+        points,
+    }
+}
+
+/// Read packed patterns.
+///
+/// Pattern data isn't needed to extract samples, so a broken pattern is replaced with an empty one.
+fn read_patterns(file: &mut impl ReadSeek, ptrs: &[u32]) -> Vec<Pattern> {
+    let mut patterns: Vec<Pattern> = Vec::with_capacity(ptrs.len());
+
+    for ptr in ptrs {
+        // A null pointer is an empty 64 row pattern
+        let pattern = match *ptr {
+            0 => Ok(Pattern::new(64, IT_CHANNELS)),
+            ptr => read_pattern(file, ptr),
+        };
+
+        patterns.push(pattern.unwrap_or_else(|_e| {
+            warn!("Could not read pattern at offset {}: {}", ptr, _e);
+            Pattern::new(64, IT_CHANNELS)
+        }));
+    }
+
+    trim_channels(&mut patterns);
+    patterns
+}
+
+fn read_pattern(file: &mut impl ReadSeek, ptr: u32) -> Result<Pattern, Error> {
+    file.set_seek_pos(ptr as u64)?;
+    let length = file.read_u16_le()?;
+    let rows = file.read_u16_le()?;
+    file.skip_bytes(4)?; // reserved
+
+    Ok(unpack_pattern(&file.read_bytes(length as usize)?, rows))
+}
+
+/// Each cell starts with a channel byte, and a mask byte (if bit 7 is set)
+/// that says which parts of the cell are stored, or repeated from the channel's last cell.
+/// A channel byte of 0 ends the row.
+pub(crate) fn unpack_pattern(buf: &[u8], rows: u16) -> Pattern {
+    let mut pattern = Pattern::new(rows, IT_CHANNELS);
+    let mut masks = [0u8; IT_CHANNELS as usize];
+    let mut last = [Cell::default(); IT_CHANNELS as usize];
+
+    let mut data = buf.iter().copied();
+    let next = |data: &mut dyn Iterator<Item = u8>| data.next().unwrap_or_default();
+    let mut row: u16 = 0;
+
+    while row < rows {
+        // Stop if the pattern data is truncated
+        let Some(channel_variable) = data.next() else {
+            break;
+        };
+
+        if channel_variable == 0 {
+            row += 1;
+            continue;
+        }
+
+        let channel = (channel_variable - 1) & (IT_CHANNELS - 1);
+        let index = channel as usize;
+
+        if channel_variable & 0x80 != 0 {
+            masks[index] = next(&mut data);
+        }
+
+        let mask = masks[index];
+        let last = &mut last[index];
+
+        if mask & 1 != 0 {
+            last.note = match next(&mut data) {
+                note @ 0..=NOTE_MAX => Note::On(note),
+                254 => Note::Cut,
+                255 => Note::Off,
+                _ => Note::Fade,
+            };
+        }
+        if mask & 2 != 0 {
+            last.instrument = next(&mut data);
+        }
+        if mask & 4 != 0 {
+            last.volume = Some(next(&mut data));
+        }
+        if mask & 8 != 0 {
+            last.effect = next(&mut data);
+            last.param = next(&mut data);
+        }
+
+        let cell = pattern.cell_mut(row, channel).unwrap();
+
+        if mask & (1 | 16) != 0 {
+            cell.note = last.note;
+        }
+        if mask & (2 | 32) != 0 {
+            cell.instrument = last.instrument;
+        }
+        if mask & (4 | 64) != 0 {
+            cell.volume = last.volume;
+        }
+        if mask & (8 | 128) != 0 {
+            cell.effect = last.effect;
+            cell.param = last.param;
+        }
+    }
+
+    pattern
+}
 
-*/
-fn build_samples(file: &mut impl ReadSeek, ptrs: Vec<u32>) -> Result<Vec<Sample>, Error> {
+/// Guess the tracker from the "created with tracker version" (cwt) field.
+///
+/// Trackers other than Impulse Tracker use the upper 4 bits to identify themselves.
+/// Schism Tracker uses the reserved field if its version overflows.
+fn tracker_info(cwt: u16, reserved: u32) -> Option<TrackerInfo> {
+    let version = cwt & 0x0FFF;
+    let bcd = |name: &str| TrackerInfo::bcd(name, version >> 8, version & 0xFF);
+
+    let tracker = match cwt >> 12 {
+        // Written by OpenMPT 1.17.02.26 up to 1.18
+        0x0 if cwt == 0x0888 => TrackerInfo::new("OpenMPT", Some("1.17".into())),
+        0x0 if cwt == 0 => return None,
+        0x0 => bcd("Impulse Tracker"),
+        0x1 => TrackerInfo::schism(version, Some(reserved).filter(|r| *r != 0)),
+        0x4 => bcd("pyIT"),
+        0x5 => bcd("OpenMPT"),
+        0x6 => bcd("BeRoTracker"),
+        0x7 => TrackerInfo::new("ITMCK", None),
+        _ => return None,
+    };
+
+    Some(tracker)
+}
+
+/// Build samples from a list of offsets pointing to ``IMPS`` sample headers.
+pub(crate) fn build_samples(
+    file: &mut impl ReadSeek,
+    ptrs: Vec<u32>,
+) -> Result<Vec<Sample>, Error> {
     let mut samples: Vec<Sample> = Vec::with_capacity(ptrs.len());
     info!("Building samples");
 
+    // Compressed samples don't store their size, so the data of the next sample bounds them.
+    let mut data_ptrs: Vec<u32> = Vec::with_capacity(ptrs.len());
+
+    for sample_header in ptrs.iter() {
+        file.set_seek_pos(*sample_header as u64 + 0x48)?;
+        data_ptrs.push(file.read_u32_le()?);
+    }
+
     for (index_raw, sample_header) in ptrs.into_iter().enumerate() {
         file.set_seek_pos(sample_header as u64)?;
 
@@ -193,34 +627,73 @@ fn build_samples(file: &mut impl ReadSeek, ptrs: Vec<u32>) -> Result<Vec<Sample>
         }
 
         let filename = read_str::<12>(file)?;
-        file.skip_bytes(2)?; // zero, gvl
+        file.skip_bytes(1)?; // zero
+        let global_volume = file.read_u8()?;
 
         let flags = file.read_u8()?;
-        file.skip_bytes(1)?; // vol
+        let volume = file.read_u8()?;
 
         let name = read_str::<26>(file)?;
         let cvt = file.read_u8()?;
-        file.skip_bytes(1)?; // dfp
+        let panning = file.read_u8()?;
         file.skip_bytes(4)?; // sample length since it's not empty
 
         let loop_start = file.read_u32_le()?;
         let loop_end = file.read_u32_le()?;
         let rate = file.read_u32_le()?;
-        file.skip_bytes(8)?; // susloopbegin, susloopend
+        let sustain_start = file.read_u32_le()?;
+        let sustain_end = file.read_u32_le()?;
 
         let pointer = file.read_u32_le()?;
         let signed = cvt.contains(CVT_SIGNED);
 
+        let [speed, vib_depth, sweep, waveform] = read_exact_const::<4>(file)?;
+        let meta = SampleMeta {
+            volume: volume.min(64),
+            global_volume: global_volume.min(64),
+            panning: panning
+                .contains(PAN_ENABLED)
+                .then(|| ((panning & 0x7f).min(64) as u16 * 255 / 64) as u8),
+            vibrato: Vibrato {
+                waveform: Waveform::from_it(waveform),
+                speed,
+                depth: vib_depth,
+                sweep,
+            },
+            ..Default::default()
+        };
+
         let pcm_type = match flags.contains(FLAG_COMPRESSION) {
             true => match cvt.contains(CVT_DELTA) {
                 true => PcmType::IT215,
                 false => PcmType::IT214,
             },
-            false => PcmType::PCM,
+            false => match cvt.contains(CVT_DELTA) {
+                true => PcmType::DELTA,
+                false => PcmType::PCM,
+            },
         };
 
-        let depth = Depth::new(!flags.contains(FLAG_BITS_16), signed, signed);
-        let channel = Channel::new(flags.contains(FLAG_STEREO), false);
+        let depth = match Depth::new(!flags.contains(FLAG_BITS_16), signed, signed) {
+            Depth::I16 if pcm_type == PcmType::PCM && cvt.contains(CVT_BITS_24) => Depth::I24,
+            Depth::I16 if pcm_type == PcmType::PCM && cvt.contains(CVT_BITS_32) => Depth::I32,
+            depth => depth,
+        };
+        let mut channel = Channel::new(flags.contains(FLAG_STEREO), false);
+
+        let next_sample = data_ptrs.iter().filter(|ptr| **ptr > pointer).min();
+
+        if channel.is_stereo()
+            && pcm_type.is_compressed()
+            && is_fake_stereo(file, pointer, length, depth.is_8_bit(), next_sample)?
+        {
+            warn!(
+                "Sample at index {} is flagged as stereo, but only has one channel of compressed data",
+                index_raw + 1
+            );
+            channel = Channel::Mono;
+        }
+
         let length = length * depth.bytes() as u32 * channel.channels() as u32; // convert to length in bytes
 
         if !is_sample_valid(pointer, length, file.len(), pcm_type.is_compressed()) {
@@ -230,13 +703,19 @@ fn build_samples(file: &mut impl ReadSeek, ptrs: Vec<u32>) -> Result<Vec<Sample>
 
         let index_raw = index_raw as u16;
         let loop_kind = match flags {
-            f if f.contains(FLAG_PINGPONG_SUSTAIN) => LoopType::PingPong,
+            f if !f.contains(FLAG_LOOP) => LoopType::Off,
             f if f.contains(FLAG_PINGPONG) => LoopType::PingPong,
-            f if f.contains(FLAG_LOOP) => LoopType::Forward,
-            f if f.contains(FLAG_SUSTAIN) => LoopType::Backward,
-            _ => LoopType::Off,
+            _ => LoopType::Forward,
         };
 
+        let sustain_kind = match flags {
+            f if !f.contains(FLAG_SUSTAIN) => LoopType::Off,
+            f if f.contains(FLAG_PINGPONG_SUSTAIN) => LoopType::PingPong,
+            _ => LoopType::Forward,
+        };
+        let sustain = Some(Loop::new(sustain_start, sustain_end, sustain_kind))
+            .filter(|sustain| !sustain.is_disabled());
+
         samples.push(Sample {
             filename: Some(filename),
             name,
@@ -248,12 +727,89 @@ fn build_samples(file: &mut impl ReadSeek, ptrs: Vec<u32>) -> Result<Vec<Sample>
             index_raw,
             pcm_type,
             looping: Loop::new(loop_start, loop_end, loop_kind),
+            sustain,
+            mpt: None,
+            meta,
         })
     }
 
     Ok(samples)
 }
 
+/// Offset after the data of a sample.
+///
+/// Compressed samples don't store their size, so their blocks are walked instead.
+fn sample_end(buf: &[u8], smp: &Sample) -> usize {
+    let start = smp.pointer as usize;
+
+    if !smp.pcm_type.is_compressed() {
+        return start + smp.length as usize;
+    }
+
+    let block_frames = match smp.is_8_bit() {
+        true => 0x8000,
+        false => 0x4000,
+    };
+
+    let blocks = smp.length_frames().div_ceil(block_frames) * smp.channels() as usize;
+    let mut end = start;
+
+    for _ in 0..blocks {
+        let Some(block_size) = le_u16(buf, end) else {
+            break;
+        };
+        end += 2 + block_size as usize;
+    }
+
+    end
+}
+
+/// Some modules (e.g. saga_musix_-_gleaming.it) flag compressed samples as stereo,
+/// but only store one channel.
+///
+/// Walk the compressed blocks of the first channel,
+/// and check if the second channel's block stream is exhausted.
+/// The data of the next sample (if any) also ends the second channel.
+fn is_fake_stereo(
+    file: &mut impl ReadSeek,
+    pointer: u32,
+    len_frames: u32,
+    is_8_bit: bool,
+    next_sample: Option<&u32>,
+) -> Result<bool, Error> {
+    let Some(size) = file.len() else {
+        return Ok(false);
+    };
+    let size = next_sample.map_or(size, |next| size.min(*next as u64));
+
+    let block_frames = match is_8_bit {
+        true => 0x8000,
+        false => 0x4000,
+    };
+
+    let exhausted = non_consume(file, |file| {
+        file.set_seek_pos(pointer as u64)?;
+
+        for _ in 0..len_frames.div_ceil(block_frames) {
+            let Ok(block_size) = file.read_u16_le() else {
+                return Ok(false);
+            };
+            file.skip_bytes(block_size as i64)?;
+        }
+
+        let second_channel = file.seek_position()?;
+
+        if second_channel + 2 > size {
+            return Ok(true);
+        }
+
+        let block_size = file.read_u16_le()?;
+        Ok(second_channel + 2 + block_size as u64 > size)
+    })?;
+
+    Ok(exhausted)
+}
+
 fn check_zirconia(file: &mut impl ReadSeek) -> Result<(), Error> {
     let magic = non_consume(file, |file| read_exact_const::<8>(file))?;
 
@@ -265,8 +821,253 @@ fn check_zirconia(file: &mut impl ReadSeek) -> Result<(), Error> {
 
 #[cfg(test)]
 mod test {
+    use std::io::Cursor;
+
+    use super::*;
     use crate::{fmt::fmt_it::parse_, interface::Module};
 
+    /// Build an Impulse Tracker module with one sample per ``(flags, cvt, length, data)``
+    pub(crate) fn it_module(samples: &[(u8, u8, u32, &[u8])]) -> Vec<u8> {
+        let mut module = MAGIC_IMPM.to_vec();
+        module.resize(0xC0, 0);
+        module[0x24..0x26].copy_from_slice(&(samples.len() as u16).to_le_bytes());
+        module[0x2A..0x2C].copy_from_slice(&MAGIC_IT215.to_le_bytes());
+
+        let headers = 0xC0 + samples.len() * 4;
+        let mut data_ptr = headers + samples.len() * 0x50;
+
+        for i in 0..samples.len() {
+            module.extend_from_slice(&((headers + i * 0x50) as u32).to_le_bytes());
+        }
+
+        for (flags, cvt, length, data) in samples {
+            let mut header = [0u8; 0x50];
+            header[..4].copy_from_slice(&MAGIC_IMPS);
+            header[0x12] = *flags;
+            header[0x2E] = *cvt;
+            header[0x30..0x34].copy_from_slice(&length.to_le_bytes());
+            header[0x3C..0x40].copy_from_slice(&8363u32.to_le_bytes());
+            header[0x48..0x4C].copy_from_slice(&(data_ptr as u32).to_le_bytes());
+            module.extend_from_slice(&header);
+            data_ptr += data.len();
+        }
+
+        for (.., data) in samples {
+            module.extend_from_slice(data);
+        }
+
+        module
+    }
+
+    #[test]
+    fn fake_stereo() {
+        let block: &[u8] = &[3, 0, 1, 0, 0];
+        let stereo = [block, block].concat();
+        let flags = FLAG_COMPRESSION | FLAG_STEREO;
+        let cvt = CVT_SIGNED | CVT_DELTA;
+
+        let module = it_module(&[(flags, cvt, 2, &stereo), (flags, cvt, 2, block)]);
+        let tracker = parse_(&mut Cursor::new(module)).unwrap();
+        let samples = tracker.samples();
+
+        assert!(samples[0].is_stereo());
+        assert_eq!(tracker.pcm(&samples[0]).unwrap().as_ref(), [1, 2, 1, 2]);
+
+        assert!(!samples[1].is_stereo());
+        assert_eq!(samples[1].length, 2);
+        assert_eq!(tracker.pcm(&samples[1]).unwrap().as_ref(), [1, 2]);
+    }
+
+    #[test]
+    fn fake_stereo_before_sample() {
+        let block: &[u8] = &[3, 0, 1, 0, 0];
+        let flags = FLAG_COMPRESSION | FLAG_STEREO;
+        let cvt = CVT_SIGNED | CVT_DELTA;
+
+        let module = it_module(&[(flags, cvt, 2, block), (FLAG_COMPRESSION, cvt, 2, block)]);
+        let tracker = parse_(&mut Cursor::new(module)).unwrap();
+        let samples = tracker.samples();
+
+        assert!(!samples[0].is_stereo());
+        assert_eq!(samples[0].length, 2);
+        assert_eq!(tracker.pcm(&samples[0]).unwrap().as_ref(), [1, 2]);
+        assert_eq!(tracker.pcm(&samples[1]).unwrap().as_ref(), [1, 2]);
+    }
+
+    #[test]
+    fn extended_depth() {
+        let flags = FLAG_BITS_16 | FLAG_LOOP;
+        let compressed = FLAG_BITS_16 | FLAG_COMPRESSION;
+        let pcm: Vec<u8> = (0..24).collect();
+
+        let module = it_module(&[
+            (flags, CVT_SIGNED | CVT_BITS_24, 8, &pcm),
+            (flags, CVT_SIGNED | CVT_BITS_32, 6, &pcm),
+            (compressed, CVT_SIGNED | CVT_BITS_24, 1, &[1, 0, 0]),
+        ]);
+        let tracker = parse_(&mut Cursor::new(module)).unwrap();
+        let samples = tracker.samples();
+
+        assert_eq!(samples[0].depth, Depth::I24);
+        assert_eq!(samples[0].length, 24);
+        assert_eq!(samples[0].length_frames(), 8);
+        assert_eq!(tracker.pcm(&samples[0]).unwrap().as_ref(), pcm);
+
+        assert_eq!(samples[1].depth, Depth::I32);
+        assert_eq!(samples[1].length, 24);
+        assert_eq!(samples[1].length_frames(), 6);
+
+        // Compressed samples can't be more than 16 bits
+        assert_eq!(samples[2].depth, Depth::I16);
+    }
+
+    #[test]
+    fn mpt_extensions() {
+        use crate::fmt::fmt_it_extensions::tests::SONG_BLOCK;
+
+        let mut module = it_module(&[(0, CVT_SIGNED, 2, &[1, 2]), (0, CVT_SIGNED, 2, &[3, 4])]);
+        module.extend_from_slice(SONG_BLOCK);
+
+        let tracker = parse_(&mut Cursor::new(module)).unwrap();
+        let samples = tracker.samples();
+
+        let first = samples[0].mpt.as_ref().unwrap();
+        assert!(first.cue_points.is_empty());
+        assert_eq!(first.tuning, None);
+        assert_eq!(first.compat_flags, Some(0x1234));
+
+        let second = samples[1].mpt.as_ref().unwrap();
+        assert_eq!(second.cue_points.as_ref(), [10, 20, 30]);
+        assert_eq!(second.tuning.as_deref(), Some("Just"));
+        assert_eq!(second.compat_flags, Some(0x1234));
+        assert_eq!(second.mptm_version, Some(4));
+
+        let plain = it_module(&[(0, CVT_SIGNED, 2, &[1, 2])]);
+        let tracker = parse_(&mut Cursor::new(plain)).unwrap();
+        assert!(tracker.samples()[0].mpt.is_none());
+
+        // The song block's magic inside sample data isn't mistaken for extensions
+        let sample: &[u8] = &[SONG_BLOCK, &[0; 2]].concat();
+        let length = sample.len() as u32;
+        let hidden = it_module(&[(0, CVT_SIGNED, length, sample)]);
+        let tracker = parse_(&mut Cursor::new(hidden)).unwrap();
+        assert!(tracker.samples()[0].mpt.is_none());
+    }
+
+    #[test]
+    fn song_message() {
+        let mut module = it_module(&[]);
+        let offset = module.len() as u32;
+        module[0x2E] = SPECIAL_MESSAGE as u8;
+        module[0x36..0x38].copy_from_slice(&11u16.to_le_bytes());
+        module[0x38..0x3C].copy_from_slice(&offset.to_le_bytes());
+        module.extend_from_slice(b"hello\rworld\0");
+
+        let tracker = parse_(&mut Cursor::new(module.clone())).unwrap();
+        assert_eq!(tracker.comments(), "hello\nworld");
+
+        module[0x2E] = 0;
+        let tracker = parse_(&mut Cursor::new(module)).unwrap();
+        assert_eq!(tracker.comments(), "");
+    }
+
+    #[test]
+    fn packed_pattern() {
+        use crate::interface::pattern::{Cell, Note};
+
+        #[rustfmt::skip]
+        let packed = [
+            0x81, 0x0F, 60, 1, 64, 1, 6, // channel 1: note, instrument, volume, effect
+            0x83, 0x01, 255,             // channel 3: note off
+            0,
+            0x81, 0xF0,                  // channel 1: repeat the last cell
+            0x03,                        // channel 3: reuse the last mask
+            254,
+            0,
+        ];
+        let pattern = unpack_pattern(&packed, 4);
+        let cell = Cell {
+            note: Note::On(60),
+            instrument: 1,
+            volume: Some(64),
+            effect: 1,
+            param: 6,
+        };
+
+        assert_eq!(pattern.rows(), 4);
+        assert_eq!(pattern.row(0)[0], cell);
+        assert_eq!(pattern.row(0)[2].note, Note::Off);
+        assert_eq!(pattern.row(1)[0], cell);
+        assert_eq!(pattern.row(1)[2].note, Note::Cut);
+        assert!(pattern.row(2).iter().all(Cell::is_empty));
+    }
+
+    #[test]
+    fn instrument() {
+        let mut ins = MAGIC_IMPI.to_vec();
+        ins.resize(IT_INS_SIZE, 0);
+        ins[0x11] = 3; // fade
+        ins[0x14] = 128; // fadeout
+        ins[0x20..0x24].copy_from_slice(b"bass");
+        // C-5 plays sample 2 at C-4
+        ins[0x40 + 60 * 2..0x40 + 60 * 2 + 2].copy_from_slice(&[48, 2]);
+
+        // Volume envelope: enabled with a sustain loop
+        ins[0x130..0x136].copy_from_slice(&[ENV_ENABLED | ENV_SUSTAIN, 2, 0, 0, 1, 1]);
+        ins[0x136..0x13C].copy_from_slice(&[64, 0, 0, 32, 10, 0]);
+
+        // Filter envelope with a loop that doesn't fit
+        ins[0x1D4..0x1DA].copy_from_slice(&[ENV_FILTER | ENV_LOOP, 1, 0, 4, 0, 0]);
+        ins[0x1DA] = (-8i8) as u8;
+
+        let instrument = read_instrument(&mut Cursor::new(ins), 0, false).unwrap();
+
+        assert_eq!(instrument.name.as_ref(), "bass");
+        assert_eq!(instrument.nna, NewNoteAction::Fade);
+        assert_eq!(instrument.fadeout, 128 * 64);
+        assert_eq!(instrument.keymap.sample(60), Some((1, 48)));
+        assert_eq!(instrument.keymap.sample(61), None);
+
+        let volume = &instrument.volume_envelope;
+        assert!(volume.enabled);
+        assert_eq!(volume.sustain, Some(EnvelopeLoop { start: 1, end: 1 }));
+        assert_eq!(volume.points[1], EnvelopePoint { tick: 10, value: 32 });
+
+        assert!(instrument.filter);
+        assert!(!instrument.pitch_envelope.enabled);
+        assert_eq!(instrument.pitch_envelope.looping, None);
+        assert_eq!(instrument.pitch_envelope.points[0].value, -8);
+    }
+
+    #[test]
+    fn tracker_versions() {
+        let version = |cwt, reserved| tracker_info(cwt, reserved).map(|t| t.to_string());
+
+        assert_eq!(version(0x0214, 0).as_deref(), Some("Impulse Tracker 2.14"));
+        assert_eq!(version(0x5128, 0).as_deref(), Some("OpenMPT 1.28"));
+        assert_eq!(version(0x10FE, 0).as_deref(), Some("Schism Tracker 2010-04-23"));
+        assert_eq!(version(0x1FFF, 366).as_deref(), Some("Schism Tracker 2010-11-01"));
+        assert_eq!(version(0, 0), None);
+    }
+
+    #[test]
+    fn delta_samples() {
+        let module = it_module(&[
+            (0, CVT_SIGNED | CVT_DELTA, 4, &[1, 1, 1, 0xFF]),
+            (FLAG_BITS_16, CVT_SIGNED | CVT_DELTA, 2, &[0x00, 0x01, 0x00, 0x01]),
+            (0, CVT_SIGNED, 2, &[5, 5]),
+        ]);
+        let tracker = parse_(&mut Cursor::new(module)).unwrap();
+        let samples = tracker.samples();
+
+        assert_eq!(samples[0].pcm_type, PcmType::DELTA);
+        assert_eq!(tracker.pcm(&samples[0]).unwrap().as_ref(), [1, 2, 3, 2]);
+        assert_eq!(tracker.pcm(&samples[1]).unwrap().as_ref(), [0x00, 0x01, 0x00, 0x02]);
+
+        assert_eq!(samples[2].pcm_type, PcmType::PCM);
+        assert_eq!(tracker.pcm(&samples[2]).unwrap().as_ref(), [5, 5]);
+    }
+
     #[test]
     pub fn a_() {
         // env_logger::init();
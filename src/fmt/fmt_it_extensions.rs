@@ -0,0 +1,203 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! OpenMPT extension blocks found in IT and MPTM files.
+//!
+//! ``XTPM`` holds extended instrument properties,
+//! ``STPM`` holds extended song properties (including sample cue points and tunings),
+//! and MPTM files end with a ``228`` block containing version specific data.
+//!
+//! Each property is stored as a four character code, a 16 bit size and its data.
+//! Like the block magic, the codes are written as little endian integers,
+//! so they appear reversed in the file, e.g. ``CUES`` is stored as ``SEUC``.
+
+use std::collections::HashMap;
+
+use crate::interface::sample::MptInfo;
+use crate::interface::Sample;
+use crate::parser::bytes::le_u16;
+use crate::parser::string::read_string;
+
+const MAGIC_XTPM: [u8; 4] = *b"XTPM";
+const MAGIC_STPM: [u8; 4] = *b"STPM";
+const MAGIC_MPTM: [u8; 3] = *b"228";
+
+/// Sample cue points
+const CODE_CUES: [u8; 4] = *b"SEUC";
+/// Sample tunings
+const CODE_TUNING: [u8; 4] = *b"NUTS";
+/// Extended compatibility flags
+const CODE_FLAGS: [u8; 4] = *b".FSM";
+
+#[derive(Default, Debug)]
+pub struct Extensions {
+    /// Cue points keyed by the (1 based) sample index
+    cue_points: HashMap<u16, Box<[u32]>>,
+    /// Tuning names keyed by the (1 based) sample index
+    tunings: HashMap<u16, Box<str>>,
+    /// Applies to the whole module
+    compat_flags: Option<u32>,
+    mptm_version: Option<u8>,
+}
+
+impl Extensions {
+    /// Attach the extended sample properties to the samples they belong to
+    pub fn apply(&self, samples: &mut [Sample]) {
+        for smp in samples {
+            let cue_points = self
+                .cue_points
+                .get(&(smp.index_raw() as u16))
+                .cloned()
+                .unwrap_or_default();
+
+            smp.mpt = Some(Box::new(MptInfo {
+                cue_points,
+                tuning: self.tunings.get(&(smp.index_raw() as u16)).cloned(),
+                compat_flags: self.compat_flags,
+                mptm_version: self.mptm_version,
+            }));
+        }
+    }
+}
+
+/// Locate and read OpenMPT's extension blocks.
+///
+/// The blocks are searched for from ``start``,
+/// which should be after the last header and the end of the last sample.
+///
+/// Returns ``None`` if the module wasn't saved with OpenMPT (or ModPlug).
+pub fn read_extensions(buf: &[u8], start: usize, ins_num: u16) -> Option<Extensions> {
+    let buf = buf.get(start..)?;
+    let mut offset = find(buf, &MAGIC_XTPM)
+        .map(|xtpm| skip_instrument_properties(buf, xtpm + 4, ins_num))
+        .or_else(|| find(buf, &MAGIC_STPM))?;
+
+    if buf.get(offset..offset + 4)? != MAGIC_STPM {
+        return None;
+    }
+    offset += 4;
+
+    let mut extensions = Extensions::default();
+
+    while let Some(code) = buf.get(offset..offset + 4) {
+        // Trailing MPTM data
+        if code[..3] == MAGIC_MPTM {
+            extensions.mptm_version = Some(code[3]);
+            break;
+        }
+
+        if !code.iter().all(|c| c.is_ascii_graphic()) {
+            break;
+        }
+
+        let Some(size) = le_u16(buf, offset + 4) else {
+            break;
+        };
+        let Some(data) = buf.get(offset + 6..offset + 6 + size as usize) else {
+            break;
+        };
+
+        match code.try_into().unwrap() {
+            CODE_CUES => {
+                if let Some(sample) = le_u16(data, 0) {
+                    let cues = data[2..]
+                        .chunks_exact(4)
+                        .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+                        .collect();
+                    extensions.cue_points.insert(sample, cues);
+                }
+            }
+            CODE_TUNING => {
+                if let Some(sample) = le_u16(data, 0) {
+                    extensions.tunings.insert(sample, read_string(&data[2..]));
+                }
+            }
+            CODE_FLAGS => {
+                let mut flags = [0u8; 4];
+                let len = data.len().min(4);
+                flags[..len].copy_from_slice(&data[..len]);
+                extensions.compat_flags = Some(u32::from_le_bytes(flags));
+            }
+            _ => (),
+        }
+
+        offset += 6 + size as usize;
+    }
+
+    // The MPTM block isn't always directly after the song properties
+    if extensions.mptm_version.is_none() {
+        extensions.mptm_version = find(&buf[offset.min(buf.len())..], &MAGIC_MPTM)
+            .and_then(|pos| buf.get(offset + pos + 3).copied());
+    }
+
+    Some(extensions)
+}
+
+/// Each instrument property stores its data for every instrument.
+///
+/// Returns the offset after the last property.
+fn skip_instrument_properties(buf: &[u8], mut offset: usize, ins_num: u16) -> usize {
+    while let Some(code) = buf.get(offset..offset + 4) {
+        if code == MAGIC_STPM || !code.iter().all(|c| c.is_ascii_graphic()) {
+            break;
+        }
+
+        let Some(size) = le_u16(buf, offset + 4) else {
+            break;
+        };
+
+        offset += 6 + size as usize * ins_num as usize;
+    }
+
+    offset
+}
+
+fn find(buf: &[u8], magic: &[u8]) -> Option<usize> {
+    buf.windows(magic.len()).position(|w| w == magic)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Instrument properties as OpenMPT writes them for two instruments:
+    /// their fadeout and volume ramping.
+    pub const INSTRUMENT_BLOCK: &[u8] = b"\
+        XTPM\
+        ..OF\x04\x00\x00\x01\x00\x00\x00\x01\x00\x00\
+        ..RV\x02\x00\x00\x00\x00\x00";
+
+    /// Song properties as OpenMPT writes them: the default tempo, compatibility flags,
+    /// and the cue points and tuning of sample 2, followed by the MPTM version.
+    pub const SONG_BLOCK: &[u8] = b"\
+        STPM\
+        ..TD\x02\x00\x7D\x00\
+        .FSM\x02\x00\x34\x12\
+        SEUC\x0E\x00\x02\x00\x0A\x00\x00\x00\x14\x00\x00\x00\x1E\x00\x00\x00\
+        NUTS\x08\x00\x02\x00Just\x00\x00\
+        228\x04";
+
+    #[test]
+    fn read_blocks() {
+        let mut buf = vec![0xAA; 32];
+        buf.extend_from_slice(INSTRUMENT_BLOCK);
+        buf.extend_from_slice(SONG_BLOCK);
+
+        let ext = read_extensions(&buf, 32, 2).unwrap();
+        assert_eq!(ext.cue_points[&2].as_ref(), [10, 20, 30]);
+        assert_eq!(ext.tunings[&2].as_ref(), "Just");
+        assert_eq!(ext.compat_flags, Some(0x1234));
+        assert_eq!(ext.mptm_version, Some(4));
+
+        assert!(read_extensions(&[0xAA; 32], 0, 1).is_none());
+
+        // Sample data before the start isn't searched
+        let mut buf = SONG_BLOCK.to_vec();
+        buf.extend_from_slice(&[0xAA; 32]);
+        assert!(read_extensions(&buf, SONG_BLOCK.len(), 0).is_none());
+    }
+}
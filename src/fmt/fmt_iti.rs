@@ -0,0 +1,139 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::fmt_it::{build_samples, read_pcm};
+use crate::info;
+use crate::interface::module::{GenericTracker, Module};
+use crate::interface::sample::Sample;
+use crate::interface::Error;
+use crate::parser::{
+    bytes::magic_header,
+    io::{is_magic, ByteReader, ReadSeek},
+    string::read_str,
+};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+const NAME: &str = "Impulse Tracker Instrument";
+
+const MAGIC_IMPI: [u8; 4] = *b"IMPI";
+
+/// Size of an instrument header. The sample headers are stored right after it.
+const ITI_INS_SIZE: u32 = 554;
+const ITI_SMP_SIZE: u32 = 80;
+
+/// Impulse Tracker Instrument
+pub struct ITI {
+    inner: GenericTracker,
+    samples: Box<[Sample]>,
+    title: Box<str>,
+    source: Option<Box<Path>>,
+}
+
+impl Module for ITI {
+    fn name(&self) -> &str {
+        &self.title
+    }
+
+    fn format(&self) -> &str {
+        NAME
+    }
+
+    fn pcm(&self, smp: &Sample) -> Result<Cow<'_, [u8]>, Error> {
+        read_pcm(&self.inner, smp)
+    }
+
+    fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    fn load(data: &mut impl ReadSeek) -> Result<Box<dyn Module>, Error> {
+        info!("Loading Impulse Tracker Instrument");
+        Ok(Box::new(parse_(data)?))
+    }
+
+    fn matches_format(buf: &[u8]) -> bool {
+        magic_header(&MAGIC_IMPI, buf)
+    }
+
+    fn set_source(mut self: Box<Self>, path: PathBuf) -> Box<dyn Module> {
+        self.source = Some(path.into());
+        self
+    }
+
+    fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+}
+
+pub fn parse_(file: &mut impl ReadSeek) -> Result<ITI, Error> {
+    if !is_magic(file, &MAGIC_IMPI)? {
+        return Err(Error::invalid("Not a valid Impulse Tracker instrument"));
+    }
+
+    file.set_seek_pos(0x1e)?;
+    let smp_num = file.read_u8()? as u32;
+    file.skip_bytes(1)?;
+
+    let title = read_str::<26>(file)?;
+
+    let smp_ptrs: Vec<u32> = (0..smp_num)
+        .map(|i| ITI_INS_SIZE + i * ITI_SMP_SIZE)
+        .collect();
+
+    let samples = build_samples(file, smp_ptrs)?.into();
+    let inner = file.load_to_memory()?.into();
+
+    Ok(ITI {
+        inner,
+        samples,
+        title,
+        source: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{parse_, ITI_INS_SIZE, MAGIC_IMPI};
+    use crate::interface::sample::{Depth, Loop, LoopType};
+    use crate::interface::Module;
+
+    #[test]
+    fn samples() {
+        let mut iti = MAGIC_IMPI.to_vec();
+        iti.resize(0x1e, 0);
+        iti.extend_from_slice(&[1, 0]); // sample number
+        iti.extend_from_slice(b"piano");
+        iti.resize(ITI_INS_SIZE as usize, 0);
+
+        // 16 bit signed sample with 4 frames, looping over the middle two
+        let pointer = ITI_INS_SIZE + 80;
+        let mut header = [0u8; 80];
+        header[..4].copy_from_slice(b"IMPS");
+        header[0x12] = 1 << 1 | 1 << 4; // 16 bit, loop
+        header[0x2E] = 1; // signed
+        header[0x30..0x34].copy_from_slice(&4u32.to_le_bytes());
+        header[0x34..0x38].copy_from_slice(&1u32.to_le_bytes());
+        header[0x38..0x3C].copy_from_slice(&3u32.to_le_bytes());
+        header[0x3C..0x40].copy_from_slice(&22050u32.to_le_bytes());
+        header[0x48..0x4C].copy_from_slice(&pointer.to_le_bytes());
+        iti.extend_from_slice(&header);
+        iti.extend_from_slice(&[0u8; 8]);
+
+        let module = parse_(&mut Cursor::new(iti)).unwrap();
+        let smp = &module.samples()[0];
+
+        assert_eq!(module.name(), "piano");
+        assert_eq!(module.samples().len(), 1);
+        assert_eq!(smp.length, 8);
+        assert_eq!(smp.depth, Depth::I16);
+        assert_eq!(smp.looping, Loop::new(1, 3, LoopType::Forward));
+        assert_eq!(smp.rate, 22050);
+    }
+}
@@ -0,0 +1,138 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::fmt_it::{build_samples, read_pcm};
+use crate::info;
+use crate::interface::module::{GenericTracker, Module};
+use crate::interface::sample::Sample;
+use crate::interface::Error;
+use crate::parser::{
+    bytes::magic_header,
+    io::{is_magic_non_consume, ByteReader, ReadSeek},
+};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+const NAME: &str = "Impulse Tracker Sample";
+
+const MAGIC_IMPS: [u8; 4] = *b"IMPS";
+
+/// Impulse Tracker Sample
+pub struct ITS {
+    inner: GenericTracker,
+    samples: Box<[Sample]>,
+    source: Option<Box<Path>>,
+}
+
+impl Module for ITS {
+    fn name(&self) -> &str {
+        match self.samples.first() {
+            Some(smp) => smp.name(),
+            None => "",
+        }
+    }
+
+    fn format(&self) -> &str {
+        NAME
+    }
+
+    fn pcm(&self, smp: &Sample) -> Result<Cow<'_, [u8]>, Error> {
+        read_pcm(&self.inner, smp)
+    }
+
+    fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    fn load(data: &mut impl ReadSeek) -> Result<Box<dyn Module>, Error> {
+        info!("Loading Impulse Tracker Sample");
+        Ok(Box::new(parse_(data)?))
+    }
+
+    fn matches_format(buf: &[u8]) -> bool {
+        magic_header(&MAGIC_IMPS, buf)
+    }
+
+    fn set_source(mut self: Box<Self>, path: PathBuf) -> Box<dyn Module> {
+        self.source = Some(path.into());
+        self
+    }
+
+    fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+}
+
+pub fn parse_(file: &mut impl ReadSeek) -> Result<ITS, Error> {
+    if !is_magic_non_consume(file, &MAGIC_IMPS)? {
+        return Err(Error::invalid("Not a valid Impulse Tracker sample"));
+    }
+
+    // An .its file is just a sample header with the pcm data following it.
+    let samples = build_samples(file, vec![0])?.into();
+    let inner = file.load_to_memory()?.into();
+
+    Ok(ITS {
+        inner,
+        samples,
+        source: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::exporter::AudioFormat;
+    use crate::interface::sample::{
+        Depth, Loop, LoopType, Sample, SampleMeta, Vibrato, Waveform,
+    };
+    use crate::load_module;
+
+    #[test]
+    fn round_trip() {
+        let pcm: Vec<u8> = (0..64).collect();
+        let sample = Sample {
+            name: "round trip".into(),
+            length: pcm.len() as u32,
+            rate: 22050,
+            depth: Depth::I8,
+            looping: Loop::new(4, 32, LoopType::Forward),
+            sustain: Some(Loop::new(8, 16, LoopType::PingPong)),
+            meta: SampleMeta {
+                volume: 48,
+                global_volume: 32,
+                panning: Some(255),
+                vibrato: Vibrato {
+                    waveform: Waveform::Square,
+                    speed: 10,
+                    depth: 4,
+                    sweep: 2,
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut its: Vec<u8> = Vec::new();
+        AudioFormat::ITS
+            .get_impl()
+            .write(&sample, pcm.as_slice().into(), &mut its)
+            .unwrap();
+
+        let module = load_module(&mut Cursor::new(its)).unwrap();
+        let loaded = &module.samples()[0];
+
+        assert_eq!(module.name(), "round trip");
+        assert_eq!(loaded.rate, 22050);
+        assert_eq!(loaded.depth, Depth::I8);
+        assert_eq!(loaded.looping, sample.looping);
+        assert_eq!(loaded.sustain, sample.sustain);
+        assert_eq!(loaded.meta, sample.meta);
+        assert_eq!(module.pcm(loaded).unwrap().as_ref(), pcm.as_slice());
+    }
+}
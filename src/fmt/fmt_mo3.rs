@@ -0,0 +1,501 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! MO3 is a container for IT, XM, S3M, MOD & MTM modules.
+//!
+//! The music data is LZ compressed, and is immediately followed by the sample data.
+//! Samples can be stored as pcm, delta compressed pcm, MP3 or Ogg Vorbis.
+//!
+//! Reference:
+//!     https://github.com/OpenMPT/openmpt/blob/master/soundlib/Load_mo3.cpp
+
+use std::borrow::Cow;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::common::MAX_SIZE_BYTES;
+use crate::dsp::vorbis::{decode_vorbis, vorbis_channels};
+use crate::fmt::fmt_mo3_compression::{decode_delta, decode_prediction, unpack_music};
+use crate::fmt::fmt_xm::xm_rate;
+use crate::interface::module::{GenericTracker, Module};
+use crate::interface::sample::{
+    remove_invalid_samples, Channel, Depth, Loop, LoopType, PcmType, Sample, SampleMeta, Vibrato,
+    Waveform,
+};
+use crate::interface::Error;
+use crate::parser::{
+    bytes::magic_header,
+    io::{is_magic, ByteReader, ReadSeek},
+    string::{join_lines, read_message, read_string},
+};
+use crate::{info, warn};
+
+const NAME: &str = "MO3";
+
+const MAGIC_MO3: [u8; 3] = *b"MO3";
+const MAX_VERSION: u8 = 5;
+
+const MO3_HEADER_SIZE: i64 = 422;
+const MO3_INSTRUMENT_SIZE: i64 = 826;
+const MO3_SAMPLE_SIZE: usize = 41;
+
+/* Header flags */
+const FLAG_S3M: u32 = 0x0002;
+const FLAG_MTM: u32 = 0x0008;
+const FLAG_MOD: u32 = 0x0080;
+const FLAG_IT: u32 = 0x0100;
+
+/* Sample flags */
+const SMP_16_BIT: u16 = 0x0001;
+const SMP_LOOP: u16 = 0x0010;
+const SMP_PING_PONG: u16 = 0x0020;
+const SMP_STEREO: u16 = 0x0400;
+
+/* Sample compression, masked with SMP_COMPRESSION */
+const SMP_COMPRESSION: u16 = 0xF000;
+const SMP_MPEG: u16 = 0x1000;
+const SMP_DELTA: u16 = 0x2000;
+const SMP_OGG: u16 = 0x3000;
+const SMP_PREDICTION: u16 = 0x4000;
+const SMP_SHARED_OGG: u16 = 0x7000;
+const SMP_OPL: u16 = 0x8000;
+
+/// MO3 compressed module
+pub struct MO3 {
+    inner: GenericTracker,
+    samples: Box<[Sample]>,
+    title: Box<str>,
+    comments: Box<str>,
+    format: Box<str>,
+    source: Option<Box<Path>>,
+}
+
+impl Module for MO3 {
+    fn name(&self) -> &str {
+        &self.title
+    }
+
+    fn format(&self) -> &str {
+        &self.format
+    }
+
+    fn comments(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.comments)
+    }
+
+    fn pcm(&self, smp: &Sample) -> Result<Cow<'_, [u8]>, Error> {
+        let frames = smp.length_frames();
+        let (channels, bits) = (smp.channels(), smp.bits());
+
+        match smp.pcm_type {
+            PcmType::MO3DELTA => {
+                let buf = self.inner.get_slice_trailing(smp)?;
+                Ok(decode_delta(buf, frames, channels, bits)?.into())
+            }
+            PcmType::MO3PREDICT => {
+                let buf = self.inner.get_slice_trailing(smp)?;
+                Ok(decode_prediction(buf, frames, channels, bits)?.into())
+            }
+            PcmType::VORBIS => Ok(decode_vorbis(self.inner.get_slice_trailing(smp)?, smp)?.into()),
+            PcmType::MP3 => Err(Error::unsupported(
+                "MO3 samples compressed with MP3 are not supported",
+            )),
+            _ => Ok(Cow::Borrowed(self.inner.get_slice(smp)?)),
+        }
+    }
+
+    fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    fn load(data: &mut impl ReadSeek) -> Result<Box<dyn Module>, Error> {
+        info!("Loading MO3");
+        Ok(Box::new(parse_(data)?))
+    }
+
+    fn matches_format(buf: &[u8]) -> bool {
+        magic_header(&MAGIC_MO3, buf) && buf.get(3).is_some_and(|v| *v <= MAX_VERSION)
+    }
+
+    fn set_source(mut self: Box<Self>, path: PathBuf) -> Box<dyn Module> {
+        self.source = Some(path.into());
+        self
+    }
+
+    fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+}
+
+pub fn parse_(file: &mut impl ReadSeek) -> Result<MO3, Error> {
+    if !is_magic(file, &MAGIC_MO3)? {
+        return Err(Error::invalid("Not a valid MO3 module"));
+    }
+
+    let version = file.read_u8()?;
+    if version > MAX_VERSION {
+        return Err(Error::unsupported("MO3 version is not supported"));
+    }
+
+    let music_size = file.read_u32_le()?;
+    if music_size as u64 > MAX_SIZE_BYTES {
+        return Err(Error::unsupported("MO3 music data is too large"));
+    }
+
+    let compressed_size = match version >= 5 {
+        true => Some(file.read_u32_le()?),
+        false => None,
+    };
+
+    let music_offset = file.seek_position()?;
+    let buf: Box<[u8]> = file.load_to_memory()?.into();
+
+    let (music, consumed) = unpack_music(
+        buf.get(music_offset as usize..).unwrap_or_default(),
+        music_size as usize,
+    )?;
+
+    // Sample data is stored after the compressed music data
+    let sample_offset = music_offset + compressed_size.unwrap_or(consumed as u32) as u64;
+
+    let mut music = Cursor::new(music);
+    let title = read_cstring(&mut music)?;
+    let message = read_cstring(&mut music)?;
+
+    let channels = music.read_u8()? as i64;
+    let orders = music.read_u16_le()? as i64;
+    music.skip_bytes(2)?; // restart position
+    let patterns = music.read_u16_le()? as i64;
+    let tracks = music.read_u16_le()?;
+    let instruments = music.read_u16_le()?;
+    let sample_num = music.read_u16_le()?;
+    music.skip_bytes(2)?; // speed, tempo
+    let flags = music.read_u32_le()?;
+    music.skip_bytes(MO3_HEADER_SIZE - 19)?;
+
+    let (format, has_filenames) = match flags {
+        f if f & FLAG_IT != 0 => ("Impulse Tracker", true),
+        f if f & FLAG_S3M != 0 => ("Scream Tracker", true),
+        f if f & FLAG_MOD != 0 => ("Amiga ProTracker", false),
+        f if f & FLAG_MTM != 0 => ("MultiTracker", false),
+        _ => ("Extended Module", false),
+    };
+    let uses_frequency = flags & (FLAG_IT | FLAG_S3M) != 0;
+    // MOD and S3M don't have a song message, so text is stored in the sample names
+    let names_as_message = flags & (FLAG_MOD | FLAG_S3M) != 0;
+
+    music.skip_bytes(orders)?;
+    music.skip_bytes(patterns * channels * 2)?; // track assignments
+    music.skip_bytes(patterns * 2)?; // pattern lengths
+
+    for _ in 0..tracks {
+        let len = music.read_u32_le()?;
+        music.skip_bytes(len as i64)?;
+    }
+
+    for _ in 0..instruments {
+        let _ = read_cstring(&mut music)?;
+        if flags & FLAG_IT != 0 {
+            let _ = read_cstring(&mut music)?;
+        }
+        music.skip_bytes(MO3_INSTRUMENT_SIZE)?;
+    }
+
+    let mut samples: Vec<Sample> = Vec::with_capacity(sample_num as usize);
+    // Position of each stored sample in ``samples``, used to resolve duplicates
+    let mut stored: Vec<Option<usize>> = Vec::with_capacity(sample_num as usize);
+    let mut pointer = sample_offset;
+
+    let mut names: Vec<Box<str>> = Vec::new();
+
+    for index_raw in 0..sample_num {
+        let name = read_cstring(&mut music)?;
+        if names_as_message {
+            names.push(name.clone());
+        }
+        let filename = match has_filenames {
+            true => Some(read_cstring(&mut music)?),
+            false => None,
+        };
+
+        let header = music.read_bytes(MO3_SAMPLE_SIZE)?;
+        let header = SampleHeader::new(&header);
+
+        let compression = header.flags & SMP_COMPRESSION;
+        if version >= 5 && compression == SMP_SHARED_OGG {
+            music.skip_bytes(2)?; // sample providing the Ogg header
+        }
+
+        // A negative size means that the sample data is shared with a previous sample
+        if header.compressed_size < 0 {
+            let source = (index_raw as i64 + header.compressed_size as i64) as usize;
+
+            if let Some(Some(position)) = stored.get(source) {
+                let mut smp = samples[*position].clone();
+                smp.name = name;
+                smp.filename = filename;
+                smp.index_raw = index_raw;
+                stored.push(Some(samples.len()));
+                samples.push(smp);
+            } else {
+                stored.push(None);
+            }
+            continue;
+        }
+
+        let data_pointer = pointer;
+        pointer += header.compressed_size as u64;
+
+        if header.length == 0 || header.compressed_size == 0 {
+            stored.push(None);
+            continue;
+        }
+
+        let mut depth = Depth::new(header.flags & SMP_16_BIT == 0, true, true);
+        let mut channel = Channel::new(header.flags & SMP_STEREO != 0, false);
+
+        let pcm_type = match compression {
+            0 => PcmType::PCM,
+            SMP_DELTA => PcmType::MO3DELTA,
+            SMP_PREDICTION => PcmType::MO3PREDICT,
+            SMP_MPEG => PcmType::MP3,
+            SMP_OGG => {
+                let head = buf.get(data_pointer as usize..).unwrap_or_default();
+                let channels = vorbis_channels(head).unwrap_or(channel.channels());
+                depth = Depth::I16;
+                channel = Channel::new(channels == 2, false);
+                PcmType::VORBIS
+            }
+            SMP_SHARED_OGG => {
+                warn!(
+                    "Skipping sample {}: shared Ogg headers are not supported",
+                    index_raw
+                );
+                stored.push(None);
+                continue;
+            }
+            SMP_OPL => {
+                warn!("Skipping sample {}: OPL instruments have no pcm", index_raw);
+                stored.push(None);
+                continue;
+            }
+            _ => {
+                warn!(
+                    "Skipping sample {}: unknown compression {:04X}",
+                    index_raw, compression
+                );
+                stored.push(None);
+                continue;
+            }
+        };
+
+        let finetune = match uses_frequency {
+            true => 0,
+            false => (header.freq_finetune as i32 - 128) as i8,
+        };
+
+        let rate = match uses_frequency {
+            true => header.freq_finetune,
+            false => xm_rate(header.transpose, finetune),
+        };
+
+        let [waveform, sweep, vib_depth, speed] = header.vibrato;
+        let meta = SampleMeta {
+            volume: header.volume.min(64),
+            // The global volume is only used by IT. XM uses it to store the instrument number.
+            global_volume: match flags & FLAG_IT != 0 {
+                true => header.global_volume.min(64),
+                false => 64,
+            },
+            panning: (header.panning <= 256).then(|| header.panning.min(255) as u8),
+            finetune,
+            relative_note: header.transpose,
+            vibrato: Vibrato {
+                waveform: match flags & FLAG_IT != 0 {
+                    true => Waveform::from_it(waveform),
+                    false => Waveform::from_xm(waveform),
+                },
+                speed,
+                depth: vib_depth,
+                sweep,
+            },
+        };
+
+        let loop_kind = match header.flags {
+            f if f & SMP_LOOP == 0 => LoopType::Off,
+            f if f & SMP_PING_PONG != 0 => LoopType::PingPong,
+            _ => LoopType::Forward,
+        };
+
+        let length = header
+            .length
+            .saturating_mul(depth.bytes() as u32 * channel.channels() as u32);
+
+        stored.push(Some(samples.len()));
+        samples.push(Sample {
+            filename,
+            name,
+            length,
+            rate,
+            pointer: data_pointer as u32,
+            depth,
+            channel,
+            index_raw,
+            pcm_type,
+            looping: Loop::new(header.loop_start, header.loop_end, loop_kind),
+            sustain: None,
+            mpt: None,
+            meta,
+        });
+    }
+
+    remove_invalid_samples(&mut samples, Some(buf.len() as u64))?;
+
+    let comments = match names_as_message {
+        true => join_lines(&names),
+        false => read_message(message.as_bytes()),
+    };
+
+    Ok(MO3 {
+        inner: buf.into(),
+        samples: samples.into(),
+        title,
+        comments,
+        format: format!("{NAME} ({format})").into(),
+        source: None,
+    })
+}
+
+/// The fields of an MO3 sample header we're interested in
+struct SampleHeader {
+    freq_finetune: u32,
+    transpose: i8,
+    volume: u8,
+    /// 0-256, or 0xFFFF if disabled
+    panning: u16,
+    length: u32,
+    loop_start: u32,
+    loop_end: u32,
+    flags: u16,
+    /// type, sweep, depth, speed
+    vibrato: [u8; 4],
+    global_volume: u8,
+    compressed_size: i32,
+}
+
+impl SampleHeader {
+    fn new(buf: &[u8]) -> Self {
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
+
+        Self {
+            freq_finetune: u32_at(0),
+            transpose: buf[4] as i8,
+            volume: buf[5],
+            panning: u16::from_le_bytes([buf[6], buf[7]]),
+            length: u32_at(8),
+            loop_start: u32_at(12),
+            loop_end: u32_at(16),
+            flags: u16::from_le_bytes([buf[20], buf[21]]),
+            vibrato: [buf[22], buf[23], buf[24], buf[25]],
+            global_volume: buf[26],
+            // sustain loop
+            compressed_size: u32_at(35) as i32,
+            // encoder delay
+        }
+    }
+}
+
+fn read_cstring(file: &mut impl ReadSeek) -> Result<Box<str>, Error> {
+    let mut buf: Vec<u8> = Vec::new();
+
+    loop {
+        match file.read_u8()? {
+            0 => break,
+            byte => buf.push(byte),
+        }
+    }
+
+    Ok(read_string(&buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Pack music data using only literals
+    fn pack(data: &[u8]) -> Vec<u8> {
+        let mut packed = vec![data[0]];
+
+        for chunk in data[1..].chunks(8) {
+            packed.push(0);
+            packed.extend_from_slice(chunk);
+        }
+
+        packed
+    }
+
+    fn sample(name: &str, flags: u16, length: u32, compressed_size: i32) -> Vec<u8> {
+        let mut smp = format!("{name}\0{name}.wav\0").into_bytes();
+        let mut header = [0u8; MO3_SAMPLE_SIZE];
+        header[0..4].copy_from_slice(&22050u32.to_le_bytes());
+        header[8..12].copy_from_slice(&length.to_le_bytes());
+        header[20..22].copy_from_slice(&flags.to_le_bytes());
+        header[35..39].copy_from_slice(&compressed_size.to_le_bytes());
+        smp.extend_from_slice(&header);
+        smp
+    }
+
+    #[test]
+    fn load_mo3() {
+        let mut music = b"song\0message\0".to_vec();
+        let mut header = [0u8; MO3_HEADER_SIZE as usize];
+        header[0] = 1; // channels
+        header[1] = 1; // orders
+        header[11] = 4; // samples
+        header[15..19].copy_from_slice(&FLAG_IT.to_le_bytes());
+        music.extend_from_slice(&header);
+        music.push(0); // order list
+
+        music.extend(sample("raw", 0, 4, 4));
+        music.extend(sample("delta", SMP_DELTA, 4, 3));
+        music.extend(sample("mp3", SMP_MPEG, 1152, 8));
+        music.extend(sample("copy", 0, 4, -3));
+
+        let mut mo3 = MAGIC_MO3.to_vec();
+        mo3.push(5);
+        mo3.extend_from_slice(&(music.len() as u32).to_le_bytes());
+        let packed = pack(&music);
+        mo3.extend_from_slice(&(packed.len() as u32).to_le_bytes());
+        mo3.extend_from_slice(&packed);
+
+        mo3.extend_from_slice(&[1, 2, 3, 4]);
+        mo3.extend_from_slice(&[0; 3]);
+        mo3.extend_from_slice(&[0xFF; 8]);
+
+        let module = MO3::load(&mut Cursor::new(mo3)).unwrap();
+        assert_eq!(module.name(), "song");
+        assert_eq!(module.format(), "MO3 (Impulse Tracker)");
+
+        let samples = module.samples();
+        assert_eq!(samples.len(), 4);
+        assert_eq!(samples[0].filename(), "raw.wav");
+        assert_eq!(samples[0].rate, 22050);
+
+        assert_eq!(module.pcm(&samples[0]).unwrap().as_ref(), [1, 2, 3, 4]);
+        assert_eq!(
+            module.pcm(&samples[1]).unwrap().as_ref(),
+            [-1i8 as u8, -2i8 as u8, -3i8 as u8, -4i8 as u8]
+        );
+        assert!(module.pcm(&samples[2]).is_err());
+
+        assert_eq!(samples[3].index_raw(), 4);
+        assert_eq!(module.pcm(&samples[3]).unwrap().as_ref(), [1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,296 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Rust implementation for decompressing MO3 music data and samples
+//!
+//! Both the music data and the lossless sample codecs store their control bits
+//! in bytes that are interleaved with the rest of the stream.
+//!
+//! Reference:
+//!     https://github.com/OpenMPT/openmpt/blob/master/soundlib/Load_mo3.cpp
+
+use crate::interface::Error;
+
+/// Reads control bits (most significant bit first) and raw bytes from the same stream.
+struct CtrlReader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+    /// Remaining control bits, followed by a sentinel bit
+    data: u16,
+}
+
+impl<'a> CtrlReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self {
+            buf,
+            offset: 0,
+            data: 0,
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        let byte = *self
+            .buf
+            .get(self.offset)
+            .ok_or_else(|| Error::invalid("MO3 compressed stream ended unexpectedly"))?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn read_bit(&mut self) -> Result<u32, Error> {
+        self.data <<= 1;
+        let mut carry = self.data > 0xFF;
+        self.data &= 0xFF;
+
+        // The sentinel was shifted out, so load the next control byte
+        if self.data == 0 {
+            self.data = ((self.read_byte()? as u16) << 1) + 1;
+            carry = self.data > 0xFF;
+            self.data &= 0xFF;
+        }
+
+        Ok(carry as u32)
+    }
+
+    /// Read a variable length number.
+    ///
+    /// The number starts with an implicit 1,
+    /// followed by pairs of (value bit, continue bit).
+    fn read_length(&mut self, mut value: i64) -> Result<i64, Error> {
+        value += 1;
+        loop {
+            value = (value << 1) + self.read_bit()? as i64;
+
+            if value > u32::MAX as i64 {
+                return Err(Error::invalid("MO3 compressed stream is corrupted"));
+            }
+
+            if self.read_bit()? == 0 {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+/// Unpack ``size`` bytes of LZ compressed music data.
+///
+/// Returns the unpacked data and the number of bytes consumed from ``buf``.
+pub fn unpack_music(buf: &[u8], size: usize) -> Result<(Vec<u8>, usize), Error> {
+    let corrupted = || Error::invalid("MO3 music data is corrupted");
+    let mut reader = CtrlReader::new(buf);
+    let mut music: Vec<u8> = Vec::with_capacity(size);
+
+    // Offset of the previous string, relative to the end of the output
+    let mut str_offset: i64 = 0;
+
+    if size == 0 {
+        return Err(corrupted());
+    }
+
+    // The first byte is always stored as is
+    music.push(reader.read_byte()?);
+
+    while music.len() < size {
+        // A 0 control bit means that the next byte is stored as is
+        if reader.read_bit()? == 0 {
+            music.push(reader.read_byte()?);
+            continue;
+        }
+
+        let mut length_adjust: i64 = 0;
+        let mut str_len = reader.read_length(0)? - 3;
+
+        if str_len < 0 {
+            // Reuse the previous offset
+            str_len = 0;
+        } else {
+            str_offset = !((str_len << 8) | reader.read_byte()? as i64);
+            str_len = 0;
+
+            if str_offset < -1280 {
+                length_adjust += 1;
+            }
+            length_adjust += 1;
+            if str_offset < -32000 {
+                length_adjust += 1;
+            }
+        }
+
+        // The next 2 bits are part of the length
+        str_len = (str_len << 1) + reader.read_bit()? as i64;
+        str_len = (str_len << 1) + reader.read_bit()? as i64;
+
+        if str_len == 0 {
+            // Length doesn't fit in 2 bits
+            str_len = reader.read_length(0)? + 2;
+        }
+        str_len += length_adjust;
+
+        let remain = (size - music.len()) as i64;
+
+        if str_len <= 0 || str_len > remain || str_offset >= 0 || -str_offset > music.len() as i64 {
+            return Err(corrupted());
+        }
+
+        // Source and destination may overlap, so copy byte by byte
+        let start = music.len() - (-str_offset) as usize;
+        for i in 0..str_len as usize {
+            music.push(music[start + i]);
+        }
+    }
+
+    Ok((music, reader.offset))
+}
+
+/// Decode an MO3 sample compressed with first order delta encoding.
+///
+/// Returns planar little endian pcm.
+pub fn decode_delta(buf: &[u8], frames: usize, channels: u16, bits: u8) -> Result<Vec<u8>, Error> {
+    decode(buf, frames, channels, bits, false)
+}
+
+/// Decode an MO3 sample compressed with second order delta encoding (prediction).
+///
+/// Returns planar little endian pcm.
+pub fn decode_prediction(
+    buf: &[u8],
+    frames: usize,
+    channels: u16,
+    bits: u8,
+) -> Result<Vec<u8>, Error> {
+    decode(buf, frames, channels, bits, true)
+}
+
+fn decode(
+    buf: &[u8],
+    frames: usize,
+    channels: u16,
+    bits: u8,
+    prediction: bool,
+) -> Result<Vec<u8>, Error> {
+    let is_16_bit = bits == 16;
+    let (mask, shift, mut dh): (u32, u32, u32) = match is_16_bit {
+        true => (0xFFFF, 15, 8),
+        false => (0xFF, 7, 4),
+    };
+
+    let sign_extend = |value: u32| -> i32 {
+        match is_16_bit {
+            true => value as u16 as i16 as i32,
+            false => value as u8 as i8 as i32,
+        }
+    };
+    let (min, max) = (sign_extend(mask / 2 + 1), sign_extend(mask / 2));
+
+    let mut reader = CtrlReader::new(buf);
+    let mut pcm: Vec<u8> = Vec::with_capacity(frames * channels as usize * (bits / 8) as usize);
+
+    let mut previous: i32 = 0;
+    let mut next: i32 = 0;
+
+    for _ in 0..channels as usize * frames {
+        let mut value: u32 = 0;
+        let mut push = |reader: &mut CtrlReader| -> Result<(), Error> {
+            value = ((value << 1) + reader.read_bit()?) & mask;
+            Ok(())
+        };
+
+        // Most significant part of the delta
+        loop {
+            push(&mut reader)?;
+            if is_16_bit && dh < 5 {
+                push(&mut reader)?;
+            }
+            if reader.read_bit()? == 0 {
+                break;
+            }
+        }
+
+        // Least significant part of the delta, including its sign
+        for _ in 0..dh {
+            push(&mut reader)?;
+        }
+
+        let mut cl: u32 = 1;
+        if value >= 4 {
+            cl = shift;
+            while (1 << cl) & value == 0 && cl > 1 {
+                cl -= 1;
+            }
+        }
+        dh = (dh + cl) >> 1;
+
+        let positive = value & 1 == 1;
+        value >>= 1;
+        if !positive {
+            value = !value & mask;
+        }
+
+        let sample = match prediction {
+            false => {
+                let sample = sign_extend(value.wrapping_add(previous as u32));
+                previous = sample;
+                sample
+            }
+            true => {
+                let delta = sign_extend(value);
+                let sample = sign_extend(value.wrapping_add(next as u32));
+                next = (sample * 2 + (delta >> 1) - previous).clamp(min, max);
+                previous = sample;
+                sample
+            }
+        };
+
+        match is_16_bit {
+            true => pcm.extend_from_slice(&(sample as i16).to_le_bytes()),
+            false => pcm.push(sample as i8 as u8),
+        }
+    }
+
+    Ok(pcm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unpack_literals_and_strings() {
+        // 'a', control byte, 'b', 'c', offset, control byte
+        // control bits: literal, literal, string (length 3, offset -3), 2 bits (0), length 3 + 2 + 1
+        let packed = [b'a', 0b0011_0001, b'b', b'c', 0x02, 0b0000_0000];
+        let (music, consumed) = unpack_music(&packed, 9).unwrap();
+
+        assert_eq!(music, b"abcabcabc");
+        assert_eq!(consumed, 6);
+        assert!(unpack_music(&packed, 10).is_err());
+    }
+
+    #[test]
+    fn decode_delta_ramp() {
+        // Zeroed control bits encode a delta of -1 every frame
+        let pcm = decode_delta(&[0u8; 16], 4, 1, 8).unwrap();
+        assert_eq!(pcm, [-1i8 as u8, -2i8 as u8, -3i8 as u8, -4i8 as u8]);
+
+        let pcm = decode_delta(&[0u8; 32], 2, 2, 16).unwrap();
+        let expected: Vec<u8> = [-1i16, -2, -3, -4]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        assert_eq!(pcm, expected);
+
+        assert!(decode_delta(&[0u8; 1], 64, 1, 8).is_err());
+    }
+
+    #[test]
+    fn decode_prediction_ramp() {
+        // delta -1: 0 + -1 = -1, next = -2 + -1 - 0 = -3
+        // delta -1: -3 + -1 = -4, ...
+        let pcm = decode_prediction(&[0u8; 16], 2, 1, 8).unwrap();
+        assert_eq!(pcm, [-1i8 as u8, -4i8 as u8]);
+    }
+}
@@ -5,16 +5,22 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::dsp::adpcm::{adpcm4_len, read_pcm};
 use crate::info;
 use crate::interface::module::{GenericTracker, Module};
-use crate::interface::sample::{remove_invalid_samples, Channel, Depth, Loop, LoopType, Sample};
-use crate::interface::Error;
+use crate::interface::sample::{
+    remove_invalid_samples, Channel, Depth, Loop, LoopType, PcmType, Sample, SampleMeta,
+};
+use crate::interface::pattern::{Note, Pattern, NOTE_MAX, NOTE_MIDDLE_C};
+use crate::interface::song::Song;
+use crate::interface::usage::{sample_usage, Instruments, SampleUsage};
+use crate::interface::{Error, TrackerInfo};
 use crate::parser::{
     io::{is_magic_non_consume, non_consume, ByteReader, Container, ReadSeek},
-    string::read_str,
+    string::{join_lines, read_str},
 };
 use std::borrow::Cow;
-use std::io::Cursor;
+use std::io::{self, Cursor};
 use std::path::{Path, PathBuf};
 
 /*
@@ -36,8 +42,14 @@ const FINETUNE: [u32; 16] = [
     7895, 7941, 7985, 8046, 8107, 8169, 8232, 8280,
 ];
 
+/// MOD patterns always have 64 rows
+const MOD_ROWS: u16 = 64;
+
 const MAGIC_PP20: [u8; 4] = *b"PP20";
 
+/// ModPlug marks ADPCM compressed samples with this tag
+const MAGIC_ADPCM: [u8; 5] = *b"ADPCM";
+
 // https://github.com/OpenMPT/openmpt/blob/d75cd3eaf299ee84c484ff66ec5836a084738351/soundlib/Load_mod.cpp#L322
 const INVALID_BYTE_THRESHOLD: u8 = 40;
 
@@ -47,6 +59,10 @@ pub struct MOD {
     samples: Box<[Sample]>,
     source: Option<Box<Path>>,
     title: Box<str>,
+    comments: Box<str>,
+    tracker: Option<TrackerInfo>,
+    patterns: Box<[Pattern]>,
+    song: Song,
 }
 
 impl Module for MOD {
@@ -58,8 +74,33 @@ impl Module for MOD {
         "Amiga ProTracker"
     }
 
+    fn comments(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.comments)
+    }
+
+    fn created_with(&self) -> Option<TrackerInfo> {
+        self.tracker.clone()
+    }
+
+    fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    fn song(&self) -> Option<&Song> {
+        Some(&self.song)
+    }
+
+    fn sample_usage(&self) -> Option<Box<[SampleUsage]>> {
+        Some(sample_usage(
+            &self.samples,
+            &self.patterns,
+            &self.song.orders,
+            Instruments::Samples,
+        ))
+    }
+
     fn pcm(&self, smp: &Sample) -> Result<Cow<[u8]>, Error> {
-        Ok(self.inner.get_slice(smp)?.into())
+        read_pcm(&self.inner, smp)
     }
 
     fn samples(&self) -> &[Sample] {
@@ -91,23 +132,45 @@ pub fn parse_(file: &mut impl ReadSeek) -> Result<MOD, Error> {
     check_xpk(file)?;
 
     let title = read_str::<20>(file)?;
-    let MODInfo { channels, samples } = get_mod_info(file)?;
-    let mut samples = build_samples(file, samples as usize)?;
-    file.skip_bytes(1)?; // song length
-    file.skip_bytes(1)?; // reset flag
-
-    let mut patterns = [0u8; 128];
-    file.read_exact(&mut patterns)?;
-    file.skip_bytes(4)?; // pseudo signature e.g "M!K!"
+    let MODInfo {
+        channels,
+        samples,
+        tracker,
+    } = get_mod_info(file)?;
+    let comments = read_sample_names(file, samples as usize)?;
+    let sample_number = samples;
+    let mut samples = build_samples(file, sample_number as usize)?;
+    let song_length = file.read_u8()?;
+    let restart = file.read_u8()?;
+
+    let mut orders = [0u8; 128];
+    file.read_exact(&mut orders)?;
+
+    // 15 sample modules don't have a signature
+    if sample_number != 15 {
+        file.skip_bytes(4)?; // pseudo signature e.g "M!K!"
+    }
 
     // I still haven't figured out why I need to add 1
-    let highest = max(&patterns) + 1;
+    let highest = max(&orders) + 1;
+    let pattern_size = channels as usize * MOD_ROWS as usize * 4;
 
-    file.skip_bytes(highest as i64 * channels as i64 * 256)?;
+    let patterns = non_consume(file, |file| read_patterns(file, highest, channels))?;
+    file.skip_bytes(highest as i64 * pattern_size as i64)?;
 
     for smp in samples.iter_mut() {
+        // The tag may be missing if we're at the end of the file
+        if let Ok(true) = is_magic_non_consume(file, &MAGIC_ADPCM) {
+            file.skip_bytes(MAGIC_ADPCM.len() as i64)?;
+            smp.pcm_type = PcmType::ADPCM4;
+        }
+
         smp.pointer = file.seek_position()? as u32;
-        file.skip_bytes(smp.length as i64)?;
+
+        match smp.pcm_type {
+            PcmType::ADPCM4 => file.skip_bytes(adpcm4_len(smp.length) as i64)?,
+            _ => file.skip_bytes(smp.length as i64)?,
+        }
     }
 
     remove_invalid_samples(&mut samples, file.len())?;
@@ -118,10 +181,93 @@ pub fn parse_(file: &mut impl ReadSeek) -> Result<MOD, Error> {
         title,
         inner,
         samples: samples.into(),
+        comments,
+        tracker,
+        patterns,
+        song: Song {
+            orders: orders[..(song_length as usize).min(orders.len())].into(),
+            // ProTracker stores 127 here
+            restart: match restart < song_length {
+                true => restart,
+                false => 0,
+            },
+            channels,
+            // Amiga channels are hard panned left, right, right, left
+            panning: (0..channels)
+                .map(|channel| match channel % 4 {
+                    0 | 3 => 0,
+                    _ => 255,
+                })
+                .collect(),
+            ..Default::default()
+        },
         source: None,
     })
 }
 
+/// Read ``count`` patterns, stopping at the first one that's truncated.
+///
+/// The patterns may be truncated, but that shouldn't stop us from extracting samples.
+fn read_patterns(file: &mut impl ReadSeek, count: u8, channels: u8) -> io::Result<Box<[Pattern]>> {
+    let pattern_size = channels as usize * MOD_ROWS as usize * 4;
+    let mut patterns: Vec<Pattern> = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        let Ok(data) = file.read_bytes(pattern_size) else {
+            break;
+        };
+        patterns.push(unpack_pattern(&data, channels));
+    }
+
+    Ok(patterns.into())
+}
+
+/// Each cell is 4 bytes, which store the sample number, period, effect and parameter.
+pub(crate) fn unpack_pattern(buf: &[u8], channels: u8) -> Pattern {
+    let mut pattern = Pattern::new(MOD_ROWS, channels);
+    let cells = buf.chunks_exact(4).take(MOD_ROWS as usize * channels as usize);
+
+    for (i, data) in cells.enumerate() {
+        let row = (i / channels as usize) as u16;
+        let channel = (i % channels as usize) as u8;
+        let cell = pattern.cell_mut(row, channel).unwrap();
+
+        let period = ((data[0] & 0x0F) as u16) << 8 | data[1] as u16;
+        cell.note = period_to_note(period);
+        cell.instrument = (data[0] & 0xF0) | (data[2] >> 4);
+        cell.effect = data[2] & 0x0F;
+        cell.param = data[3];
+    }
+
+    pattern
+}
+
+/// Period 428 (C-2 in ProTracker) plays a sample at its sample rate
+fn period_to_note(period: u16) -> Note {
+    const MIDDLE_C_PERIOD: f64 = 428.0;
+
+    if period == 0 {
+        return Note::None;
+    }
+
+    let note = NOTE_MIDDLE_C as f64 + 12.0 * (MIDDLE_C_PERIOD / period as f64).log2();
+    Note::On(note.round().clamp(0.0, NOTE_MAX as f64) as u8)
+}
+
+/// Authors often write messages in the sample names, including empty samples.
+fn read_sample_names(file: &mut impl ReadSeek, sample_number: usize) -> Result<Box<str>, Error> {
+    let names = non_consume(file, |file| {
+        let mut names: Vec<Box<str>> = Vec::with_capacity(sample_number);
+        for _ in 0..sample_number {
+            names.push(read_str::<22>(file)?);
+            file.skip_bytes(8)?; // rest of the sample header
+        }
+        Ok(names)
+    })?;
+
+    Ok(join_lines(&names))
+}
+
 fn get_mod_info(data: &mut impl ReadSeek) -> std::io::Result<MODInfo> {
     non_consume(data, |data| {
         data.set_seek_pos(1080)?;
@@ -133,6 +279,7 @@ fn get_mod_info(data: &mut impl ReadSeek) -> std::io::Result<MODInfo> {
 struct MODInfo {
     pub channels: u8,
     pub samples: u8,
+    pub tracker: Option<TrackerInfo>,
 }
 
 impl MODInfo {
@@ -168,10 +315,32 @@ impl MODInfo {
             },
         };
 
-        Self { channels, samples }
+        Self {
+            channels,
+            samples,
+            tracker: tracker_info(magic, samples),
+        }
     }
 }
 
+/// The magic only hints at the tracker, since most trackers write "M.K." for compatibility.
+fn tracker_info(magic: [u8; 4], samples: u8) -> Option<TrackerInfo> {
+    let name = match &magic {
+        _ if samples == 15 => "Ultimate SoundTracker",
+        b"M.K." | b"M!K!" => "ProTracker",
+        b"M&K!" | b"N.T." => "NoiseTracker",
+        b"CD61" | b"CD81" => "Octalyser",
+        b"OKTA" => "Oktalyzer",
+        b"16CN" | b"32CN" => "TakeTracker",
+        m if m[..3] == *b"FLT" => "StarTrekker",
+        m if m[..3] == *b"TDZ" => "TakeTracker",
+        m if m[1..] == *b"CHN" || m[2..] == *b"CH" => "FastTracker",
+        _ => return None,
+    };
+
+    Some(TrackerInfo::new(name, None))
+}
+
 #[rustfmt::skip]
 fn build_samples(file: &mut impl ReadSeek, sample_number: usize) -> Result<Vec<Sample>, Error> {
     let mut samples: Vec<Sample> = Vec::new();
@@ -226,6 +395,11 @@ fn build_samples(file: &mut impl ReadSeek, sample_number: usize) -> Result<Vec<S
                 channel: Channel::Mono,
                 index_raw: i as u16,
                 looping: Loop::new(loop_start, loop_end, loop_kind),
+                meta: SampleMeta {
+                    volume: volume.min(64),
+                    finetune: ((finetune & 0x0F) << 4) as i8, // signed nibble, 1/8th of a semitone
+                    ..Default::default()
+                },
                 ..Default::default()
             });
         }
@@ -278,12 +452,101 @@ fn max(f: &[u8; 128]) -> u8 {
 #[cfg(test)]
 mod test {
     use std::fs::File;
+    use std::io::Cursor;
 
     use super::parse_;
+    use crate::interface::{sample::PcmType, Module};
 
     #[test]
     fn a() {
         let mut m = File::open("./modules/debranu.mod").unwrap();
         parse_(&mut m).unwrap();
     }
+
+    #[test]
+    fn adpcm() {
+        let mut module = b"adpcm".to_vec();
+        module.resize(1084, 0);
+        module[20 + 22..20 + 24].copy_from_slice(&8u16.to_be_bytes()); // 16 byte sample
+        module[20 + 25] = 64;
+        module[1080..1084].copy_from_slice(b"M.K.");
+        module.extend_from_slice(&[0u8; 1024]);
+        module.extend_from_slice(b"ADPCM");
+        module.extend_from_slice(&[1u8; 16]); // every delta is +1
+        module.extend_from_slice(&[0u8; 8]);
+
+        let module = parse_(&mut Cursor::new(module)).unwrap();
+        let smp = &module.samples()[0];
+        let expected: Vec<u8> = (1..=16).collect();
+
+        assert_eq!(smp.pcm_type, PcmType::ADPCM4);
+        assert_eq!(module.pcm(smp).unwrap().as_ref(), expected);
+    }
+
+    #[test]
+    fn sample_names() {
+        let mut module = b"names".to_vec();
+        module.resize(1084, 0);
+        module[20..25].copy_from_slice(b"hello");
+        module[20 + 30 * 2..20 + 30 * 2 + 5].copy_from_slice(b"world");
+        module[20 + 22..20 + 24].copy_from_slice(&2u16.to_be_bytes()); // 4 byte sample
+        module[1080..1084].copy_from_slice(b"M.K.");
+        module.extend_from_slice(&[0u8; 1024 + 4]);
+
+        let module = parse_(&mut Cursor::new(module)).unwrap();
+        assert_eq!(module.comments(), "hello\n\nworld");
+        assert_eq!(module.created_with().unwrap().to_string(), "ProTracker");
+    }
+
+    #[test]
+    fn pattern_cells() {
+        use super::unpack_pattern;
+        use crate::interface::pattern::Note;
+
+        // sample 0x11, period 428, effect C
+        let cell = [0x11, 0xAC, 0x1C, 0x40];
+        let pattern = unpack_pattern(&[[0; 4], cell].concat(), 2);
+        let cell = pattern.row(0)[1];
+
+        assert!(pattern.row(0)[0].is_empty());
+        assert_eq!(cell.note, Note::On(60));
+        assert_eq!(cell.instrument, 0x11);
+        assert_eq!((cell.effect, cell.param), (0x0C, 0x40));
+        assert_eq!(pattern.rows(), 64);
+    }
+
+    #[test]
+    fn fifteen_samples() {
+        let mut module = b"soundtracker".to_vec();
+        module.resize(20 + 15 * 30, 0);
+        module[20 + 22..20 + 24].copy_from_slice(&2u16.to_be_bytes()); // 4 byte sample
+        module[20 + 25] = 64;
+        module.extend_from_slice(&[1, 0]); // song length, restart
+        module.resize(600, 0); // orders
+
+        let mut pattern = [0u8; 1024];
+        pattern[..4].copy_from_slice(&[0x01, 0xAC, 0x10, 0x00]);
+        module.extend_from_slice(&pattern);
+        module.extend_from_slice(&[1, 2, 3, 4]);
+
+        let module = parse_(&mut Cursor::new(module)).unwrap();
+        let smp = &module.samples()[0];
+
+        assert_eq!(smp.pointer, 600 + 1024);
+        assert_eq!(module.pcm(smp).unwrap().as_ref(), [1, 2, 3, 4]);
+        assert!(!module.patterns()[0].row(0)[0].is_empty());
+    }
+
+    #[test]
+    fn truncated_patterns() {
+        use super::read_patterns;
+
+        // The second pattern is cut short
+        let mut data = vec![0u8; 1024 + 512];
+        data[..4].copy_from_slice(&[0x01, 0xAC, 0x10, 0x00]);
+
+        let patterns = read_patterns(&mut Cursor::new(data), 2, 4).unwrap();
+        assert_eq!(patterns.len(), 1);
+        assert!(!patterns[0].row(0)[0].is_empty());
+    }
 }
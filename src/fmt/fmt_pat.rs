@@ -0,0 +1,234 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::info;
+use crate::interface::module::{GenericTracker, Module};
+use crate::interface::sample::{
+    remove_invalid_samples, Channel, Depth, Loop, LoopType, Sample, SampleMeta, Vibrato,
+};
+use crate::interface::Error;
+use crate::parser::{
+    bitflag::BitFlag,
+    bytes::magic_header,
+    io::{read_exact_const, ByteReader, ReadSeek},
+    string::{read_str, read_string},
+};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+const NAME: &str = "Gravis Ultrasound Patch";
+
+const MAGIC_GF1PATCH_110: [u8; 12] = *b"GF1PATCH110\0";
+const MAGIC_GF1PATCH_100: [u8; 12] = *b"GF1PATCH100\0";
+const MAGIC_ID: [u8; 10] = *b"ID#000002\0";
+const INVALID: &str = "Not a valid Gravis Ultrasound patch";
+
+/// Offset to the first wave header.
+/// (patch header: 129 bytes, instrument header: 63 bytes, layer header: 47 bytes)
+const PAT_WAVE_OFFSET: u64 = 239;
+
+/* Wave flags */
+const FLAG_BITS_16: u8 = 1 << 0;
+const FLAG_UNSIGNED: u8 = 1 << 1;
+const FLAG_LOOP: u8 = 1 << 2;
+const FLAG_PINGPONG: u8 = 1 << 3;
+const FLAG_BACKWARD: u8 = 1 << 4;
+
+/// Gravis Ultrasound Patch
+pub struct PAT {
+    inner: GenericTracker,
+    samples: Box<[Sample]>,
+    title: Box<str>,
+    comments: Box<str>,
+    source: Option<Box<Path>>,
+}
+
+impl Module for PAT {
+    fn name(&self) -> &str {
+        &self.title
+    }
+
+    fn format(&self) -> &str {
+        NAME
+    }
+
+    fn comments(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.comments)
+    }
+
+    fn pcm(&self, smp: &Sample) -> Result<Cow<'_, [u8]>, Error> {
+        Ok(self.inner.get_slice(smp)?.into())
+    }
+
+    fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    fn load(data: &mut impl ReadSeek) -> Result<Box<dyn Module>, Error> {
+        info!("Loading Gravis Ultrasound Patch");
+        Ok(Box::new(parse_(data)?))
+    }
+
+    fn matches_format(buf: &[u8]) -> bool {
+        magic_header(&MAGIC_GF1PATCH_110, buf) | magic_header(&MAGIC_GF1PATCH_100, buf)
+    }
+
+    fn set_source(mut self: Box<Self>, path: PathBuf) -> Box<dyn Module> {
+        self.source = Some(path.into());
+        self
+    }
+
+    fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+}
+
+pub fn parse_(file: &mut impl ReadSeek) -> Result<PAT, Error> {
+    let magic = file.read_bytes(12)?;
+
+    if magic != MAGIC_GF1PATCH_110 && magic != MAGIC_GF1PATCH_100 {
+        return Err(Error::invalid(INVALID));
+    }
+
+    if file.read_bytes(10)? != MAGIC_ID {
+        return Err(Error::invalid(INVALID));
+    }
+
+    let comments = read_str::<60>(file)?; // description
+    file.skip_bytes(3)?; // instruments, voices, channels
+
+    let waveforms = file.read_u16_le()?;
+    file.skip_bytes(2 + 4 + 36)?; // master volume, data size, reserved
+
+    file.skip_bytes(2)?; // instrument id
+    let title = read_str::<16>(file)?;
+
+    file.set_seek_pos(PAT_WAVE_OFFSET)?;
+    let mut samples = build_samples(file, waveforms)?;
+    remove_invalid_samples(&mut samples, file.len())?;
+
+    let inner = file.load_to_memory()?.into();
+
+    Ok(PAT {
+        inner,
+        samples: samples.into(),
+        title,
+        comments,
+        source: None,
+    })
+}
+
+fn build_samples(file: &mut impl ReadSeek, waveforms: u16) -> Result<Vec<Sample>, Error> {
+    let mut samples: Vec<Sample> = Vec::with_capacity(waveforms as usize);
+    info!("Building samples");
+
+    for index_raw in 0..waveforms {
+        let name = read_string(&file.read_bytes(7)?);
+        file.skip_bytes(1)?; // fractions
+
+        let length = file.read_u32_le()?;
+        let loop_start = file.read_u32_le()?;
+        let loop_end = file.read_u32_le()?;
+        let rate = file.read_u16_le()? as u32;
+        file.skip_bytes(4 + 4 + 4 + 2)?; // low freq, high freq, root freq, tune
+        let balance = file.read_u8()?;
+        file.skip_bytes(6 + 6 + 3)?; // envelope, tremolo
+        let [sweep, speed, vib_depth] = read_exact_const::<3>(file)?;
+
+        let flags = file.read_u8()?;
+        file.skip_bytes(2 + 2 + 36)?; // scale frequency, scale factor, reserved
+
+        let pointer = file.seek_position()? as u32;
+        file.skip_bytes(length as i64)?;
+
+        if length == 0 {
+            info!("Skipping empty sample at index: {}", index_raw + 1);
+            continue;
+        }
+
+        let signed = !flags.contains(FLAG_UNSIGNED);
+        let depth = Depth::new(!flags.contains(FLAG_BITS_16), signed, signed);
+
+        // loop points are stored in bytes
+        let loop_kind = match flags {
+            f if !f.contains(FLAG_LOOP) => LoopType::Off,
+            f if f.contains(FLAG_PINGPONG) => LoopType::PingPong,
+            f if f.contains(FLAG_BACKWARD) => LoopType::Backward,
+            _ => LoopType::Forward,
+        };
+        let loop_start = loop_start / depth.bytes() as u32;
+        let loop_end = loop_end / depth.bytes() as u32;
+
+        samples.push(Sample {
+            filename: None,
+            name,
+            length,
+            rate,
+            pointer,
+            depth,
+            channel: Channel::Mono,
+            index_raw,
+            looping: Loop::new(loop_start, loop_end, loop_kind),
+            meta: SampleMeta {
+                panning: Some(balance.min(15) * 17), // 0-15
+                vibrato: Vibrato {
+                    speed,
+                    depth: vib_depth,
+                    sweep,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{parse_, FLAG_BITS_16, FLAG_LOOP, MAGIC_GF1PATCH_110, MAGIC_ID, PAT_WAVE_OFFSET};
+    use crate::interface::sample::{Depth, Loop, LoopType};
+    use crate::interface::Module;
+
+    #[test]
+    fn samples() {
+        let mut pat = MAGIC_GF1PATCH_110.to_vec();
+        pat.extend_from_slice(&MAGIC_ID);
+        pat.extend_from_slice(b"grand piano");
+        pat.resize(85, 0);
+        pat.extend_from_slice(&1u16.to_le_bytes()); // waveforms
+        pat.resize(131, 0);
+        pat.extend_from_slice(b"piano");
+        pat.resize(PAT_WAVE_OFFSET as usize, 0);
+
+        // 16 bit signed wave with 4 frames, looping over the middle two
+        pat.extend_from_slice(b"C4\0\0\0\0\0\0");
+        pat.extend_from_slice(&8u32.to_le_bytes());
+        pat.extend_from_slice(&2u32.to_le_bytes()); // loop points are in bytes
+        pat.extend_from_slice(&6u32.to_le_bytes());
+        pat.extend_from_slice(&22050u16.to_le_bytes());
+        pat.resize(pat.len() + 14 + 1 + 15 + 3, 0); // frequencies, balance, envelope, vibrato
+        pat.push(FLAG_BITS_16 | FLAG_LOOP);
+        pat.resize(pat.len() + 40, 0);
+        pat.extend_from_slice(&[0u8; 8]);
+
+        let module = parse_(&mut Cursor::new(pat)).unwrap();
+        let smp = &module.samples()[0];
+
+        assert_eq!(module.name(), "piano");
+        assert_eq!(module.comments(), "grand piano");
+        assert_eq!(module.samples().len(), 1);
+        assert_eq!(smp.length, 8);
+        assert_eq!(smp.depth, Depth::I16);
+        assert_eq!(smp.looping, Loop::new(1, 3, LoopType::Forward));
+        assert_eq!(smp.rate, 22050);
+    }
+}
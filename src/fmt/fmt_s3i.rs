@@ -0,0 +1,139 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::fmt_s3m::build_sample;
+use crate::dsp::adpcm::read_pcm;
+use crate::info;
+use crate::interface::module::{GenericTracker, Module};
+use crate::interface::sample::{remove_invalid_samples, Sample};
+use crate::interface::Error;
+use crate::parser::{
+    bytes::magic_header,
+    io::{ByteReader, ReadSeek},
+};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+const NAME: &str = "Scream Tracker 3 Instrument";
+
+const MAGIC_SAMPLE: [u8; 4] = *b"SCRS";
+const MAGIC_INSTRUMENT: [u8; 4] = *b"SCRI";
+
+/// Size of the instrument header.
+///
+/// Some programs don't bother setting the sample pointer,
+/// so we assume the sample data follows the header.
+const S3I_HEADER_SIZE: u32 = 0x50;
+
+/// Scream Tracker 3 Instrument
+pub struct S3I {
+    inner: GenericTracker,
+    samples: Box<[Sample]>,
+    source: Option<Box<Path>>,
+}
+
+impl Module for S3I {
+    fn name(&self) -> &str {
+        match self.samples.first() {
+            Some(smp) => smp.name(),
+            None => "",
+        }
+    }
+
+    fn format(&self) -> &str {
+        NAME
+    }
+
+    fn pcm(&self, smp: &Sample) -> Result<Cow<'_, [u8]>, Error> {
+        read_pcm(&self.inner, smp)
+    }
+
+    fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    fn load(data: &mut impl ReadSeek) -> Result<Box<dyn Module>, Error> {
+        info!("Loading Scream Tracker 3 Instrument");
+        Ok(Box::new(parse_(data)?))
+    }
+
+    fn matches_format(buf: &[u8]) -> bool {
+        let Some(slice) = buf.get(0x4c..) else {
+            return false;
+        };
+
+        buf[0] == 1 && (magic_header(&MAGIC_SAMPLE, slice) | magic_header(&MAGIC_INSTRUMENT, slice))
+    }
+
+    fn set_source(mut self: Box<Self>, path: PathBuf) -> Box<dyn Module> {
+        self.source = Some(path.into());
+        self
+    }
+
+    fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+}
+
+pub fn parse_(file: &mut impl ReadSeek) -> Result<S3I, Error> {
+    // Standalone instruments are stored as unsigned pcm
+    let Some(mut sample) = build_sample(file, 0, false)? else {
+        return Err(Error::invalid(
+            "Scream Tracker 3 Instrument does not contain any pcm data",
+        ));
+    };
+
+    if sample.pointer < S3I_HEADER_SIZE {
+        sample.pointer = S3I_HEADER_SIZE;
+    }
+
+    let mut samples = vec![sample];
+    remove_invalid_samples(&mut samples, file.len())?;
+
+    let inner = file.load_to_memory()?.into();
+
+    Ok(S3I {
+        inner,
+        samples: samples.into(),
+        source: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::exporter::AudioFormat;
+    use crate::interface::sample::{Depth, Sample};
+    use crate::load_module;
+
+    #[test]
+    fn round_trip() {
+        let pcm: Vec<u8> = (0..64).collect();
+        let sample = Sample {
+            name: "round trip".into(),
+            length: pcm.len() as u32,
+            rate: 8363,
+            depth: Depth::U8,
+            ..Default::default()
+        };
+
+        let mut s3i: Vec<u8> = Vec::new();
+        AudioFormat::S3I
+            .get_impl()
+            .write(&sample, pcm.as_slice().into(), &mut s3i)
+            .unwrap();
+
+        let module = load_module(&mut Cursor::new(s3i)).unwrap();
+        let loaded = &module.samples()[0];
+
+        assert_eq!(module.format(), "Scream Tracker 3 Instrument");
+        assert_eq!(loaded.rate, 8363);
+        assert_eq!(loaded.length, 64);
+        assert_eq!(module.pcm(loaded).unwrap().as_ref(), pcm.as_slice());
+    }
+}
@@ -5,15 +5,23 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::info;
+use crate::dsp::adpcm::{adpcm4_len, read_pcm};
+use crate::{info, warn};
 use crate::interface::module::{GenericTracker, Module};
-use crate::interface::sample::{is_sample_valid, Channel, Depth, Loop, LoopType, Sample};
-use crate::interface::Error;
+use crate::interface::sample::{
+    is_sample_valid, Channel, Depth, Loop, LoopType, PcmType, Sample, SampleMeta,
+};
+use crate::interface::pattern::{
+    trim_channels, Effects, Note, Pattern, NOTE_MAX,
+};
+use crate::interface::song::Song;
+use crate::interface::usage::{sample_usage, Instruments, SampleUsage};
+use crate::interface::{Error, TrackerInfo};
 use crate::parser::{
     bitflag::BitFlag,
     bytes::magic_header,
-    io::{is_magic, ByteReader, ReadSeek},
-    string::read_str,
+    io::{is_magic, read_exact_const, ByteReader, ReadSeek},
+    string::{join_lines, read_str},
 };
 use std::borrow::Cow;
 use std::io::Cursor;
@@ -24,8 +32,21 @@ const NAME: &str = "Scream Tracker";
 const MAGIC_SCRM: [u8; 4] = *b"SCRM";
 const MAGIC_NUMBER: [u8; 1] = [0x10];
 const MAGIC_SAMPLE: [u8; 4] = *b"SCRS";
+const MAGIC_INSTRUMENT: [u8; 4] = *b"SCRI";
 const INVALID: &str = "Not a valid Scream Tracker module";
 
+/// ModPlug stores ADPCM compressed samples with this pack value
+const PACK_ADPCM: u8 = 4;
+
+/// Scream Tracker patterns always have 64 rows, and up to 32 channels
+const S3M_ROWS: u16 = 64;
+const S3M_CHANNELS: u8 = 32;
+
+/// A default panning table follows the pattern pointers
+const DEFAULT_PANNING: u8 = 252;
+/// Entries of the default panning table with bit 5 set override the channel panning
+const PAN_ENABLED: u8 = 1 << 5;
+
 const FLAG_LOOP: u8 = 1 << 0;
 const FLAG_STEREO: u8 = 1 << 1;
 const FLAG_BITS: u8 = 1 << 2;
@@ -35,6 +56,10 @@ pub struct S3M {
     inner: GenericTracker,
     samples: Box<[Sample]>,
     name: Box<str>,
+    comments: Box<str>,
+    tracker: Option<TrackerInfo>,
+    patterns: Box<[Pattern]>,
+    song: Song,
     source: Option<Box<Path>>,
 }
 
@@ -47,8 +72,33 @@ impl Module for S3M {
         NAME
     }
 
+    fn comments(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.comments)
+    }
+
+    fn created_with(&self) -> Option<TrackerInfo> {
+        self.tracker.clone()
+    }
+
+    fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    fn song(&self) -> Option<&Song> {
+        Some(&self.song)
+    }
+
+    fn sample_usage(&self) -> Option<Box<[SampleUsage]>> {
+        Some(sample_usage(
+            &self.samples,
+            &self.patterns,
+            &self.song.orders,
+            Instruments::Samples,
+        ))
+    }
+
     fn pcm(&self, smp: &Sample) -> Result<Cow<[u8]>, Error> {
-        Ok(self.inner.get_slice(smp)?.into())
+        read_pcm(&self.inner, smp)
     }
 
     fn samples(&self) -> &[Sample] {
@@ -89,7 +139,10 @@ pub fn parse_(file: &mut impl ReadSeek) -> Result<S3M, Error> {
 
     let ord_count = file.read_u16_le()?;
     let ins_count = file.read_u16_le()?;
-    file.skip_bytes(6)?; // pattern ptr, flags, tracker version
+    let pat_count = file.read_u16_le()?;
+    file.skip_bytes(2)?; // flags
+
+    let created_with = file.read_u16_le()?;
 
     let signed = file.read_u16_le()? == 1;
 
@@ -97,89 +150,289 @@ pub fn parse_(file: &mut impl ReadSeek) -> Result<S3M, Error> {
         return Err(Error::invalid(INVALID));
     }
 
-    file.set_seek_pos((0x0060 + ord_count) as u64)?;
+    let global_volume = file.read_u8()?;
+    let speed = file.read_u8()?;
+    let tempo = file.read_u8()?;
+    file.skip_bytes(2)?; // master volume, ultra click removal
+    let default_panning = file.read_u8()? == DEFAULT_PANNING;
+
+    file.set_seek_pos(0x0040)?;
+    let mut panning = read_exact_const::<{ S3M_CHANNELS as usize }>(file)?.map(s3m_panning);
+
+    file.set_seek_pos(0x0060)?;
+    let orders = file.read_bytes(ord_count as usize)?.into();
+
     let mut ptrs: Vec<u32> = Vec::with_capacity(ins_count as usize);
 
     for _ in 0..ins_count {
         ptrs.push((file.read_u16_le()? as u32) << 4);
     }
 
+    let mut pat_ptrs: Vec<u32> = Vec::with_capacity(pat_count as usize);
+
+    for _ in 0..pat_count {
+        pat_ptrs.push((file.read_u16_le()? as u32) << 4);
+    }
+
+    if default_panning {
+        let table = read_exact_const::<{ S3M_CHANNELS as usize }>(file)?;
+
+        for (pan, value) in panning.iter_mut().zip(table) {
+            if value & PAN_ENABLED != 0 {
+                *pan = (value & 0x0F) * 17;
+            }
+        }
+    }
+
+    let patterns = read_patterns(file, &pat_ptrs);
+    let comments = read_sample_names(file, &ptrs)?;
     let samples = build(file, ptrs, signed)?.into();
     let inner = file.load_to_memory()?.into();
 
+    let channels = patterns.iter().map(Pattern::channels).max().unwrap_or(0);
+
     Ok(S3M {
         name: title,
+        comments,
+        tracker: tracker_info(created_with),
+        song: Song {
+            orders,
+            speed,
+            tempo,
+            global_volume: global_volume.min(64) * 2,
+            channels,
+            panning: panning[..channels as usize].into(),
+            effects: Effects::S3M,
+            ..Default::default()
+        },
+        patterns: patterns.into(),
         inner,
         samples,
         source: None,
     })
 }
 
+/// Channels 0-7 play on the left, 8-15 on the right.
+fn s3m_panning(setting: u8) -> u8 {
+    match setting & 0x7F {
+        0..=7 => 0x33,
+        8..=15 => 0xCC,
+        _ => 0x80,
+    }
+}
+
+/// Read packed patterns.
+///
+/// Pattern data isn't needed to extract samples, so a broken pattern is replaced with an empty one.
+fn read_patterns(file: &mut impl ReadSeek, ptrs: &[u32]) -> Vec<Pattern> {
+    let mut patterns: Vec<Pattern> = Vec::with_capacity(ptrs.len());
+
+    for ptr in ptrs {
+        let pattern = match *ptr {
+            0 => Ok(Pattern::new(S3M_ROWS, S3M_CHANNELS)),
+            ptr => read_pattern(file, ptr),
+        };
+
+        patterns.push(pattern.unwrap_or_else(|_e| {
+            warn!("Could not read pattern at offset {}: {}", ptr, _e);
+            Pattern::new(S3M_ROWS, S3M_CHANNELS)
+        }));
+    }
+
+    trim_channels(&mut patterns);
+    patterns
+}
+
+fn read_pattern(file: &mut impl ReadSeek, ptr: u32) -> Result<Pattern, Error> {
+    file.set_seek_pos(ptr as u64)?;
+    let length = file.read_u16_le()?.saturating_sub(2); // includes itself
+
+    Ok(unpack_pattern(&file.read_bytes(length as usize)?))
+}
+
+/// Each cell starts with a byte containing the channel,
+/// and flags that say which parts of the cell follow.
+/// A byte of 0 ends the row.
+pub(crate) fn unpack_pattern(buf: &[u8]) -> Pattern {
+    const EMPTY: u8 = 255;
+    const NOTE_CUT: u8 = 254;
+
+    let mut pattern = Pattern::new(S3M_ROWS, S3M_CHANNELS);
+    let mut data = buf.iter().copied();
+    let next = |data: &mut dyn Iterator<Item = u8>| data.next().unwrap_or_default();
+    let mut row: u16 = 0;
+
+    while row < S3M_ROWS {
+        // Stop if the pattern data is truncated
+        let Some(what) = data.next() else {
+            break;
+        };
+
+        if what == 0 {
+            row += 1;
+            continue;
+        }
+
+        let cell = pattern.cell_mut(row, what & (S3M_CHANNELS - 1)).unwrap();
+
+        if what & 32 != 0 {
+            // The upper nibble is the octave, C-4 plays a sample at its sample rate
+            cell.note = match next(&mut data) {
+                EMPTY => Note::None,
+                NOTE_CUT => Note::Cut,
+                note if note & 0x0F < 12 => {
+                    Note::On(((note >> 4) * 12 + (note & 0x0F) + 12).min(NOTE_MAX))
+                }
+                _ => Note::None,
+            };
+            cell.instrument = next(&mut data);
+        }
+        if what & 64 != 0 {
+            cell.volume = Some(next(&mut data));
+        }
+        if what & 128 != 0 {
+            cell.effect = next(&mut data);
+            cell.param = next(&mut data);
+        }
+    }
+
+    pattern
+}
+
+/// Guess the tracker from the "created with tracker version" (cwt) field.
+///
+/// The upper 4 bits identify the tracker, the rest is the version.
+fn tracker_info(cwt: u16) -> Option<TrackerInfo> {
+    let version = cwt & 0x0FFF;
+    let bcd = |name: &str| TrackerInfo::bcd(name, version >> 8, version & 0xFF);
+
+    let tracker = match cwt >> 12 {
+        _ if cwt == 0xCA00 => TrackerInfo::new("Camoto", None),
+        0x1 => bcd("Scream Tracker"),
+        0x2 => bcd("Imago Orpheus"),
+        0x3 => bcd("Impulse Tracker"),
+        0x4 => TrackerInfo::schism(version, None),
+        0x5 => bcd("OpenMPT"),
+        0x6 => bcd("BeRoTracker"),
+        0x7 => bcd("CreamTracker"),
+        _ => return None,
+    };
+
+    Some(tracker)
+}
+
+/// Authors often write messages in the sample names, including non-pcm instruments.
+fn read_sample_names(file: &mut impl ReadSeek, ptrs: &[u32]) -> Result<Box<str>, Error> {
+    let mut names: Vec<Box<str>> = Vec::with_capacity(ptrs.len());
+
+    for ptr in ptrs {
+        file.set_seek_pos(*ptr as u64 + 0x30)?;
+        names.push(read_str::<28>(file)?);
+    }
+
+    Ok(join_lines(&names))
+}
+
 fn build(file: &mut impl ReadSeek, ptrs: Vec<u32>, signed: bool) -> Result<Vec<Sample>, Error> {
     let mut samples: Vec<Sample> = Vec::with_capacity(ptrs.len());
 
     for (index_raw, ptr) in ptrs.into_iter().enumerate() {
         file.set_seek_pos(ptr as u64)?;
 
-        if file.read_u8()? != 1 {
-            info!("Skipping non-pcm instrument at index: {}", index_raw + 1);
-            continue;
+        if let Some(sample) = build_sample(file, index_raw, signed)? {
+            samples.push(sample);
         }
-        let filename = read_str::<12>(file)?;
-        let pointer = file.read_u24_le()?; //
-        let length = file.read_u32_le()? & 0xffff; // ignore upper 16 bits
+    }
 
-        if length == 0 {
-            info!("Skipping empty sample at index: {}", index_raw + 1);
-            continue;
-        }
+    Ok(samples)
+}
 
-        let loop_start = file.read_u32_le()?;
-        let loop_stop = file.read_u32_le()?;
-        file.skip_bytes(3)?; // vol, reserved byte, pack
+/// Read an 80 byte instrument header from the current position.
+///
+/// Returns ``None`` if the instrument isn't a valid pcm sample.
+pub(crate) fn build_sample(
+    file: &mut impl ReadSeek,
+    index_raw: usize,
+    signed: bool,
+) -> Result<Option<Sample>, Error> {
+    if file.read_u8()? != 1 {
+        info!("Skipping non-pcm instrument at index: {}", index_raw + 1);
+        return Ok(None);
+    }
+    let filename = read_str::<12>(file)?;
+    let pointer = file.read_u24_le()?; //
+    let length = file.read_u32_le()? & 0xffff; // ignore upper 16 bits
 
-        let flags = file.read_u8()?;
-        let loop_kind = match flags.contains(FLAG_LOOP) {
-            true => LoopType::Forward,
-            false => LoopType::Off,
-        };
+    if length == 0 {
+        info!("Skipping empty sample at index: {}", index_raw + 1);
+        return Ok(None);
+    }
 
-        let rate = file.read_u32_le()? & 0xffff;
-        let rate = if rate <= 1 { 1024 } else { rate }; // TODO: some samples have low freq
+    let loop_start = file.read_u32_le()?;
+    let loop_stop = file.read_u32_le()?;
+    let volume = file.read_u8()?;
+    file.skip_bytes(1)?; // reserved byte
+    let pack = file.read_u8()?;
 
-        file.skip_bytes(12)?; // internal buffer used during playback
+    let flags = file.read_u8()?;
+    let loop_kind = match flags.contains(FLAG_LOOP) {
+        true => LoopType::Forward,
+        false => LoopType::Off,
+    };
 
-        let name = read_str::<28>(file)?;
-        if !is_magic(file, &MAGIC_SAMPLE)? {
-            return Err(Error::invalid(INVALID));
-        }
+    let rate = file.read_u32_le()? & 0xffff;
+    let rate = if rate <= 1 { 1024 } else { rate }; // TODO: some samples have low freq
 
-        let depth = Depth::new(!flags.contains(FLAG_BITS), signed, signed);
-        let channel = Channel::new(flags.contains(FLAG_STEREO), false);
-        let length = length * channel.channels() as u32 * depth.bytes() as u32;
+    file.skip_bytes(12)?; // internal buffer used during playback
 
-        if !is_sample_valid(pointer, length, file.len(), false) {
-            info!("Skipping invalid sample at index: {}...", index_raw + 1);
-            continue;
-        }
+    let name = read_str::<28>(file)?;
 
-        let index_raw = index_raw as u16;
-
-        samples.push(Sample {
-            filename: Some(filename),
-            name,
-            length,
-            rate,
-            pointer,
-            depth,
-            channel,
-            index_raw,
-            looping: Loop::new(loop_start, loop_stop, loop_kind),
-            ..Default::default()
-        })
+    // Standalone instruments may use "SCRI" instead
+    let magic = read_exact_const::<4>(file)?;
+    if magic != MAGIC_SAMPLE && magic != MAGIC_INSTRUMENT {
+        return Err(Error::invalid(INVALID));
     }
 
-    Ok(samples)
+    let (depth, channel, pcm_type) = match pack {
+        // ADPCM always decodes to signed 8 bit mono
+        PACK_ADPCM => (Depth::I8, Channel::Mono, PcmType::ADPCM4),
+        _ => (
+            Depth::new(!flags.contains(FLAG_BITS), signed, signed),
+            Channel::new(flags.contains(FLAG_STEREO), false),
+            PcmType::PCM,
+        ),
+    };
+    let length = length * channel.channels() as u32 * depth.bytes() as u32;
+
+    let stored_length = match pcm_type {
+        PcmType::ADPCM4 => adpcm4_len(length),
+        _ => length,
+    };
+
+    if !is_sample_valid(pointer, stored_length, file.len(), false) {
+        info!("Skipping invalid sample at index: {}...", index_raw + 1);
+        return Ok(None);
+    }
+
+    let index_raw = index_raw as u16;
+
+    Ok(Some(Sample {
+        filename: Some(filename),
+        name,
+        length,
+        rate,
+        pointer,
+        depth,
+        channel,
+        index_raw,
+        pcm_type,
+        looping: Loop::new(loop_start, loop_stop, loop_kind),
+        meta: SampleMeta {
+            volume: volume.min(64),
+            ..Default::default()
+        },
+        ..Default::default()
+    }))
 }
 
 #[test]
@@ -213,3 +466,35 @@ pub fn a() {
     // ripper.change_format(ExportFormat::AIFF.into());
     // ripper.rip_to_dir("./dusk/", &tracker).unwrap()
 }
+
+#[test]
+fn tracker_versions() {
+    let version = |cwt| tracker_info(cwt).map(|t| t.to_string());
+
+    assert_eq!(version(0x1320).as_deref(), Some("Scream Tracker 3.20"));
+    assert_eq!(version(0x3214).as_deref(), Some("Impulse Tracker 2.14"));
+    assert_eq!(version(0x4051).as_deref(), Some("Schism Tracker 2009-11-01"));
+    assert_eq!(version(0), None);
+}
+
+#[test]
+fn packed_pattern() {
+    use crate::interface::pattern::Note;
+
+    #[rustfmt::skip]
+    let packed = [
+        0x20 | 0x40 | 0x80 | 2, 0x40, 3, 32, 4, 0x10, // channel 3: C-4, instrument, volume, effect
+        0,
+        0x20, 254, 0,                                   // channel 1: note cut
+        0,
+    ];
+    let pattern = unpack_pattern(&packed);
+
+    let cell = pattern.row(0)[2];
+    assert_eq!(cell.note, Note::On(60));
+    assert_eq!(cell.instrument, 3);
+    assert_eq!(cell.volume, Some(32));
+    assert_eq!((cell.effect, cell.param), (4, 0x10));
+    assert_eq!(pattern.row(1)[0].note, Note::Cut);
+    assert_eq!(pattern.rows(), 64);
+}
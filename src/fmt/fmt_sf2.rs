@@ -0,0 +1,470 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::interface::module::{GenericTracker, Module};
+use crate::interface::sample::{Channel, Depth, Loop, LoopType, Sample, SampleMeta};
+use crate::interface::Error;
+use crate::parser::{
+    bytes::magic_header,
+    io::{is_magic, read_exact_const, ByteReader, ReadSeek},
+    string::{read_str, read_string},
+};
+use crate::{info, warn};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+const NAME: &str = "SoundFont 2";
+
+const MAGIC_RIFF: [u8; 4] = *b"RIFF";
+const MAGIC_SFBK: [u8; 4] = *b"sfbk";
+const INVALID: &str = "Not a valid SoundFont 2 bank";
+
+/* Chunk sizes */
+const SHDR_SIZE: u32 = 46;
+const INST_SIZE: u32 = 22;
+const BAG_SIZE: u32 = 4;
+const GEN_SIZE: u32 = 4;
+
+/* Generator operators */
+const GEN_SAMPLE_ID: u16 = 53;
+const GEN_SAMPLE_MODES: u16 = 54;
+
+/* Sample types */
+const SAMPLE_ROM: u16 = 0x8000;
+
+/// Pitch used by trackers as a reference (C-5)
+const MIDDLE_C: u8 = 60;
+
+/// SoundFont 2 bank
+pub struct SF2 {
+    inner: GenericTracker,
+    samples: Box<[Sample]>,
+    title: Box<str>,
+    comments: Box<str>,
+    source: Option<Box<Path>>,
+    /// Offset to the ``smpl`` chunk
+    smpl: u32,
+    /// Offset to the ``sm24`` chunk, if the bank contains 24-bit samples
+    sm24: Option<u32>,
+}
+
+impl SF2 {
+    /// 24-bit samples are split into two chunks:
+    /// ``smpl`` holds the upper 16 bits, ``sm24`` holds the lower 8 bits.
+    ///
+    /// Recombine them into packed 24-bit pcm.
+    fn pcm_24_bit(&self, smp: &Sample, sm24: u32) -> Result<Vec<u8>, Error> {
+        let frames = smp.length_frames();
+        let start = (smp.pointer - self.smpl) as usize / 2;
+
+        let msb_offset = smp.pointer as usize;
+        let lsb_offset = sm24 as usize + start;
+
+        let msb = self
+            .inner
+            .get_slice_from_range(smp, msb_offset..(msb_offset + frames * 2))?;
+        let lsb = self
+            .inner
+            .get_slice_from_range(smp, lsb_offset..(lsb_offset + frames))?;
+
+        let mut pcm: Vec<u8> = Vec::with_capacity(frames * 3);
+
+        for (lsb, msb) in lsb.iter().zip(msb.chunks_exact(2)) {
+            pcm.extend_from_slice(&[*lsb, msb[0], msb[1]]);
+        }
+
+        Ok(pcm)
+    }
+}
+
+impl Module for SF2 {
+    fn name(&self) -> &str {
+        &self.title
+    }
+
+    fn format(&self) -> &str {
+        NAME
+    }
+
+    fn comments(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.comments)
+    }
+
+    fn pcm(&self, smp: &Sample) -> Result<Cow<'_, [u8]>, Error> {
+        match (smp.depth, self.sm24) {
+            (Depth::I24, Some(sm24)) => Ok(self.pcm_24_bit(smp, sm24)?.into()),
+            _ => Ok(self.inner.get_slice(smp)?.into()),
+        }
+    }
+
+    fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    fn load(data: &mut impl ReadSeek) -> Result<Box<dyn Module>, Error> {
+        info!("Loading SoundFont 2 bank");
+        Ok(Box::new(parse_(data)?))
+    }
+
+    fn matches_format(buf: &[u8]) -> bool {
+        match buf.get(8..) {
+            Some(slice) => magic_header(&MAGIC_RIFF, buf) && magic_header(&MAGIC_SFBK, slice),
+            None => false,
+        }
+    }
+
+    fn set_source(mut self: Box<Self>, path: PathBuf) -> Box<dyn Module> {
+        self.source = Some(path.into());
+        self
+    }
+
+    fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+}
+
+/// Location of a chunk's data
+#[derive(Clone, Copy)]
+struct Chunk {
+    offset: u32,
+    size: u32,
+}
+
+/// Chunks we care about
+#[derive(Default)]
+struct Chunks {
+    name: Option<Box<str>>,
+    comment: Option<Box<str>>,
+    smpl: Option<Chunk>,
+    sm24: Option<Chunk>,
+    shdr: Option<Chunk>,
+    inst: Option<Chunk>,
+    ibag: Option<Chunk>,
+    igen: Option<Chunk>,
+}
+
+pub fn parse_(file: &mut impl ReadSeek) -> Result<SF2, Error> {
+    if !is_magic(file, &MAGIC_RIFF)? {
+        return Err(Error::invalid(INVALID));
+    }
+
+    let riff_size = file.read_u32_le()?;
+
+    if !is_magic(file, &MAGIC_SFBK)? {
+        return Err(Error::invalid(INVALID));
+    }
+
+    let end = match file.len() {
+        Some(len) => (riff_size as u64 + 8).min(len),
+        None => riff_size as u64 + 8,
+    };
+
+    let mut chunks = Chunks::default();
+    read_chunks(file, 12, end, &mut chunks)?;
+
+    let (Some(smpl), Some(shdr)) = (chunks.smpl, chunks.shdr) else {
+        return Err(Error::invalid(
+            "SoundFont 2 bank does not contain any samples",
+        ));
+    };
+
+    // The sm24 chunk is ignored if its size doesn't line up with the smpl chunk
+    let sm24 = chunks
+        .sm24
+        .filter(|sm24| sm24.size >= smpl.size / 2)
+        .map(|sm24| sm24.offset);
+
+    if chunks.sm24.is_some() && sm24.is_none() {
+        warn!("SoundFont 2 bank has an invalid sm24 chunk, samples will be 16-bit");
+    }
+
+    let loops = sample_modes(file, &chunks, shdr.size / SHDR_SIZE)?;
+    let samples = build_samples(file, shdr, smpl, sm24.is_some(), &loops)?.into();
+    let inner = file.load_to_memory()?.into();
+
+    Ok(SF2 {
+        inner,
+        samples,
+        title: chunks.name.unwrap_or_default(),
+        comments: chunks.comment.unwrap_or_default(),
+        source: None,
+        smpl: smpl.offset,
+        sm24,
+    })
+}
+
+/// Walk through the RIFF structure and record the location of each chunk.
+fn read_chunks(
+    file: &mut impl ReadSeek,
+    start: u64,
+    end: u64,
+    chunks: &mut Chunks,
+) -> Result<(), Error> {
+    let mut offset = start;
+
+    while offset + 8 <= end {
+        file.set_seek_pos(offset)?;
+
+        let id = read_exact_const::<4>(file)?;
+        let size = file.read_u32_le()?;
+        let data = offset + 8;
+
+        let chunk = Chunk {
+            offset: data as u32,
+            size,
+        };
+
+        match &id {
+            b"LIST" => read_chunks(file, data + 4, (data + size as u64).min(end), chunks)?,
+            b"INAM" => chunks.name = Some(read_string(&file.read_bytes(size.min(256) as usize)?)),
+            b"ICMT" => chunks.comment = Some(read_string(&file.read_bytes(size.min(65536) as usize)?)),
+            b"smpl" => chunks.smpl = Some(chunk),
+            b"sm24" => chunks.sm24 = Some(chunk),
+            b"shdr" => chunks.shdr = Some(chunk),
+            b"inst" => chunks.inst = Some(chunk),
+            b"ibag" => chunks.ibag = Some(chunk),
+            b"igen" => chunks.igen = Some(chunk),
+            _ => (),
+        };
+
+        // chunks are padded to an even size
+        offset = data + size as u64 + (size & 1) as u64;
+    }
+
+    Ok(())
+}
+
+/// Sample headers don't say if a sample loops, the instruments using them do.
+///
+/// Returns a list of sample ids that are looped by at least one instrument zone.
+fn sample_modes(file: &mut impl ReadSeek, chunks: &Chunks, total: u32) -> Result<Vec<bool>, Error> {
+    let mut loops = vec![false; total as usize];
+
+    let (Some(inst), Some(ibag), Some(igen)) = (chunks.inst, chunks.ibag, chunks.igen) else {
+        return Ok(loops);
+    };
+
+    let mut instruments: Vec<u16> = Vec::with_capacity((inst.size / INST_SIZE) as usize);
+    file.set_seek_pos(inst.offset as u64)?;
+
+    for _ in 0..inst.size / INST_SIZE {
+        file.skip_bytes(20)?; // instrument name
+        instruments.push(file.read_u16_le()?);
+    }
+
+    let mut bags: Vec<u16> = Vec::with_capacity((ibag.size / BAG_SIZE) as usize);
+    file.set_seek_pos(ibag.offset as u64)?;
+
+    for _ in 0..ibag.size / BAG_SIZE {
+        bags.push(file.read_u16_le()?);
+        file.skip_bytes(2)?; // modulator index
+    }
+
+    let mut generators: Vec<(u16, u16)> = Vec::with_capacity((igen.size / GEN_SIZE) as usize);
+    file.set_seek_pos(igen.offset as u64)?;
+
+    for _ in 0..igen.size / GEN_SIZE {
+        generators.push((file.read_u16_le()?, file.read_u16_le()?));
+    }
+
+    for zones in instruments.windows(2) {
+        let (first, last) = (zones[0] as usize, zones[1] as usize);
+        let mut global_mode: Option<u16> = None;
+
+        for zone in first..last {
+            let (Some(&start), Some(&end)) = (bags.get(zone), bags.get(zone + 1)) else {
+                break;
+            };
+
+            let mut mode: Option<u16> = None;
+            let mut sample_id: Option<u16> = None;
+
+            for (oper, amount) in generators
+                .get(start as usize..end as usize)
+                .unwrap_or_default()
+            {
+                match *oper {
+                    GEN_SAMPLE_MODES => mode = Some(*amount),
+                    GEN_SAMPLE_ID => sample_id = Some(*amount),
+                    _ => (),
+                }
+            }
+
+            match sample_id {
+                // The first zone is global if it doesn't reference a sample
+                None if zone == first => global_mode = mode,
+                None => (),
+                Some(id) => {
+                    if let Some(looped) = loops.get_mut(id as usize) {
+                        // 1 = continuous loop, 3 = loop until release
+                        *looped |= matches!(mode.or(global_mode), Some(1 | 3));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(loops)
+}
+
+fn build_samples(
+    file: &mut impl ReadSeek,
+    shdr: Chunk,
+    smpl: Chunk,
+    is_24_bit: bool,
+    loops: &[bool],
+) -> Result<Vec<Sample>, Error> {
+    // The last header is a terminal record
+    let total = (shdr.size / SHDR_SIZE).saturating_sub(1);
+    let mut samples: Vec<Sample> = Vec::with_capacity(total as usize);
+    let smpl_frames = smpl.size / 2;
+
+    file.set_seek_pos(shdr.offset as u64)?;
+
+    for index_raw in 0..total {
+        let name = read_str::<20>(file)?;
+        let start = file.read_u32_le()?;
+        let end = file.read_u32_le()?;
+        let loop_start = file.read_u32_le()?;
+        let loop_end = file.read_u32_le()?;
+        let rate = file.read_u32_le()?;
+        let original_pitch = file.read_u8()?;
+        let pitch_correction = file.read_u8()? as i8;
+        file.skip_bytes(2)?; // sample link
+        let sample_type = file.read_u16_le()?;
+
+        if sample_type & SAMPLE_ROM != 0 {
+            info!("Skipping ROM sample at index: {}", index_raw + 1);
+            continue;
+        }
+
+        if end <= start || end > smpl_frames {
+            info!("Skipping invalid sample at index: {}...", index_raw + 1);
+            continue;
+        }
+
+        let depth = match is_24_bit {
+            true => Depth::I24,
+            false => Depth::I16,
+        };
+
+        let frames = end - start;
+        let loop_kind = match loops.get(index_raw as usize) {
+            Some(true) => LoopType::Forward,
+            _ => LoopType::Off,
+        };
+
+        samples.push(Sample {
+            filename: None,
+            name,
+            length: frames * depth.bytes() as u32,
+            rate: c5_rate(rate, original_pitch, pitch_correction),
+            meta: SampleMeta {
+                finetune: (pitch_correction as i32 * 128 / 100).clamp(-128, 127) as i8, // cents
+                relative_note: match original_pitch {
+                    0..=127 => MIDDLE_C as i8 - original_pitch as i8,
+                    _ => 0,
+                },
+                ..Default::default()
+            },
+            pointer: smpl.offset + start * 2,
+            depth,
+            channel: Channel::Mono,
+            index_raw: index_raw as u16,
+            looping: Loop::new(
+                loop_start.saturating_sub(start),
+                loop_end.saturating_sub(start),
+                loop_kind,
+            ),
+            ..Default::default()
+        });
+    }
+
+    Ok(samples)
+}
+
+/// Like XM, the original pitch is folded into the sample rate
+/// so that the sample plays at the correct pitch on C-5.
+fn c5_rate(rate: u32, original_pitch: u8, pitch_correction: i8) -> u32 {
+    // Values above 127 mean the sample is unpitched
+    let original_pitch = match original_pitch {
+        0..=127 => original_pitch,
+        _ => MIDDLE_C,
+    };
+
+    let semitones = (MIDDLE_C as f64 - original_pitch as f64) + (pitch_correction as f64 / 100.0);
+    (rate as f64 * 2.0_f64.powf(semitones / 12.0)).round() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{c5_rate, parse_};
+    use crate::interface::sample::Depth;
+    use crate::interface::Module;
+    use std::io::Cursor;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut buf = id.to_vec();
+        buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(data);
+        if data.len() % 2 != 0 {
+            buf.push(0);
+        }
+        buf
+    }
+
+    fn list(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = kind.to_vec();
+        chunks.iter().for_each(|c| data.extend_from_slice(c));
+        chunk(b"LIST", &data)
+    }
+
+    fn shdr(name: &[u8], start: u32, end: u32) -> Vec<u8> {
+        let mut buf = [0u8; 20].to_vec();
+        buf[..name.len()].copy_from_slice(name);
+        for value in [start, end, start, end, 22050] {
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        buf.extend_from_slice(&[60, 0, 0, 0, 1, 0]);
+        buf
+    }
+
+    #[test]
+    fn load_24_bit() {
+        let smpl: Vec<u8> = vec![0x10, 0x20, 0x30, 0x40, 0x50, 0x60];
+        let sm24: Vec<u8> = vec![0x01, 0x02, 0x03];
+
+        let mut headers = shdr(b"sine", 1, 3);
+        headers.extend(shdr(b"EOS", 0, 0));
+
+        let body = [
+            b"sfbk".to_vec(),
+            list(b"INFO", &[chunk(b"INAM", b"bank\0")]),
+            list(b"sdta", &[chunk(b"smpl", &smpl), chunk(b"sm24", &sm24)]),
+            list(b"pdta", &[chunk(b"shdr", &headers)]),
+        ]
+        .concat();
+
+        let module = parse_(&mut Cursor::new(chunk(b"RIFF", &body))).unwrap();
+        let sample = &module.samples()[0];
+
+        assert_eq!(module.name(), "bank");
+        assert_eq!(sample.depth, Depth::I24);
+        assert_eq!(sample.length_frames(), 2);
+        assert_eq!(
+            module.pcm(sample).unwrap().as_ref(),
+            [0x02, 0x30, 0x40, 0x03, 0x50, 0x60]
+        );
+    }
+
+    #[test]
+    fn pitch_folding() {
+        assert_eq!(c5_rate(44100, 60, 0), 44100);
+        assert_eq!(c5_rate(44100, 72, 0), 22050);
+        assert_eq!(c5_rate(44100, 255, 0), 44100);
+    }
+}
@@ -5,142 +5,432 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::borrow::Cow;
 use std::io::Cursor;
+use std::path::{Path, PathBuf};
 
-use crate::fmt::{formats::*, loader::identify_module, Format};
-use crate::info;
-use crate::interface::{Error, Module};
+use crate::common::MAX_SIZE_BYTES;
+use crate::fmt::fmt_zip::wav_sample;
+use crate::fmt::{formats::*, loader::identify_compression, Compression, Format};
+use crate::interface::module::GenericTracker;
+use crate::interface::{Error, Module, Sample};
 use crate::parser::io::Container;
 use crate::parser::{
     bytes::magic_header,
     io::{is_magic, ByteReader, ReadSeek},
     string::read_string,
 };
+use crate::{info, warn};
 
-const MAGIC_UPKG: [u8; 4] = [0xC1, 0x83, 0x2A, 0x9E];
+const NAME: &str = "Unreal Package";
 
-struct Private;
+const MAGIC_UPKG: [u8; 4] = [0xC1, 0x83, 0x2A, 0x9E];
 
 /// Unreal Package
 ///
 /// "Abandon all hope ye who try to parse this file format." - Tim Sweeney, Unreal Packages
-pub struct UMX(Private);
+///
+/// Packages with a single ``Music`` export are loaded as the module they contain.
+/// Packages with more than one, or with ``Sound`` exports (UAX) are bundled together.
+pub struct UMX {
+    /// The package itself, only stored if it contains ``Sound`` exports
+    inner: GenericTracker,
+    modules: Box<[Box<dyn Module>]>,
+    /// Where each bundled sample originates from
+    origin: Box<[Origin]>,
+    samples: Box<[Sample]>,
+    title: Box<str>,
+    source: Option<Box<Path>>,
+}
+
+impl Module for UMX {
+    fn name(&self) -> &str {
+        &self.title
+    }
+
+    fn format(&self) -> &str {
+        NAME
+    }
+
+    /// Comments of each bundled module
+    fn comments(&self) -> Cow<'_, str> {
+        let comments: Vec<Cow<str>> = self
+            .modules
+            .iter()
+            .map(|module| module.comments())
+            .filter(|comments| !comments.is_empty())
+            .collect();
+
+        Cow::Owned(comments.join("\n\n"))
+    }
+
+    fn pcm(&self, smp: &Sample) -> Result<Cow<'_, [u8]>, Error> {
+        match self.origin.get(smp.index_raw as usize) {
+            Some(Origin::Module(module, index)) => {
+                let module = &self.modules[*module];
+                module.pcm(&module.samples()[*index])
+            }
+            Some(Origin::Sound) => Ok(self.inner.get_slice(smp)?.into()),
+            None => Err(Error::bad_sample(smp)),
+        }
+    }
+
+    fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
 
-impl UMX {
-    pub fn load(data: &mut impl ReadSeek) -> Result<Box<dyn Module>, Error> {
+    fn load(data: &mut impl ReadSeek) -> Result<Box<dyn Module>, Error> {
         info!("Loading Unreal package");
         parse_(data)
     }
-    pub fn matches_format(buf: &[u8]) -> bool {
+
+    fn matches_format(buf: &[u8]) -> bool {
         magic_header(&MAGIC_UPKG, buf)
     }
+
+    fn set_source(mut self: Box<Self>, path: PathBuf) -> Box<dyn Module> {
+        self.source = Some(path.into());
+        self
+    }
+
+    fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+}
+
+enum Origin {
+    /// Module & sample index
+    Module(usize, usize),
+    /// Embedded WAV data of a ``Sound`` export
+    Sound,
+}
+
+struct Header {
+    version: u16,
+    name_count: u32,
+    name_offset: u32,
+    export_count: u32,
+    export_offset: u32,
+    import_count: u32,
+    import_offset: u32,
+}
+
+/// An object stored in the package, with its class name resolved.
+struct Export {
+    class: Box<str>,
+    name: Box<str>,
+    serial_size: u32,
+    serial_offset: u32,
 }
 
 pub fn parse_(file: &mut impl ReadSeek) -> Result<Box<dyn Module>, Error> {
+    let header = read_header(file)?;
+    let exports = read_exports(file, &header)?;
+
+    let mut modules: Vec<(u32, Box<str>, Box<dyn Module>)> = Vec::new();
+    let mut error: Option<Error> = None;
+
+    for export in exports
+        .iter()
+        .filter(|e| e.class.eq_ignore_ascii_case("Music"))
+    {
+        match load_music(file, header.version, export) {
+            Ok((offset, module)) => modules.push((offset, export.name.clone(), module)),
+            Err(e) => {
+                warn!("Could not load music object: {}, {}", export.name, e);
+                error = Some(e);
+            }
+        }
+    }
+
+    let mut sounds: Vec<Sample> = Vec::new();
+
+    for export in exports
+        .iter()
+        .filter(|e| e.class.eq_ignore_ascii_case("Sound"))
+    {
+        match load_sound(file, header.version, export) {
+            Ok(Some(smp)) => sounds.push(smp),
+            Ok(None) => info!("Skipping unsupported sound object: {}", export.name),
+            Err(e) => {
+                warn!("Could not load sound object: {}, {}", export.name, e);
+                error = Some(e);
+            }
+        }
+    }
+
+    match (modules.len(), sounds.len()) {
+        (0, 0) => Err(error.unwrap_or_else(|| {
+            Error::invalid("Unreal Package does not contain any music or sounds")
+        })),
+        (1, 0) => Ok(modules.pop().unwrap().2),
+        (_, 0) => Ok(Box::new(bundle(modules, sounds, Vec::new()))),
+        _ => Ok(Box::new(bundle(modules, sounds, file.load_to_memory()?))),
+    }
+}
+
+fn read_header(file: &mut impl ReadSeek) -> Result<Header, Error> {
     if !is_magic(file, &MAGIC_UPKG)? {
         return Err(Error::invalid("Not a valid Unreal package"));
     }
 
-    let version = file.read_u32_le()?;
-    if version < 61 {
-        return Err(Error::unsupported(
-            "UMX versions under 61 are not supported",
-        ));
-    }
-    file.skip_bytes(4)?;
+    let version = file.read_u16_le()?;
+    file.skip_bytes(2)?; // licensee
+    file.skip_bytes(4)?; // package flags
+
+    Ok(Header {
+        version,
+        name_count: file.read_u32_le()?,
+        name_offset: file.read_u32_le()?,
+        export_count: file.read_u32_le()?,
+        export_offset: file.read_u32_le()?,
+        import_count: file.read_u32_le()?,
+        import_offset: file.read_u32_le()?,
+    })
+}
 
-    let name_count = file.read_u32_le()?;
-    let name_offset = file.read_u32_le()?;
-    file.skip_bytes(4)?;
+/// Walk the export table, resolving each object's class through the name & import tables.
+fn read_exports(file: &mut impl ReadSeek, header: &Header) -> Result<Vec<Export>, Error> {
+    let version = header.version;
 
-    let export_offset = file.read_u32_le()?;
-    file.set_seek_pos(name_offset as u64)?;
+    file.set_seek_pos(header.name_offset as u64)?;
+    let mut names: Vec<Box<str>> =
+        Vec::with_capacity(header.name_count.min(u16::MAX as u32) as usize);
 
-    let mut contains_music: bool = false;
+    for _ in 0..header.name_count {
+        names.push(match version {
+            v if v < 64 => name_table_below_64(file)?,
+            _ => name_table_above_64(file)?,
+        });
+        file.skip_bytes(4)?; // flags
+    }
 
-    let get_name_entry = match version {
-        v if v < 64 => name_table_below_64,
-        _ => name_table_above_64,
+    let name = |index: i32| -> Result<Box<str>, Error> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| names.get(i))
+            .cloned()
+            .ok_or_else(|| Error::invalid("Unreal package references an invalid name"))
     };
 
-    for _ in 0..name_count {
-        let name = get_name_entry(file)?;
-        if name.as_ref() == "Music" {
-            contains_music = true;
-            break;
+    file.set_seek_pos(header.import_offset as u64)?;
+    let mut imports: Vec<Box<str>> = Vec::new();
+
+    for _ in 0..header.import_count {
+        let _ = read_compact_index(file)?; // class package
+        let _ = read_compact_index(file)?; // class name
+
+        // package
+        match version {
+            v if v >= 60 => file.skip_bytes(4)?,
+            _ => _ = read_compact_index(file)?,
         }
 
-        file.skip_bytes(4)?;
+        imports.push(name(read_compact_index(file)?)?);
     }
 
-    if !contains_music {
-        return Err(Error::invalid("Unreal Package does not contain any music"));
-    }
+    file.set_seek_pos(header.export_offset as u64)?;
+    let mut raw_exports: Vec<(i32, Box<str>, u32, u32)> = Vec::new();
+
+    for _ in 0..header.export_count {
+        let class_index = read_compact_index(file)?;
+        let _ = read_compact_index(file)?; // super index
 
-    file.set_seek_pos(export_offset as u64)?;
+        if version >= 60 {
+            file.skip_bytes(4)?; // group
+        }
 
-    let _ = read_compact_index(file)?; // class index
-    let _ = read_compact_index(file)?; // super index
-    file.skip_bytes(4)?; // group
+        let object_name = name(read_compact_index(file)?)?;
+        file.skip_bytes(4)?; // obj flags
 
-    let _ = read_compact_index(file)?; // obj name
-    file.skip_bytes(4)?; // obj flags
+        let serial_size = read_compact_index(file)?.max(0) as u32;
+        let serial_offset = match serial_size {
+            0 => 0,
+            _ => read_compact_index(file)?.max(0) as u32,
+        };
 
-    let serial_size = read_compact_index(file)?;
-    if serial_size == 0 {
+        raw_exports.push((class_index, object_name, serial_size, serial_offset));
+    }
+
+    // A negative class index refers to an import, a positive one refers to another export.
+    let class_name = |index: i32| -> Box<str> {
+        let class = match index {
+            i if i < 0 => imports.get((-i - 1) as usize),
+            i if i > 0 => raw_exports.get((i - 1) as usize).map(|e| &e.1),
+            _ => None,
+        };
+        class.cloned().unwrap_or_else(|| "Class".into())
+    };
+
+    Ok(raw_exports
+        .iter()
+        .map(|(class_index, name, serial_size, serial_offset)| Export {
+            class: class_name(*class_index),
+            name: name.clone(),
+            serial_size: *serial_size,
+            serial_offset: *serial_offset,
+        })
+        .collect())
+}
+
+/// Locate the raw data stored inside a ``Music`` or ``Sound`` object.
+///
+/// Returns the offset and size of the data.
+fn object_data(
+    file: &mut impl ReadSeek,
+    version: u16,
+    export: &Export,
+) -> Result<(u32, u32), Error> {
+    if export.serial_size == 0 {
         return Err(Error::invalid("UMX doesn't contain anything"));
     }
 
-    let serial_offset = read_compact_index(file)? as u64;
-    file.set_seek_pos(serial_offset)?;
+    file.set_seek_pos(export.serial_offset as u64)?;
 
-    let _ = read_compact_index(file)?; // skip name index
+    if version < 40 {
+        file.skip_bytes(8)?;
+    }
 
-    if version > 61 {
-        file.skip_bytes(4)?;
+    if version < 60 {
+        file.skip_bytes(16)?;
     }
 
-    let _ = read_compact_index(file)?; // obj size field
-    let _inner_size = read_compact_index(file)? as u64;
+    let _ = read_compact_index(file)?; // property list, should only contain "None"
 
-    // let start_pos = file.position() as usize;
+    match version {
+        v if v >= 120 => {
+            let _ = read_compact_index(file)?; // format name
+            file.skip_bytes(8)?;
+        }
+        v if v >= 100 => {
+            file.skip_bytes(4)?;
+            let _ = read_compact_index(file)?; // format name
+            file.skip_bytes(4)?;
+        }
+        v if v >= 62 => {
+            let _ = read_compact_index(file)?; // format name
+            file.skip_bytes(4)?; // offset to the end of the object
+        }
+        _ => {
+            let _ = read_compact_index(file)?; // format name
+        }
+    }
 
-    // let file: Vec<u8> = std::mem::take(file)
-    //     .into_inner()
-    //     .drain(start_pos..(start_pos + inner_size as usize))
-    //     .collect(); // remove umx header + tables
+    let size = read_compact_index(file)?.max(0) as u32;
+    let offset = file.seek_position()? as u32;
 
-    let size = file.size();
+    Ok((offset, size))
+}
+
+fn load_music(
+    file: &mut impl ReadSeek,
+    version: u16,
+    export: &Export,
+) -> Result<(u32, Box<dyn Module>), Error> {
+    let (offset, size) = object_data(file, version, export)?;
 
     // store the reader into a Container struct
     // so that seeking is relative to this current offset
-    let mut file = Container::new(file, size);
+    let mut file = Container::new(file, Some(offset as u64 + size as u64));
     let file = &mut file;
 
     // done to prevent overflow compile error
-    let module: Box<dyn Module> = match identify_module(file)? {
-        Format::IT => IT::load(file)?,
-        Format::XM => XM::load(file)?,
-        Format::S3M => S3M::load(file)?,
-        Format::MOD => MOD::load(file)?,
-        Format::UMX => return Err(Error::invalid("Nested Unreal music containers are invalid")),
+    let module: Box<dyn Module> = match identify_compression(file)? {
+        (Compression::None, Format::IT) => IT::load(file)?,
+        (Compression::None, Format::XM) => XM::load(file)?,
+        (Compression::None, Format::S3M) => S3M::load(file)?,
+        (Compression::None, Format::MOD) => MOD::load(file)?,
+        (Compression::None, Format::UMX) => {
+            return Err(Error::invalid("Nested Unreal music containers are invalid"))
+        }
+        _ => {
+            return Err(Error::invalid(
+                "Unreal package does not contain a tracker module",
+            ))
+        }
     };
-    Ok(module)
+    Ok((offset, module))
+}
+
+/// Sound objects store a WAV file, its pcm is referenced directly.
+fn load_sound(
+    file: &mut impl ReadSeek,
+    version: u16,
+    export: &Export,
+) -> Result<Option<Sample>, Error> {
+    let (offset, size) = object_data(file, version, export)?;
+
+    // The size is read from the file, don't let it allocate more than what's there.
+    let remaining = file.size().unwrap_or(MAX_SIZE_BYTES).saturating_sub(offset as u64);
+
+    if size as u64 > remaining.min(MAX_SIZE_BYTES) {
+        return Err(Error::invalid("Sound object is larger than the package"));
+    }
+
+    let riff = file.read_bytes(size as usize)?;
+
+    Ok(
+        wav_sample(&mut Cursor::new(riff), offset, 0)?.map(|smp| Sample {
+            name: export.name.clone(),
+            ..smp
+        }),
+    )
+}
+
+/// Combine modules and sounds into one, samples are renumbered and their pointers made absolute.
+fn bundle(
+    modules: Vec<(u32, Box<str>, Box<dyn Module>)>,
+    sounds: Vec<Sample>,
+    package: Vec<u8>,
+) -> UMX {
+    let mut samples: Vec<Sample> = Vec::new();
+    let mut origin: Vec<Origin> = Vec::new();
+    let mut names: Vec<&str> = Vec::new();
+
+    for (module_index, (offset, name, module)) in modules.iter().enumerate() {
+        names.push(name);
+
+        for (index, smp) in module.samples().iter().enumerate() {
+            samples.push(Sample {
+                index_raw: samples.len() as u16,
+                pointer: offset + smp.pointer,
+                ..smp.clone()
+            });
+            origin.push(Origin::Module(module_index, index));
+        }
+    }
+
+    for smp in sounds {
+        samples.push(Sample {
+            index_raw: samples.len() as u16,
+            ..smp
+        });
+        origin.push(Origin::Sound);
+    }
+
+    UMX {
+        inner: package.into(),
+        title: names.join(", ").into(),
+        modules: modules.into_iter().map(|(_, _, module)| module).collect(),
+        origin: origin.into(),
+        samples: samples.into(),
+        source: None,
+    }
 }
 
 fn name_table_above_64(file: &mut impl ReadSeek) -> Result<Box<str>, Error> {
-    let length: usize = file.read_u8()? as usize;
+    let length = read_compact_index(file)?.max(0) as usize;
     Ok(read_string(&file.read_bytes(length)?))
 }
 
 fn name_table_below_64(file: &mut impl ReadSeek) -> Result<Box<str>, Error> {
-    const NULL: &u8 = &0;
-    const MAX_LEN: usize = 10;
+    let mut buffer: Vec<u8> = Vec::new();
 
-    let mut buffer: Vec<u8> = Vec::with_capacity(MAX_LEN);
-
-    while buffer.last() != Some(NULL) && buffer.len() < MAX_LEN {
-        buffer.push(file.read_byte()?)
+    loop {
+        match file.read_byte()? {
+            0 => break,
+            byte => buffer.push(byte),
+        }
     }
 
     Ok(read_string(&buffer))
@@ -190,7 +480,7 @@ mod tests {
 
     use crate::fmt::fmt_umx::read_compact_index;
 
-    use super::parse_;
+    use super::{parse_, MAGIC_UPKG};
 
     // Test read compact index works
     #[test]
@@ -218,4 +508,191 @@ mod tests {
         // let _ = a.drain(..1);
         dbg!(a);
     }
+
+    /// Inverse of [read_compact_index] for positive numbers
+    fn compact(mut n: u32) -> Vec<u8> {
+        let mut out = vec![(n & 0x3F) as u8];
+        n >>= 6;
+        while n > 0 {
+            *out.last_mut().unwrap() |= if out.len() == 1 { 0x40 } else { 0x80 };
+            out.push((n & 0x7F) as u8);
+            n >>= 7;
+        }
+        out
+    }
+
+    fn tiny_mod(title: &[u8], fill: u8) -> Vec<u8> {
+        let mut module = title.to_vec();
+        module.resize(1084, 0);
+        module[20 + 22..20 + 24].copy_from_slice(&8u16.to_be_bytes());
+        module[20 + 25] = 64;
+        module[1080..1084].copy_from_slice(b"M.K.");
+        module.extend_from_slice(&[0u8; 1024]);
+        module.extend_from_slice(&[fill; 16]);
+        module
+    }
+
+    /// Build a package with a ``Music`` export for each module and a ``Sound`` export for each WAV.
+    fn package(version: u16, modules: &[Vec<u8>], sounds: &[Vec<u8>]) -> Vec<u8> {
+        let names = [
+            "None", "Music", "Core", "Class", "mod", "SongA", "SongB", "Sound", "wav", "Kick",
+        ];
+
+        let mut table = Vec::new();
+        for name in names {
+            if version >= 64 {
+                table.extend(compact(name.len() as u32 + 1));
+            }
+            table.extend_from_slice(name.as_bytes());
+            table.extend_from_slice(&[0, 0, 0, 0, 0]); // null, flags
+        }
+
+        // Music & Sound are imported from Core.Class
+        let mut imports = Vec::new();
+        for class in [1, 7] {
+            imports.extend_from_slice(&[2, 3]);
+            match version {
+                v if v >= 60 => imports.extend_from_slice(&[0u8; 4]),
+                _ => imports.push(0),
+            }
+            imports.push(class);
+        }
+
+        let header_size = 64;
+        let name_offset = header_size;
+        let import_offset = name_offset + table.len();
+        let export_offset = import_offset + imports.len();
+
+        // Export table size depends on the offsets it stores, so overestimate it.
+        let export_count = modules.len() + sounds.len();
+        let mut data_offset = export_offset + export_count * 32;
+        let mut exports = Vec::new();
+        let mut objects = Vec::new();
+
+        // class, format & object name
+        let music = modules
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (0x81, 4, 5 + i as u8, m));
+        let sounds = sounds.iter().map(|s| (0x82, 8, 9, s));
+
+        for (class, format, name, data) in music.chain(sounds) {
+            let mut object = Vec::new();
+            if version < 40 {
+                object.extend_from_slice(&[0u8; 8]);
+            }
+            if version < 60 {
+                object.extend_from_slice(&[0u8; 16]);
+            }
+            object.push(0); // None
+            object.push(format);
+            if version >= 62 {
+                object.extend_from_slice(&[0u8; 4]);
+            }
+            object.extend(compact(data.len() as u32));
+            object.extend_from_slice(data);
+
+            exports.push(class); // negative import index
+            exports.push(0); // super
+            if version >= 60 {
+                exports.extend_from_slice(&[0u8; 4]);
+            }
+            exports.push(name);
+            exports.extend_from_slice(&[0u8; 4]);
+            exports.extend(compact(object.len() as u32));
+            exports.extend(compact(data_offset as u32));
+
+            data_offset += object.len();
+            objects.extend(object);
+        }
+
+        let mut buf = MAGIC_UPKG.to_vec();
+        buf.extend_from_slice(&version.to_le_bytes());
+        buf.extend_from_slice(&[0u8; 2 + 4]);
+        for n in [
+            names.len(),
+            name_offset,
+            export_count,
+            export_offset,
+            2,
+            import_offset,
+        ] {
+            buf.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        buf.resize(header_size, 0);
+        buf.extend(table);
+        buf.extend(imports);
+        buf.extend(exports);
+        buf.resize(export_offset + export_count * 32, 0);
+        buf.extend(objects);
+        buf
+    }
+
+    #[test]
+    fn multiple_music_exports() {
+        for version in [35, 60, 61, 69] {
+            let modules = [tiny_mod(b"first", 1), tiny_mod(b"second", 2)];
+            let module = parse_(&mut Cursor::new(package(version, &modules, &[]))).unwrap();
+
+            assert_eq!(module.name(), "SongA, SongB");
+            assert_eq!(module.samples().len(), 2);
+
+            for (i, smp) in module.samples().iter().enumerate() {
+                assert_eq!(smp.index_raw, i as u16);
+                assert_eq!(module.pcm(smp).unwrap().as_ref(), [i as u8 + 1; 16]);
+            }
+        }
+    }
+
+    #[test]
+    fn single_music_export() {
+        let module = parse_(&mut Cursor::new(package(61, &[tiny_mod(b"only", 3)], &[]))).unwrap();
+        assert_eq!(module.name(), "only");
+    }
+
+    fn wav(pcm: &[u8]) -> Vec<u8> {
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&[1, 0, 1, 0]); // pcm, mono
+        wav.extend_from_slice(&11025u32.to_le_bytes());
+        wav.extend_from_slice(&11025u32.to_le_bytes());
+        wav.extend_from_slice(&[1, 0, 8, 0]);
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(pcm.len() as u32).to_le_bytes());
+        wav.extend_from_slice(pcm);
+        wav
+    }
+
+    #[test]
+    fn sound_exports() {
+        let pcm: Vec<u8> = (0..32).collect();
+        let wav = wav(&pcm);
+
+        for version in [61, 69] {
+            let uax = package(version, &[tiny_mod(b"song", 3)], &[wav.clone()]);
+            let module = parse_(&mut Cursor::new(uax)).unwrap();
+            let samples = module.samples();
+
+            assert_eq!(samples.len(), 2);
+            assert_eq!(module.pcm(&samples[0]).unwrap().as_ref(), [3u8; 16]);
+            assert_eq!(samples[1].name(), "Kick");
+            assert_eq!(samples[1].rate, 11025);
+            assert_eq!(samples[1].index_raw, 1);
+            assert_eq!(module.pcm(&samples[1]).unwrap().as_ref(), pcm);
+        }
+    }
+
+    #[test]
+    fn oversized_sound() {
+        let wav = wav(&[0u8; 32]);
+        let mut uax = package(69, &[tiny_mod(b"song", 3)], &[wav.clone()]);
+
+        // claim the sound is as large as a two byte compact index allows
+        let size = uax.len() - wav.len() - 2;
+        assert_eq!(uax[size..size + 2], compact(wav.len() as u32));
+        uax[size..size + 2].copy_from_slice(&[0x7F, 0x7F]);
+
+        let module = parse_(&mut Cursor::new(uax)).unwrap();
+        assert_eq!(module.samples().len(), 1);
+    }
 }
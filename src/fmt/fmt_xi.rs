@@ -0,0 +1,155 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::fmt_xm::{delta_decode, read_sample_header, read_vibrato};
+use crate::info;
+use crate::interface::module::{GenericTracker, Module};
+use crate::interface::sample::{remove_invalid_samples, Sample};
+use crate::interface::Error;
+use crate::parser::{
+    bytes::magic_header,
+    io::{is_magic, ByteReader, ReadSeek},
+    string::read_str,
+};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+const NAME: &str = "Extended Instrument";
+
+const MAGIC_EXTENDED_INSTRUMENT: [u8; 21] = *b"Extended Instrument: ";
+const MAGIC_NUMBER: u8 = 0x1A;
+
+/// Offset to the instrument's auto vibrato.
+const XI_VIBRATO: u64 = 0x10C;
+
+/// Offset to the number of samples, the sample headers follow right after.
+const XI_SMP_NUM: u64 = 0x128;
+
+/// Fasttracker 2 Extended Instrument
+pub struct XI {
+    inner: GenericTracker,
+    samples: Box<[Sample]>,
+    source: Option<Box<Path>>,
+    title: Box<str>,
+}
+
+impl Module for XI {
+    fn name(&self) -> &str {
+        &self.title
+    }
+
+    fn format(&self) -> &str {
+        NAME
+    }
+
+    fn pcm(&self, smp: &Sample) -> Result<Cow<'_, [u8]>, Error> {
+        Ok(delta_decode(smp, self.inner.get_owned_slice(smp)?).into())
+    }
+
+    fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    fn load(data: &mut impl ReadSeek) -> Result<Box<dyn Module>, Error> {
+        info!("Loading Extended Instrument");
+        Ok(Box::new(parse_(data)?))
+    }
+
+    fn matches_format(buf: &[u8]) -> bool {
+        magic_header(&MAGIC_EXTENDED_INSTRUMENT, buf)
+    }
+
+    fn set_source(mut self: Box<Self>, path: PathBuf) -> Box<dyn Module> {
+        self.source = Some(path.into());
+        self
+    }
+
+    fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+}
+
+pub fn parse_(file: &mut impl ReadSeek) -> Result<XI, Error> {
+    if !is_magic(file, &MAGIC_EXTENDED_INSTRUMENT)? {
+        return Err(Error::invalid("Not a valid Extended Instrument"));
+    }
+
+    let title = read_str::<22>(file)?;
+
+    if !is_magic(file, &[MAGIC_NUMBER])? {
+        return Err(Error::invalid("Not a valid Extended Instrument"));
+    }
+
+    file.set_seek_pos(XI_VIBRATO)?;
+    let vibrato = read_vibrato(file)?;
+
+    file.set_seek_pos(XI_SMP_NUM)?;
+    let sample_number = file.read_u16_le()?;
+
+    let mut samples: Vec<Sample> = Vec::with_capacity(sample_number as usize);
+
+    for index_raw in 0..sample_number {
+        let mut sample = read_sample_header(file, index_raw)?;
+        sample.meta.vibrato = vibrato;
+        samples.push(sample);
+    }
+
+    // Sample data is stored after the headers, one after the other.
+    for smp in samples.iter_mut() {
+        smp.pointer = file.seek_position()? as u32;
+        file.skip_bytes(smp.length as i64)?;
+    }
+
+    samples.retain(|smp| smp.length != 0);
+    remove_invalid_samples(&mut samples, file.len())?;
+
+    let inner = file.load_to_memory()?.into();
+
+    Ok(XI {
+        title,
+        inner,
+        samples: samples.into(),
+        source: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{parse_, XI_SMP_NUM};
+    use crate::interface::sample::{Depth, Loop, LoopType};
+    use crate::interface::Module;
+
+    #[test]
+    fn samples() {
+        let mut xi = b"Extended Instrument: piano".to_vec();
+        xi.resize(43, 0);
+        xi.push(0x1A);
+        xi.resize(XI_SMP_NUM as usize, 0);
+        xi.extend_from_slice(&1u16.to_le_bytes());
+
+        // 16 bit sample with 4 frames, looping over the middle two, an octave up
+        xi.extend_from_slice(&8u32.to_le_bytes());
+        xi.extend_from_slice(&2u32.to_le_bytes());
+        xi.extend_from_slice(&4u32.to_le_bytes());
+        // volume, finetune, flags, panning, relative note, reserved
+        xi.extend_from_slice(&[64, 0, 0x11, 128, 12, 0]);
+        xi.resize(xi.len() + 22, 0);
+        xi.extend_from_slice(&[0u8; 8]);
+
+        let module = parse_(&mut Cursor::new(xi)).unwrap();
+        let smp = &module.samples()[0];
+
+        assert_eq!(module.name(), "piano");
+        assert_eq!(module.samples().len(), 1);
+        assert_eq!(smp.length, 8);
+        assert_eq!(smp.depth, Depth::I16);
+        assert_eq!(smp.looping, Loop::new(1, 3, LoopType::Forward));
+        assert_eq!(smp.rate, 16726);
+    }
+}
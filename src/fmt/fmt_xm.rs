@@ -6,18 +6,29 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::dsp::deltadecode::{delta_decode_u16, delta_decode_u8};
+use crate::dsp::vorbis::{decode_vorbis, vorbis_channels, MAGIC_OGGS};
 use crate::info;
 use crate::interface::module::{GenericTracker, Module};
-use crate::interface::sample::{remove_invalid_samples, Channel, Depth, Loop, LoopType, Sample, PcmType};
-use crate::interface::Error;
-use crate::parser::io::{non_consume, read_exact_const};
+use crate::interface::sample::{
+    remove_invalid_samples, Channel, Depth, Loop, LoopType, PcmType, Sample, SampleMeta, Vibrato,
+    Waveform,
+};
+use crate::interface::instrument::{
+    Envelope, EnvelopeLoop, EnvelopePoint, Instrument, Keymap, KeymapEntry, NewNoteAction,
+};
+use crate::interface::pattern::{Effects, Note, Pattern};
+use crate::interface::song::{FrequencyMode, Song};
+use crate::interface::usage::{sample_usage, Instruments, SampleUsage};
+use crate::interface::{Error, TrackerInfo};
+use crate::parser::io::{is_magic_non_consume, non_consume, read_exact_const};
 use crate::parser::{
     bitflag::BitFlag,
     bytes::magic_header,
     io::{is_magic, ByteReader, ReadSeek},
-    string::read_str,
+    string::{read_message, read_str},
 };
 use std::borrow::Cow;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
 const NAME: &str = "Extended Module";
@@ -27,6 +38,12 @@ const MAGIC_MOD_PLUGIN_PACKED: [u8; 20] = *b"MOD Plugin packed   ";
 const MAGIC_NUMBER: u8 = 0x1A;
 const MINIMUM_VERSION: u16 = 0x0104;
 
+/// OpenMPT stores the song message in a chunk after the sample data
+const MAGIC_TEXT: [u8; 4] = *b"text";
+
+/// Bit 0 of the song flags means the linear frequency table is used
+const FLAG_LINEAR: u16 = 1;
+
 const FLAG_BITS: u8 = 1 << 4;
 const FLAG_STEREO: u8 = 1 << 5;
 
@@ -36,6 +53,11 @@ pub struct XM {
     samples: Box<[Sample]>,
     source: Option<Box<Path>>,
     title: Box<str>,
+    comments: Box<str>,
+    tracker: Option<TrackerInfo>,
+    patterns: Box<[Pattern]>,
+    song: Song,
+    instruments: Box<[Instrument]>,
 }
 
 impl Module for XM {
@@ -47,8 +69,40 @@ impl Module for XM {
         NAME
     }
 
+    fn comments(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.comments)
+    }
+
+    fn created_with(&self) -> Option<TrackerInfo> {
+        self.tracker.clone()
+    }
+
+    fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    fn song(&self) -> Option<&Song> {
+        Some(&self.song)
+    }
+
+    fn instruments(&self) -> &[Instrument] {
+        &self.instruments
+    }
+
+    fn sample_usage(&self) -> Option<Box<[SampleUsage]>> {
+        Some(sample_usage(
+            &self.samples,
+            &self.patterns,
+            &self.song.orders,
+            Instruments::Keymaps(&self.instruments),
+        ))
+    }
+
     fn pcm(&self, smp: &Sample) -> Result<Cow<[u8]>, Error> {
-        Ok(delta_decode(smp, self.inner.get_owned_slice(smp)?).into())
+        match smp.pcm_type {
+            PcmType::VORBIS => Ok(decode_vorbis(self.inner.get_slice_trailing(smp)?, smp)?.into()),
+            _ => Ok(delta_decode(smp, self.inner.get_owned_slice(smp)?).into()),
+        }
     }
 
     fn samples(&self) -> &[Sample] {
@@ -114,7 +168,7 @@ pub fn parse_(file: &mut impl ReadSeek) -> Result<XM, Error> {
         return Err(Error::invalid("Not a valid Extended Module"));
     }
 
-    file.skip_bytes(20)?; // Name of the tracking software that made the module.
+    let tracker_name = read_str::<20>(file)?; // Name of the tracking software that made the module.
 
     let version = file.read_u16_le()?;
     if version < MINIMUM_VERSION {
@@ -122,7 +176,10 @@ pub fn parse_(file: &mut impl ReadSeek) -> Result<XM, Error> {
     }
 
     let header_size = file.read_u32_le()?;
-    file.skip_bytes(6)?; // song length, song restart position, channels
+    let song_length = file.read_u16_le()?.min(256);
+    let restart = file.read_u16_le()?;
+
+    let channels = file.read_u16_le()?.min(u8::MAX as u16) as u8;
 
     let patnum = file.read_u16_le()?;
     let insnum = file.read_u16_le()?;
@@ -136,38 +193,169 @@ pub fn parse_(file: &mut impl ReadSeek) -> Result<XM, Error> {
         ));
     }
 
-    // skip patterns
-    file.set_seek_pos(60 + header_size as u64)?;
-
-    for _ in 0..patnum {
-        let _ = file.read_u32_le()?;
-        file.skip_bytes(3)?; // pattern length, packing type, number of rows in pattern
+    let flags = file.read_u16_le()?;
+    let speed = file.read_u16_le()?;
+    let tempo = file.read_u16_le()?;
+    let orders = file.read_bytes(song_length as usize)?;
 
-        let data_size = file.read_u16_le()? as i64;
-        file.skip_bytes(data_size)?;
-        // if data_size > 9 {
-        //     file.skip_bytes(header_size as i64 -9)?;
-        // }
-    }
+    file.set_seek_pos(60 + header_size as u64)?;
+    let patterns = read_patterns(file, patnum, channels)?;
 
-    let mut samples = build(file, insnum)?;
+    let (mut samples, instruments) = build(file, insnum)?;
+    let comments = read_text_chunk(file)?;
     remove_invalid_samples(&mut samples, file.len())?;
 
     let inner = file.load_to_memory()?.into();
 
     Ok(XM {
         title,
+        comments,
+        tracker: tracker_info(&tracker_name, version),
+        patterns: patterns.into(),
+        song: Song {
+            orders: orders.into(),
+            restart: match restart < song_length {
+                true => restart as u8,
+                false => 0,
+            },
+            speed: speed.min(u8::MAX as u16) as u8,
+            tempo: tempo.min(u8::MAX as u16) as u8,
+            global_volume: 128,
+            channels,
+            panning: vec![128; channels as usize].into(),
+            frequencies: match flags & FLAG_LINEAR != 0 {
+                true => FrequencyMode::Linear,
+                false => FrequencyMode::Amiga,
+            },
+            effects: Effects::XM,
+        },
+        instruments: instruments.into(),
         inner,
         samples: samples.into(),
         source: None,
     })
 }
 
+/// If the first byte of a cell has bit 7 set, it says which parts of the cell follow.
+/// Otherwise it's a note, and the rest of the cell follows.
+/// Read and unpack every pattern.
+///
+/// A truncated pattern keeps the rows that are there,
+/// it shouldn't stop us from extracting samples.
+fn read_patterns(
+    file: &mut impl ReadSeek,
+    patnum: u16,
+    channels: u8,
+) -> Result<Vec<Pattern>, Error> {
+    let mut patterns: Vec<Pattern> = Vec::with_capacity(patnum as usize);
+
+    for _ in 0..patnum {
+        let _ = file.read_u32_le()?; // pattern header length
+        file.skip_bytes(1)?; // packing type
+        let rows = file.read_u16_le()?;
+
+        let data_size = file.read_u16_le()?;
+        let start = file.seek_position()?;
+
+        let mut data = Vec::with_capacity(data_size as usize);
+        file.by_ref().take(data_size as u64).read_to_end(&mut data)?;
+        patterns.push(unpack_pattern(&data, rows, channels));
+
+        file.set_seek_pos(start + data_size as u64)?;
+    }
+
+    Ok(patterns)
+}
+
+pub(crate) fn unpack_pattern(buf: &[u8], rows: u16, channels: u8) -> Pattern {
+    const NOTE_OFF: u8 = 97;
+
+    let mut pattern = Pattern::new(rows, channels);
+    let mut data = buf.iter().copied();
+    let next = |data: &mut dyn Iterator<Item = u8>| data.next().unwrap_or_default();
+
+    'rows: for row in 0..rows {
+        for channel in 0..channels {
+            // Stop if the pattern data is truncated
+            let Some(first) = data.next() else {
+                break 'rows;
+            };
+
+            let (flags, note) = match first & 0x80 != 0 {
+                true => (first, (first & 1 != 0).then(|| next(&mut data))),
+                false => (0xFF, Some(first)),
+            };
+
+            let cell = pattern.cell_mut(row, channel).unwrap();
+
+            // C-4 plays a sample at its sample rate
+            cell.note = match note {
+                Some(note @ 1..=96) => Note::On(note - 1 + 12),
+                Some(NOTE_OFF) => Note::Off,
+                _ => Note::None,
+            };
+            if flags & 2 != 0 {
+                cell.instrument = next(&mut data);
+            }
+            if flags & 4 != 0 {
+                cell.volume = Some(next(&mut data)).filter(|volume| *volume != 0);
+            }
+            if flags & 8 != 0 {
+                cell.effect = next(&mut data);
+            }
+            if flags & 16 != 0 {
+                cell.param = next(&mut data);
+            }
+        }
+    }
+
+    pattern
+}
+
+/// Most trackers write their name and version,
+/// but FastTracker 2.08 and later always write "FastTracker v2.00".
+fn tracker_info(tracker: &str, version: u16) -> Option<TrackerInfo> {
+    match tracker.trim_end() {
+        "FastTracker v2.00" if version == MINIMUM_VERSION => {
+            Some(TrackerInfo::new("FastTracker", Some("2.08".into())))
+        }
+        name => TrackerInfo::parse(name),
+    }
+}
+
+/// Read the song message from the current position, if there is one.
+fn read_text_chunk(file: &mut impl ReadSeek) -> Result<Box<str>, Error> {
+    if !matches!(is_magic_non_consume(file, &MAGIC_TEXT), Ok(true)) {
+        return Ok(Default::default());
+    }
+
+    file.skip_bytes(MAGIC_TEXT.len() as i64)?;
+    let length = file.read_u32_le()? as u64;
+    let remaining = file.len().unwrap_or_default().saturating_sub(file.seek_position()?);
+    let message = file.read_bytes(length.min(remaining) as usize)?;
+
+    Ok(read_message(&message))
+}
+
 const XM_INS_SIZE: u32 = 263;
 const XM_SMP_SIZE: u64 = 40;
-
-fn build(file: &mut impl ReadSeek, ins_num: u16) -> Result<Vec<Sample>, Error> {
+/// Offset of the auto vibrato in an instrument header
+const XM_INS_VIBRATO: u32 = 235;
+/// Offset of the sample number for each note in an instrument header.
+///
+/// The rest of the header is only stored if the instrument has samples.
+const XM_INS_KEYMAP: u32 = 33;
+/// Size of the header from the keymap up to (and including) the fadeout
+const XM_INS_EXTRA: usize = 208;
+
+const ENV_ENABLED: u8 = 1;
+const ENV_SUSTAIN: u8 = 1 << 1;
+const ENV_LOOP: u8 = 1 << 2;
+
+/// Returns the samples, and the instruments they belong to
+fn build(file: &mut impl ReadSeek, ins_num: u16) -> Result<(Vec<Sample>, Vec<Instrument>), Error> {
     let mut samples: Vec<Sample> = Vec::new();
+    let mut instruments: Vec<Instrument> = Vec::with_capacity(ins_num as usize);
     let mut staging_samples: Vec<Sample> = Vec::new();
     let mut total_samples: u16 = 0;
     let file_size = file.len().expect("size of reader");
@@ -176,7 +364,7 @@ fn build(file: &mut impl ReadSeek, ins_num: u16) -> Result<Vec<Sample>, Error> {
         let offset = file.seek_position()?;
 
         let mut header_size = file.read_u32_le()?;
-        file.skip_bytes(22)?; // instrument name
+        let name = read_str::<22>(file)?;
         file.skip_bytes(1)?; // instrument type
 
         let sample_number = file.read_u16_le()?;
@@ -185,77 +373,240 @@ fn build(file: &mut impl ReadSeek, ins_num: u16) -> Result<Vec<Sample>, Error> {
             header_size = XM_INS_SIZE;
         }
 
+        let vibrato = match header_size >= XM_INS_VIBRATO + 4 {
+            true => non_consume(file, |file| {
+                file.set_seek_pos(offset + XM_INS_VIBRATO as u64)?;
+                Ok(read_vibrato(file))
+            })??,
+            false => Vibrato::default(),
+        };
+
+        let extra = match sample_number > 0 && header_size > XM_INS_KEYMAP {
+            true => non_consume(file, |file| {
+                file.set_seek_pos(offset + XM_INS_KEYMAP as u64)?;
+                file.read_bytes((header_size - XM_INS_KEYMAP) as usize)
+            })?,
+            false => Vec::new(),
+        };
+        instruments.push(xm_instrument(name, extra, total_samples, sample_number));
+
         let total_smp_hdr_size = XM_SMP_SIZE * sample_number as u64;
         let start_smp_hdr = header_size as u64 + offset;
 
         file.set_seek_pos(start_smp_hdr)?; // skip to sample headers
 
         for _ in 0..sample_number {
-            let length = file.read_u32_le()?;
+            let length = non_consume(file, |file| file.read_u32_le())?;
 
             // Break out of loop if it will lead to an eof error
             if (start_smp_hdr + total_smp_hdr_size + length as u64) > file_size {
                 break 'ins;
             }
 
-            let loop_start = file.read_u32_le()?;
-            let loop_length = file.read_u32_le()?;
-            file.skip_bytes(1)?; // volume
-
-            let finetune = file.read_u8()? as i8;
-            let flag = file.read_u8()?;
-            file.skip_bytes(1)?; // panning,
-
-            let notenum = file.read_u8()? as i8;
-            file.skip_bytes(1)?; // reserved
-
-            let name = read_str::<22>(file)?;
-
-            let period: f32 = 7680.0 - ((48.0 + notenum as f32) * 64.0) - (finetune as f32 / 2.0);
-            let rate: u32 = (8363.0 * 2.0_f32.powf((4608.0 - period) / 768.0)) as u32;
-
-            let depth = Depth::new(!flag.contains(FLAG_BITS), true, true);
-            let channel = Channel::new(flag.contains(FLAG_STEREO), false);
-
-            let loop_start = loop_start / (depth.bytes() as u32 * channel.channels() as u32);
-            let loop_length = loop_length / (depth.bytes() as u32 * channel.channels() as u32);
-            let loop_end = loop_start.checked_add(loop_length).unwrap_or(0);
+            let mut sample = read_sample_header(file, total_samples)?;
+            sample.meta.vibrato = vibrato;
 
-            let loop_kind = match flag & 0x3 {
-                0 => LoopType::Off,
-                1 => LoopType::Forward,
-                2 => LoopType::PingPong,
-                3 => LoopType::PingPong,
-                _ => LoopType::Off,
-            };
-
-            if length != 0 {
-                staging_samples.push(Sample {
-                    filename: None,
-                    name,
-                    length,
-                    rate,
-                    pointer: 0,
-                    depth,
-                    channel,
-                    index_raw: total_samples,
-                    pcm_type: PcmType::DELTA,
-                    looping: Loop::new(loop_start, loop_end, loop_kind),
-                });
+            if sample.length != 0 {
+                staging_samples.push(sample);
             }
             total_samples += 1;
         }
 
         for smp in staging_samples.iter_mut() {
             let pointer = file.seek_position()? as u32;
+            let stored_length = smp.length;
             smp.pointer = pointer;
-            file.skip_bytes(smp.length as i64)?;
+            check_oggmod(file, smp)?;
+            file.skip_bytes(stored_length as i64)?;
         }
 
         samples.append(&mut staging_samples);
     }
 
-    Ok(samples)
+    Ok((samples, instruments))
+}
+
+/// Build an instrument from the part of its header after the sample header size.
+///
+/// Missing fields are zeroed, but a missing keymap doesn't map to anything.
+fn xm_instrument(name: Box<str>, mut extra: Vec<u8>, first: u16, sample_number: u16) -> Instrument {
+    let keymap = xm_keymap(extra.get(..96).unwrap_or_default(), first, sample_number);
+    extra.resize(XM_INS_EXTRA, 0);
+
+    Instrument {
+        name,
+        keymap,
+        volume_envelope: xm_envelope(
+            &extra[96..144],
+            [extra[192], extra[194], extra[195], extra[196]],
+            extra[200],
+            0,
+        ),
+        panning_envelope: xm_envelope(
+            &extra[144..192],
+            [extra[193], extra[197], extra[198], extra[199]],
+            extra[201],
+            32,
+        ),
+        fadeout: u16::from_le_bytes([extra[206], extra[207]]).min(0xFFF) * 2,
+        nna: NewNoteAction::Cut,
+        ..Default::default()
+    }
+}
+
+/// ``params`` are the number of points, sustain point, loop start and loop end.
+///
+/// Values are stored from 0 to 64, ``center`` is subtracted from them.
+fn xm_envelope(points: &[u8], params: [u8; 4], flags: u8, center: i8) -> Envelope {
+    let [count, sustain, start, end] = params;
+
+    let points: Box<[EnvelopePoint]> = points
+        .chunks_exact(4)
+        .take(count as usize)
+        .map(|point| EnvelopePoint {
+            tick: u16::from_le_bytes([point[0], point[1]]),
+            value: point[2].min(64) as i8 - center,
+        })
+        .collect();
+
+    Envelope {
+        enabled: flags & ENV_ENABLED != 0,
+        sustain: (flags & ENV_SUSTAIN != 0)
+            .then(|| EnvelopeLoop::new(sustain, sustain, points.len()))
+            .flatten(),
+        looping: (flags & ENV_LOOP != 0)
+            .then(|| EnvelopeLoop::new(start, end, points.len()))
+            .flatten(),
+        points,
+    }
+}
+
+/// XM instruments map notes C-0 to B-7 to one of their samples, the note is unchanged.
+///
+/// ``first`` is the raw index of the instrument's first sample.
+fn xm_keymap(map: &[u8], first: u16, sample_number: u16) -> Keymap {
+    Keymap::from_fn(|note| {
+        let sample = (note as usize)
+            .checked_sub(12)
+            .and_then(|i| map.get(i))
+            .filter(|smp| (**smp as u16) < sample_number)
+            .map(|smp| first + *smp as u16);
+
+        KeymapEntry { sample, note }
+    })
+}
+
+/// OggMod (OXM) replaces the sample data with its original length, followed by an Ogg Vorbis stream.
+///
+/// The sample header's length is the size of the stored data,
+/// so the sample is adjusted to describe the decoded 16 bit pcm instead.
+fn check_oggmod(file: &mut impl ReadSeek, smp: &mut Sample) -> Result<(), Error> {
+    // Enough to contain the first Ogg page
+    const PEEK: u32 = 128;
+
+    if smp.length < 8 {
+        return Ok(());
+    }
+
+    // A truncated sample isn't an error here, it's removed later on
+    let Ok(head) = non_consume(file, |file| file.read_bytes(smp.length.min(PEEK) as usize)) else {
+        return Ok(());
+    };
+
+    if head[4..8] != MAGIC_OGGS {
+        return Ok(());
+    }
+
+    let original_length = u32::from_le_bytes(head[..4].try_into().unwrap());
+    let frames = original_length / (smp.bytes() as u32 * smp.channels() as u32);
+    let channels = vorbis_channels(&head[4..]).unwrap_or(smp.channels());
+
+    smp.pointer += 4;
+    smp.pcm_type = PcmType::VORBIS;
+    smp.depth = Depth::I16;
+    smp.channel = Channel::new(channels == 2, false);
+    smp.length = frames * 2 * smp.channels() as u32;
+
+    Ok(())
+}
+
+/// Read a 40 byte sample header.
+///
+/// The pointer is left as zero since the sample data is stored after all of the headers.
+pub(crate) fn read_sample_header(
+    file: &mut impl ReadSeek,
+    index_raw: u16,
+) -> Result<Sample, Error> {
+    let length = file.read_u32_le()?;
+    let loop_start = file.read_u32_le()?;
+    let loop_length = file.read_u32_le()?;
+    let volume = file.read_u8()?;
+
+    let finetune = file.read_u8()? as i8;
+    let flag = file.read_u8()?;
+    let panning = file.read_u8()?;
+
+    let notenum = file.read_u8()? as i8;
+    file.skip_bytes(1)?; // reserved
+
+    let name = read_str::<22>(file)?;
+
+    let rate = xm_rate(notenum, finetune);
+
+    let depth = Depth::new(!flag.contains(FLAG_BITS), true, true);
+    let channel = Channel::new(flag.contains(FLAG_STEREO), false);
+
+    let loop_start = loop_start / (depth.bytes() as u32 * channel.channels() as u32);
+    let loop_length = loop_length / (depth.bytes() as u32 * channel.channels() as u32);
+    let loop_end = loop_start.checked_add(loop_length).unwrap_or(0);
+
+    let loop_kind = match flag & 0x3 {
+        0 => LoopType::Off,
+        1 => LoopType::Forward,
+        2 => LoopType::PingPong,
+        3 => LoopType::PingPong,
+        _ => LoopType::Off,
+    };
+
+    Ok(Sample {
+        filename: None,
+        name,
+        length,
+        rate,
+        pointer: 0,
+        depth,
+        channel,
+        index_raw,
+        pcm_type: PcmType::DELTA,
+        looping: Loop::new(loop_start, loop_end, loop_kind),
+        sustain: None,
+        mpt: None,
+        meta: SampleMeta {
+            volume: volume.min(64),
+            panning: Some(panning),
+            finetune,
+            relative_note: notenum,
+            ..Default::default()
+        },
+    })
+}
+
+/// Read the auto vibrato stored in an instrument header
+pub(crate) fn read_vibrato(file: &mut impl ReadSeek) -> Result<Vibrato, Error> {
+    let [waveform, sweep, depth, speed] = read_exact_const::<4>(file)?;
+
+    Ok(Vibrato {
+        waveform: Waveform::from_xm(waveform),
+        speed,
+        depth,
+        sweep,
+    })
+}
+
+/// Calculate the sample rate from the relative note & finetune
+pub(crate) fn xm_rate(relative_note: i8, finetune: i8) -> u32 {
+    let period: f32 = 7680.0 - ((48.0 + relative_note as f32) * 64.0) - (finetune as f32 / 2.0);
+    (8363.0 * 2.0_f32.powf((4608.0 - period) / 768.0)) as u32
 }
 
 fn check_mod_plugin_packed(file: &mut impl ReadSeek) -> Result<(), Error> {
@@ -277,11 +628,12 @@ mod test {
     use std::{fs::File, io::Cursor};
 
     use crate::{
+        interface::sample::{Depth, PcmType},
         interface::{ripper::Ripper, Module},
         parser::io::{ByteReader, Container},
     };
 
-    use super::parse_;
+    use super::{parse_, tracker_info, unpack_pattern, xm_instrument, xm_keymap, MAGIC_TEXT};
 
     #[test]
     fn validate() {
@@ -302,4 +654,245 @@ mod test {
         // (module as dyn Module).samples()
         // ripper.rip_to_dir("./xm/", &module).unwrap();
     }
+
+    fn sample_header(length: u32, name: &[u8]) -> Vec<u8> {
+        let mut header = length.to_le_bytes().to_vec();
+        header.resize(18, 0); // loop, volume, finetune, flags, panning, relative note, reserved
+        header.extend_from_slice(name);
+        header.resize(40, 0);
+        header
+    }
+
+    /// A module with a single instrument, up to its sample headers
+    fn one_instrument(sample_number: u16) -> Vec<u8> {
+        let mut xm = b"Extended Module: oggmod".to_vec();
+        xm.resize(37, 0);
+        xm.push(0x1A);
+        xm.resize(58, 0);
+        xm.extend_from_slice(&0x0104u16.to_le_bytes());
+        xm.extend_from_slice(&276u32.to_le_bytes());
+        xm.extend_from_slice(&[0, 0, 0, 0, 2, 0, 0, 0, 1, 0]); // 0 patterns, 1 instrument
+        xm.resize(60 + 276, 0);
+
+        xm.extend_from_slice(&263u32.to_le_bytes());
+        xm.resize(xm.len() + 23, 0);
+        xm.extend_from_slice(&sample_number.to_le_bytes());
+        xm.resize(60 + 276 + 263, 0);
+        xm
+    }
+
+    #[test]
+    fn oggmod() {
+        // Ogg page containing a mono vorbis identification header
+        let mut ogg = b"OggS".to_vec();
+        ogg.resize(26, 0);
+        ogg.extend_from_slice(&[1, 30]);
+        ogg.extend_from_slice(b"\x01vorbis\0\0\0\0\x01");
+        ogg.resize(ogg.len() + 19, 0);
+
+        let mut vorbis = 100u32.to_le_bytes().to_vec();
+        vorbis.extend_from_slice(&ogg);
+
+        let mut xm = one_instrument(2);
+        xm.extend(sample_header(vorbis.len() as u32, b"vorbis"));
+        xm.extend(sample_header(4, b"pcm"));
+        let data_start = xm.len() as u32;
+        xm.extend_from_slice(&vorbis);
+        xm.extend_from_slice(&[1, 1, 1, 1]);
+
+        let module = parse_(&mut Cursor::new(xm)).unwrap();
+        let samples = module.samples();
+
+        assert_eq!(samples[0].pcm_type, PcmType::VORBIS);
+        assert_eq!(samples[0].pointer, data_start + 4);
+        assert_eq!(samples[0].length, 200);
+        assert_eq!(samples[0].depth, Depth::I16);
+        assert!(!samples[0].is_stereo());
+
+        assert_eq!(samples[1].pointer, data_start + vorbis.len() as u32);
+        assert_eq!(module.pcm(&samples[1]).unwrap().as_ref(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn truncated_sample() {
+        let mut xm = one_instrument(2);
+        xm.extend(sample_header(200, b"pcm"));
+        xm.extend(sample_header(100, b"truncated"));
+        xm.resize(xm.len() + 200 + 6, 1);
+
+        // The last sample is cut short, so it's dropped rather than probed for Ogg data
+        let module = parse_(&mut Cursor::new(xm)).unwrap();
+        assert_eq!(module.samples().len(), 1);
+        assert_eq!(&*module.samples()[0].name, "pcm");
+    }
+
+    #[test]
+    fn text_chunk() {
+        let mut xm = b"Extended Module: text".to_vec();
+        xm.resize(37, 0);
+        xm.push(0x1A);
+        xm.resize(58, 0);
+        xm.extend_from_slice(&0x0104u16.to_le_bytes());
+        xm.extend_from_slice(&20u32.to_le_bytes());
+        xm.extend_from_slice(&[0, 0, 0, 0, 2, 0, 0, 0, 1, 0]); // 0 patterns, 1 instrument
+        xm.resize(60 + 20, 0);
+
+        xm.extend_from_slice(&263u32.to_le_bytes());
+        xm.resize(xm.len() + 23, 0);
+        xm.extend_from_slice(&1u16.to_le_bytes());
+        xm.resize(60 + 20 + 263, 0);
+
+        xm.extend(sample_header(4, b"pcm"));
+        xm.extend_from_slice(&[1, 1, 1, 1]);
+
+        xm.extend_from_slice(&MAGIC_TEXT);
+        xm.extend_from_slice(&11u32.to_le_bytes());
+        xm.extend_from_slice(b"hello\rworld");
+
+        let module = parse_(&mut Cursor::new(xm)).unwrap();
+        assert_eq!(module.comments(), "hello\nworld");
+    }
+
+    #[test]
+    fn song_header() {
+        use crate::interface::song::FrequencyMode;
+
+        let mut xm = b"Extended Module: song".to_vec();
+        xm.resize(37, 0);
+        xm.push(0x1A);
+        xm.resize(58, 0);
+        xm.extend_from_slice(&0x0104u16.to_le_bytes());
+        xm.extend_from_slice(&276u32.to_le_bytes());
+        #[rustfmt::skip]
+        xm.extend_from_slice(&[
+            3, 0,   // song length
+            1, 0,   // restart position
+            4, 0,   // channels
+            0, 0,   // patterns
+            1, 0,   // instruments
+            1, 0,   // linear frequencies
+            3, 0,   // speed
+            140, 0, // tempo
+            0, 1, 0 // orders
+        ]);
+        xm.resize(60 + 276, 0);
+
+        xm.extend_from_slice(&263u32.to_le_bytes());
+        xm.resize(xm.len() + 23, 0);
+        xm.extend_from_slice(&1u16.to_le_bytes());
+        xm.resize(60 + 276 + 263, 0);
+        xm.extend(sample_header(4, b"pcm"));
+        xm.extend_from_slice(&[1, 1, 1, 1]);
+
+        let module = parse_(&mut Cursor::new(xm)).unwrap();
+        let song = module.song().unwrap();
+
+        assert_eq!(song.orders.as_ref(), [0, 1, 0]);
+        assert_eq!(song.restart, 1);
+        assert_eq!((song.speed, song.tempo), (3, 140));
+        assert_eq!(song.global_volume, 128);
+        assert_eq!(song.channels, 4);
+        assert_eq!(song.frequencies, FrequencyMode::Linear);
+    }
+
+    #[test]
+    fn tracker_names() {
+        let version = |name, version| tracker_info(name, version).map(|t| t.to_string());
+
+        assert_eq!(version("FastTracker v2.00   ", 0x0104).as_deref(), Some("FastTracker 2.08"));
+        assert_eq!(version("OpenMPT 1.28.01.00", 0x0104).as_deref(), Some("OpenMPT 1.28.01.00"));
+        assert_eq!(version("", 0x0104), None);
+    }
+
+    #[test]
+    fn packed_pattern() {
+        use crate::interface::pattern::Note;
+
+        #[rustfmt::skip]
+        let packed = [
+            49, 1, 0x40, 0x0C, 0x20, // C-4, instrument, volume, effect
+            0x80,                    // empty
+            0x81, 97,                // note off
+            0x82, 2,                 // instrument
+        ];
+        let pattern = unpack_pattern(&packed, 3, 2);
+
+        let cell = pattern.row(0)[0];
+        assert_eq!(cell.note, Note::On(60));
+        assert_eq!(cell.instrument, 1);
+        assert_eq!(cell.volume, Some(0x40));
+        assert_eq!((cell.effect, cell.param), (0x0C, 0x20));
+        assert!(pattern.row(0)[1].is_empty());
+
+        assert_eq!(pattern.row(1)[0].note, Note::Off);
+        assert_eq!(pattern.row(1)[1].instrument, 2);
+        assert_eq!(pattern.row(1)[1].note, Note::None);
+        assert!(pattern.row(2).iter().all(|cell| cell.is_empty()));
+    }
+
+    #[test]
+    fn truncated_pattern() {
+        use super::read_patterns;
+        use crate::interface::pattern::Note;
+
+        let mut header = 9u32.to_le_bytes().to_vec();
+        header.push(0); // packing type
+        header.extend_from_slice(&2u16.to_le_bytes()); // rows
+        header.extend_from_slice(&64u16.to_le_bytes()); // data size
+
+        // Only the first row of the pattern made it
+        let data = [header, vec![0x81, 49, 0x81, 50]].concat();
+        let patterns = read_patterns(&mut Cursor::new(data), 1, 2).unwrap();
+
+        assert_eq!(patterns[0].row(0)[0].note, Note::On(60));
+        assert_eq!(patterns[0].row(0)[1].note, Note::On(61));
+        assert!(patterns[0].row(1).iter().all(|cell| cell.is_empty()));
+    }
+
+    #[test]
+    fn keymap() {
+        // Notes from C-4 play the second sample
+        let mut map = [0u8; 96];
+        map[48..].fill(1);
+        map[95] = 5; // out of range
+
+        let keymap = xm_keymap(&map, 10, 2);
+
+        assert_eq!(keymap.sample(59), Some((10, 59)));
+        assert_eq!(keymap.sample(60), Some((11, 60)));
+        assert_eq!(keymap.sample(107), None);
+        assert_eq!(keymap.sample(110), None);
+        assert_eq!(keymap.sample(11), None);
+        assert_eq!(xm_keymap(&[], 0, 0).sample(60), None);
+    }
+
+    #[test]
+    fn instrument() {
+        use crate::interface::instrument::{EnvelopeLoop, EnvelopePoint};
+
+        let mut extra = vec![0u8; 208];
+        extra[144..152].copy_from_slice(&[0, 0, 0, 0, 20, 0, 64, 0]); // panning points
+        extra[193] = 2; // panning points
+        extra[197..200].copy_from_slice(&[1, 0, 1]); // sustain, loop start, loop end
+        extra[201] = 0b111; // enabled, sustain, loop
+        extra[206..208].copy_from_slice(&0x100u16.to_le_bytes());
+
+        let instrument = xm_instrument("pad".into(), extra, 4, 1);
+
+        assert_eq!(instrument.name.as_ref(), "pad");
+        assert_eq!(instrument.keymap.sample(60), Some((4, 60)));
+        assert_eq!(instrument.fadeout, 0x200);
+        assert!(!instrument.volume_envelope.enabled);
+
+        let panning = &instrument.panning_envelope;
+        assert!(panning.enabled);
+        assert_eq!(panning.points[0], EnvelopePoint { tick: 0, value: -32 });
+        assert_eq!(panning.points[1], EnvelopePoint { tick: 20, value: 32 });
+        assert_eq!(panning.sustain, Some(EnvelopeLoop { start: 1, end: 1 }));
+        assert_eq!(panning.looping, Some(EnvelopeLoop { start: 0, end: 1 }));
+
+        // Truncated header
+        let instrument = xm_instrument("".into(), vec![0; 10], 0, 1);
+        assert_eq!(instrument.keymap.sample(60), None);
+    }
 }
@@ -0,0 +1,398 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::borrow::Cow;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::common::MAX_SIZE_BYTES;
+use crate::dsp::flac::decode_flac;
+use crate::fmt::loader::load_nested;
+use crate::interface::module::{GenericTracker, Module};
+use crate::interface::sample::{Channel, Depth, Loop, LoopType, PcmType, Sample};
+use crate::interface::Error;
+use crate::parser::{
+    bytes::magic_header,
+    io::{is_magic, ByteReader, ReadSeek},
+    zip::{read_entries, read_entry, ZipEntry, MAGIC_LOCAL_FILE},
+};
+use crate::{info, warn};
+
+const NAME: &str = "Zip Archive";
+
+/// Extensions of modules stored inside OpenMPT's zipped formats (MDZ, S3Z, XMZ, ITZ)
+const MODULE_EXTENSIONS: &[&str] = &["mod", "s3m", "xm", "it", "mptm", "umx"];
+
+const MAGIC_RIFF: [u8; 4] = *b"RIFF";
+const MAGIC_WAVE: [u8; 4] = *b"WAVE";
+const MAGIC_FLAC: [u8; 4] = *b"fLaC";
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Zip archive
+///
+/// Zipped modules (MDZ, S3Z, XMZ, ITZ) are loaded as the module they contain.
+/// Renoise songs and instruments (XRNS, XRNI) are loaded from their WAV & FLAC samples.
+pub struct ZIP {
+    inner: GenericTracker,
+    samples: Box<[Sample]>,
+    title: Box<str>,
+    source: Option<Box<Path>>,
+}
+
+impl Module for ZIP {
+    fn name(&self) -> &str {
+        &self.title
+    }
+
+    fn format(&self) -> &str {
+        NAME
+    }
+
+    fn pcm(&self, smp: &Sample) -> Result<Cow<'_, [u8]>, Error> {
+        match smp.pcm_type {
+            PcmType::FLAC => Ok(decode_flac(self.inner.get_slice_trailing(smp)?, smp)?.into()),
+            _ => Ok(self.inner.get_slice(smp)?.into()),
+        }
+    }
+
+    fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    fn load(data: &mut impl ReadSeek) -> Result<Box<dyn Module>, Error> {
+        parse_(data, 0)
+    }
+
+    fn matches_format(buf: &[u8]) -> bool {
+        magic_header(&MAGIC_LOCAL_FILE, buf)
+    }
+
+    fn set_source(mut self: Box<Self>, path: PathBuf) -> Box<dyn Module> {
+        self.source = Some(path.into());
+        self
+    }
+
+    fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+}
+
+/// `depth` is how many containers this archive was extracted from.
+pub fn parse_(file: &mut impl ReadSeek, depth: u8) -> Result<Box<dyn Module>, Error> {
+    info!("Loading zip archive");
+    let entries = read_entries(file)?;
+
+    let is_module = |entry: &&ZipEntry| {
+        !entry.is_dir()
+            && entry
+                .extension()
+                .is_some_and(|ext| MODULE_EXTENSIONS.contains(&ext.as_str()))
+    };
+
+    let mut modules = entries.iter().filter(is_module);
+
+    if let Some(entry) = modules.next() {
+        if modules.next().is_some() {
+            warn!(
+                "Zip archive contains more than one module, only loading {}",
+                entry.name
+            );
+        }
+        info!("Loading zipped module: {}", entry.name);
+        let data = read_entry(file, entry, MAX_SIZE_BYTES as usize)?;
+        return load_nested(&mut Cursor::new(data), depth + 1);
+    }
+
+    Ok(Box::new(parse_renoise(file, &entries)?))
+}
+
+/// Renoise stores its samples as individual WAV or FLAC files inside a zip archive.
+fn parse_renoise(file: &mut impl ReadSeek, entries: &[ZipEntry]) -> Result<ZIP, Error> {
+    let mut buf: Vec<u8> = Vec::new();
+    let mut samples: Vec<Sample> = Vec::new();
+    let mut title: Box<str> = "".into();
+
+    for entry in entries.iter().filter(|entry| !entry.is_dir()) {
+        let remaining = MAX_SIZE_BYTES as usize - buf.len();
+
+        match entry.extension().as_deref() {
+            Some("xml") if title.is_empty() => {
+                let xml = read_entry(file, entry, remaining)?;
+                title = renoise_title(&String::from_utf8_lossy(&xml)).into();
+            }
+            Some(ext @ ("wav" | "flac")) => {
+                let data = read_entry(file, entry, remaining)?;
+                let base = buf.len() as u32;
+                let index_raw = samples.len() as u16;
+
+                let sample = match ext {
+                    "wav" => wav_sample(&mut Cursor::new(&data), base, index_raw),
+                    _ => flac_sample(&mut Cursor::new(&data), base, index_raw),
+                };
+
+                match sample {
+                    Ok(Some(smp)) => samples.push(Sample {
+                        filename: Some(entry.file_name().into()),
+                        name: stem(entry.file_name()).into(),
+                        ..smp
+                    }),
+                    Ok(None) => info!("Skipping unsupported sample: {}", entry.name),
+                    Err(_e) => warn!("Skipping invalid sample: {}, {}", entry.name, _e),
+                }
+                buf.extend_from_slice(&data);
+            }
+            _ => (),
+        }
+    }
+
+    if samples.is_empty() {
+        return Err(Error::invalid(
+            "Zip archive does not contain any modules or samples",
+        ));
+    }
+
+    Ok(ZIP {
+        inner: buf.into(),
+        samples: samples.into(),
+        title,
+        source: None,
+    })
+}
+
+/// Walk the chunks of a WAV file, ``base`` is where the file is stored in the final buffer.
+pub(crate) fn wav_sample(
+    file: &mut impl ReadSeek,
+    base: u32,
+    index_raw: u16,
+) -> Result<Option<Sample>, Error> {
+    if !is_magic(file, &MAGIC_RIFF)? {
+        return Err(Error::invalid("Not a valid WAV file"));
+    }
+    file.skip_bytes(4)?; // size
+
+    if !is_magic(file, &MAGIC_WAVE)? {
+        return Err(Error::invalid("Not a valid WAV file"));
+    }
+
+    let size = file.size().unwrap_or_default() as u32;
+    let mut format: Option<(u16, u16, u32, u16)> = None;
+    let mut data: Option<(u32, u32)> = None;
+    let mut looping = Loop::default();
+
+    while file.seek_position()? + 8 <= size as u64 {
+        let id = file.read_bytes(4)?;
+        let chunk_size = file.read_u32_le()?;
+        let offset = file.seek_position()? as u32;
+
+        match id.as_slice() {
+            b"fmt " => {
+                let tag = file.read_u16_le()?;
+                let channels = file.read_u16_le()?;
+                let rate = file.read_u32_le()?;
+                file.skip_bytes(4 + 2)?; // byte rate, block align
+                let bits = file.read_u16_le()?;
+                format = Some((tag, channels, rate, bits));
+            }
+            b"data" => data = Some((offset, chunk_size.min(size - offset))),
+            b"smpl" => {
+                file.skip_bytes(28)?;
+                if file.read_u32_le()? > 0 {
+                    file.skip_bytes(4 + 4)?; // sampler data, cue point id
+                    let kind = match file.read_u32_le()? {
+                        1 => LoopType::PingPong,
+                        2 => LoopType::Backward,
+                        _ => LoopType::Forward,
+                    };
+                    let start = file.read_u32_le()?;
+                    let end = file.read_u32_le()?;
+                    looping = Loop::new(start, end.saturating_add(1), kind);
+                }
+            }
+            _ => (),
+        }
+
+        // chunks are padded to an even size
+        let next = offset as u64 + chunk_size as u64 + (chunk_size & 1) as u64;
+        file.set_seek_pos(next.min(size as u64))?;
+    }
+
+    let Some((tag, channels, rate, bits)) = format else {
+        return Err(Error::invalid("WAV file is missing its format chunk"));
+    };
+
+    let Some((pointer, length)) = data else {
+        return Err(Error::invalid("WAV file is missing its data chunk"));
+    };
+
+    if !matches!(tag, WAVE_FORMAT_PCM | WAVE_FORMAT_EXTENSIBLE) || !matches!(channels, 1 | 2) {
+        return Ok(None);
+    }
+
+    let depth = match bits {
+        8 => Depth::U8,
+        16 => Depth::I16,
+        24 => Depth::I24,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(Sample {
+        length,
+        rate,
+        pointer: base + pointer,
+        depth,
+        channel: Channel::new(channels == 2, true),
+        index_raw,
+        looping,
+        ..Default::default()
+    }))
+}
+
+/// Obtain the sample information from a FLAC stream's ``STREAMINFO`` block.
+///
+/// The sample's length is the size of the decoded pcm.
+fn flac_sample(
+    file: &mut impl ReadSeek,
+    base: u32,
+    index_raw: u16,
+) -> Result<Option<Sample>, Error> {
+    if !is_magic(file, &MAGIC_FLAC)? {
+        return Err(Error::invalid("Not a valid FLAC file"));
+    }
+
+    file.skip_bytes(4 + 10)?; // metadata block header, block & frame sizes
+
+    let info = u64::from_be_bytes(file.read_bytes(8)?.try_into().unwrap());
+    let rate = (info >> 44) as u32;
+    let channels = ((info >> 41) & 0b111) as u16 + 1;
+    let bits = ((info >> 36) & 0b11111) as u16 + 1;
+    let frames = info & 0xF_FFFF_FFFF;
+
+    if frames == 0 || !matches!(channels, 1 | 2) {
+        return Ok(None);
+    }
+
+    let depth = match bits {
+        8 => Depth::I8,
+        16 => Depth::I16,
+        24 => Depth::I24,
+        _ => return Ok(None),
+    };
+
+    let length = frames * channels as u64 * depth.bytes() as u64;
+
+    if length > MAX_SIZE_BYTES {
+        return Err(Error::unsupported("FLAC sample is too large"));
+    }
+
+    Ok(Some(Sample {
+        length: length as u32,
+        rate,
+        pointer: base,
+        depth,
+        channel: Channel::new(channels == 2, false),
+        index_raw,
+        pcm_type: PcmType::FLAC,
+        ..Default::default()
+    }))
+}
+
+/// Songs store their name in ``Song.xml`` and instruments in ``Instrument.xml``.
+fn renoise_title(xml: &str) -> &str {
+    ["<SongName>", "<Name>"]
+        .iter()
+        .find_map(|tag| {
+            let start = xml.find(tag)? + tag.len();
+            let end = xml[start..].find('<')?;
+            Some(xml[start..start + end].trim())
+        })
+        .unwrap_or_default()
+}
+
+fn stem(file_name: &str) -> &str {
+    file_name
+        .rsplit_once('.')
+        .map_or(file_name, |(stem, _)| stem)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::fmt::loader::load_module;
+    use crate::interface::sample::{Channel, Depth};
+    use crate::parser::zip::tests::build_zip;
+
+    fn wav(pcm: &[u8]) -> Vec<u8> {
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&[1, 0, 2, 0]); // pcm, stereo
+        wav.extend_from_slice(&22050u32.to_le_bytes());
+        wav.extend_from_slice(&(22050u32 * 4).to_le_bytes());
+        wav.extend_from_slice(&[4, 0, 16, 0]);
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(pcm.len() as u32).to_le_bytes());
+        wav.extend_from_slice(pcm);
+        wav
+    }
+
+    #[test]
+    fn renoise_instrument() {
+        let pcm: Vec<u8> = (0..64).collect();
+        let xml = b"<RenoiseInstrument><Name>Piano</Name></RenoiseInstrument>";
+        let zip = build_zip(&[
+            ("Instrument.xml", xml),
+            ("SampleData/Sample00 (C-4).wav", &wav(&pcm)),
+        ]);
+
+        let module = load_module(&mut Cursor::new(zip)).unwrap();
+        assert_eq!(module.name(), "Piano");
+
+        let smp = &module.samples()[0];
+        assert_eq!(smp.filename(), "Sample00 (C-4).wav");
+        assert_eq!(smp.name(), "Sample00 (C-4)");
+        assert_eq!(smp.rate, 22050);
+        assert_eq!(smp.depth, Depth::I16);
+        assert_eq!(smp.channel, Channel::Stereo { interleaved: true });
+        assert_eq!(module.pcm(smp).unwrap().as_ref(), pcm);
+    }
+
+    fn module() -> Vec<u8> {
+        let mut module = b"zipped".to_vec();
+        module.resize(1084, 0);
+        module[20 + 22..20 + 24].copy_from_slice(&8u16.to_be_bytes()); // 16 byte sample
+        module[20 + 25] = 64; // volume
+        module[1080..1084].copy_from_slice(b"M.K.");
+        module.extend_from_slice(&[0u8; 1024]); // pattern
+        module.extend_from_slice(&[1u8; 16]);
+        module
+    }
+
+    #[test]
+    fn zipped_module() {
+        let zip = build_zip(&[("readme.txt", b"hello"), ("song.mod", &module())]);
+        let module = load_module(&mut Cursor::new(zip)).unwrap();
+
+        assert_eq!(module.name(), "zipped");
+        assert_eq!(module.samples().len(), 1);
+        assert_eq!(
+            module.pcm(&module.samples()[0]).unwrap().as_ref(),
+            [1u8; 16]
+        );
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let inner = build_zip(&[("song.mod", &module())]);
+        let outer = build_zip(&[("song.mod", &inner)]);
+        assert!(load_module(&mut Cursor::new(outer.clone())).is_ok());
+
+        let outermost = build_zip(&[("song.mod", &outer)]);
+        assert!(load_module(&mut Cursor::new(outermost)).is_err());
+    }
+}
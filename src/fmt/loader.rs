@@ -7,15 +7,25 @@
 
 use std::io::Cursor;
 
+use crate::common::MAX_SIZE_BYTES;
 use crate::interface::{Error, Module};
-use crate::parser::io::{non_consume, ReadSeek};
+use crate::parser::gzip::{inflate_gzip, inflate_gzip_head, is_gzip};
+use crate::parser::io::{non_consume, ByteReader, ReadSeek};
 
 pub mod formats {
     pub use crate::fmt::fmt_it::IT;
+    pub use crate::fmt::fmt_iti::ITI;
+    pub use crate::fmt::fmt_its::ITS;
+    pub use crate::fmt::fmt_mo3::MO3;
     pub use crate::fmt::fmt_mod::MOD;
+    pub use crate::fmt::fmt_pat::PAT;
+    pub use crate::fmt::fmt_s3i::S3I;
     pub use crate::fmt::fmt_s3m::S3M;
+    pub use crate::fmt::fmt_sf2::SF2;
     pub use crate::fmt::fmt_umx::UMX;
+    pub use crate::fmt::fmt_xi::XI;
     pub use crate::fmt::fmt_xm::XM;
+    pub use crate::fmt::fmt_zip::ZIP;
 }
 use formats::*;
 
@@ -26,28 +36,103 @@ pub enum Format {
     S3M,
     MOD,
     UMX,
+    MO3,
+    ITS,
+    ITI,
+    XI,
+    S3I,
+    PAT,
+    SF2,
+    ZIP,
 }
 
+/// How a module is stored
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+}
+
+/// How many containers (gzip, zip) a module can be nested in
+const MAX_NESTING: u8 = 2;
+
 /// load a module
 pub fn load_module(data: &mut impl ReadSeek) -> Result<Box<dyn Module>, Error> {
-    let module = match identify_module(data)? {
+    load_nested(data, 0)
+}
+
+/// Load a module that was extracted from `depth` containers.
+pub(crate) fn load_nested(data: &mut impl ReadSeek, depth: u8) -> Result<Box<dyn Module>, Error> {
+    let (compression, format) = identify_compression(data)?;
+    let container = compression == Compression::Gzip || matches!(format, Format::ZIP);
+
+    if container && depth >= MAX_NESTING {
+        return Err(Error::unsupported("Module is nested in too many containers"));
+    }
+
+    if compression == Compression::Gzip {
+        let data = inflate_gzip(data, MAX_SIZE_BYTES as usize)?;
+        return load_nested(&mut Cursor::new(data), depth + 1);
+    }
+
+    let module = match format {
         Format::IT => IT::load(data)?,
         Format::XM => XM::load(data)?,
         Format::S3M => S3M::load(data)?,
         Format::MOD => MOD::load(data)?,
         Format::UMX => UMX::load(data)?,
+        Format::MO3 => MO3::load(data)?,
+        Format::ITS => ITS::load(data)?,
+        Format::ITI => ITI::load(data)?,
+        Format::XI => XI::load(data)?,
+        Format::S3I => S3I::load(data)?,
+        Format::PAT => PAT::load(data)?,
+        Format::SF2 => SF2::load(data)?,
+        Format::ZIP => crate::fmt::fmt_zip::parse_(data, depth)?,
     };
     Ok(module)
 }
 
+/// Identify a module, looking through any compression around it
 pub fn identify_module(data: &mut impl ReadSeek) -> Result<Format, Error> {
-    let mut bytes = [0u8; 64];
+    identify_compression(data).map(|(_, format)| format)
+}
+
+/// Identify a module, and how it is compressed
+pub fn identify_compression(data: &mut impl ReadSeek) -> Result<(Compression, Format), Error> {
+    let mut bytes = [0u8; 128];
     non_consume(data, |data| data.read(&mut bytes))?;
 
-    match &bytes {
+    if is_gzip(&bytes) {
+        let mut head = [0u8; 128];
+        let rewind_pos = data.seek_position()?;
+        let len = inflate_gzip_head(data, &mut head);
+        data.set_seek_pos(rewind_pos)?;
+
+        let len = len?;
+        let format = identify_format(&head[..len])?;
+        return Ok((Compression::Gzip, format));
+    }
+
+    Ok((Compression::None, identify_format(&bytes)?))
+}
+
+fn identify_format(bytes: &[u8]) -> Result<Format, Error> {
+    let mut buf = [0u8; 128];
+    buf[..bytes.len()].copy_from_slice(bytes);
+
+    match &buf {
         buf if IT::matches_format(buf) => Ok(Format::IT),
+        buf if ITS::matches_format(buf) => Ok(Format::ITS),
+        buf if ITI::matches_format(buf) => Ok(Format::ITI),
         buf if XM::matches_format(buf) => Ok(Format::XM),
+        buf if XI::matches_format(buf) => Ok(Format::XI),
         buf if S3M::matches_format(buf) => Ok(Format::S3M),
+        buf if S3I::matches_format(buf) => Ok(Format::S3I),
+        buf if PAT::matches_format(buf) => Ok(Format::PAT),
+        buf if SF2::matches_format(buf) => Ok(Format::SF2),
+        buf if ZIP::matches_format(buf) => Ok(Format::ZIP),
+        buf if MO3::matches_format(buf) => Ok(Format::MO3),
         buf if UMX::matches_format(buf) => Ok(Format::UMX),
         buf if MOD::matches_format(buf) => Ok(Format::MOD),
         _ => Err(Error::NoFormatFound),
@@ -65,6 +150,14 @@ impl std::fmt::Display for Format {
                 Self::S3M => "Scream Tracker 3",
                 Self::MOD => "Amiga ProTracker",
                 Self::UMX => "Unreal Music Container",
+                Self::MO3 => "MO3",
+                Self::ITS => "Impulse Tracker Sample",
+                Self::ITI => "Impulse Tracker Instrument",
+                Self::XI => "FastTracker 2 Instrument",
+                Self::S3I => "Scream Tracker 3 Instrument",
+                Self::PAT => "Gravis Ultrasound Patch",
+                Self::SF2 => "SoundFont 2",
+                Self::ZIP => "Zip Archive",
             }
         )
     }
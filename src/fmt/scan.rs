@@ -0,0 +1,179 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::Cursor;
+use std::ops::Range;
+
+use crate::fmt::formats::{IT, MOD, S3M, XM};
+use crate::fmt::Format;
+use crate::interface::{Error, Module};
+use crate::parser::io::{ByteReader, Container, ReadSeek};
+use crate::{info, trace, warn};
+
+/// Offset of the ``M.K.`` signature in a MOD
+const MOD_MAGIC_OFFSET: usize = 1080;
+const MOD_MAGIC: [&[u8; 4]; 2] = [b"M.K.", b"M!K!"];
+
+/// A tracker module found inside of an arbitrary blob of data
+pub struct EmbeddedModule {
+    /// Where the module is stored in the blob
+    pub range: Range<u64>,
+    pub module: Box<dyn Module>,
+}
+
+/// Scan arbitrary data (game archives, demo executables...) for embedded tracker modules.
+///
+/// Every offset is checked for an ``IT``, ``XM``, ``S3M`` or ``MOD`` signature.
+/// Each candidate is validated with the real parser, so false positives are discarded.
+pub fn scan_embedded_modules(data: &mut impl ReadSeek) -> Result<Vec<EmbeddedModule>, Error> {
+    info!("Scanning for embedded modules");
+    let data = data.load_to_memory()?;
+    let candidates = find_candidates(&data);
+
+    let mut modules: Vec<EmbeddedModule> = Vec::new();
+
+    for (i, (start, format)) in candidates.iter().enumerate() {
+        let start = *start;
+
+        // Skip candidates that are part of a module we've already found
+        if modules.last().is_some_and(|m| start < m.range.end as usize) {
+            continue;
+        }
+
+        let Ok(module) = load_at(&data, start..data.len(), format) else {
+            trace!("Discarding {} candidate at offset {}", format, start);
+            continue;
+        };
+
+        // The size of compressed samples is unknown,
+        // so assume the module ends where the next one starts.
+        let next = candidates[i + 1..]
+            .iter()
+            .map(|(offset, _)| *offset)
+            .find(|offset| *offset > start)
+            .unwrap_or(data.len());
+
+        let end = module
+            .samples()
+            .iter()
+            .map(|smp| match smp.pcm_type.is_compressed() {
+                true => next,
+                false => start + smp.ptr_range().end,
+            })
+            .max()
+            .unwrap_or(next)
+            .min(data.len());
+
+        // Reload the module so it only holds on to its own data
+        let Ok(module) = load_at(&data, start..end, format) else {
+            warn!("{} at offset {} could not be reloaded", format, start);
+            continue;
+        };
+        info!("Found {} at {}..{}", format, start, end);
+
+        modules.push(EmbeddedModule {
+            range: start as u64..end as u64,
+            module,
+        });
+    }
+
+    Ok(modules)
+}
+
+/// Find offsets that look like the start of a module
+fn find_candidates(data: &[u8]) -> Vec<(usize, Format)> {
+    let mut candidates: Vec<(usize, Format)> = Vec::new();
+
+    for offset in 0..data.len() {
+        let buf = &data[offset..];
+
+        let format = match buf {
+            buf if IT::matches_format(buf) => Format::IT,
+            buf if XM::matches_format(buf) => Format::XM,
+            buf if S3M::matches_format(buf) => Format::S3M,
+            buf if is_mod(buf) => Format::MOD,
+            _ => continue,
+        };
+
+        candidates.push((offset, format));
+    }
+
+    candidates
+}
+
+fn is_mod(buf: &[u8]) -> bool {
+    buf.get(MOD_MAGIC_OFFSET..MOD_MAGIC_OFFSET + 4)
+        .is_some_and(|magic| MOD_MAGIC.iter().any(|m| m.as_slice() == magic))
+}
+
+fn load_at(data: &[u8], range: Range<usize>, format: &Format) -> Result<Box<dyn Module>, Error> {
+    let mut cursor = Cursor::new(data);
+    cursor.set_seek_pos(range.start as u64)?;
+
+    // Seeking is relative to the start of the module
+    let mut file = Container::new(cursor, Some(range.end as u64));
+    let file = &mut file;
+
+    let module = match format {
+        Format::IT => IT::load(file)?,
+        Format::XM => XM::load(file)?,
+        Format::S3M => S3M::load(file)?,
+        Format::MOD => MOD::load(file)?,
+        _ => unreachable!("Only tracker modules are scanned"),
+    };
+
+    Ok(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::scan_embedded_modules;
+
+    fn tiny_mod(title: &[u8], fill: u8) -> Vec<u8> {
+        let mut module = title.to_vec();
+        module.resize(1084, 0);
+        module[20 + 22..20 + 24].copy_from_slice(&8u16.to_be_bytes());
+        module[20 + 25] = 64;
+        module[1080..1084].copy_from_slice(b"M.K.");
+        module.extend_from_slice(&[0u8; 1024]);
+        module.extend_from_slice(&[fill; 16]);
+        module
+    }
+
+    #[test]
+    fn find_modules() {
+        let first = tiny_mod(b"first", 1);
+        let second = tiny_mod(b"second", 2);
+
+        let mut blob = b"garbage SCRM IMP".repeat(20);
+        let first_start = blob.len() as u64;
+        blob.extend_from_slice(&first);
+        blob.extend_from_slice(&[0xAA; 333]);
+        let second_start = blob.len() as u64;
+        blob.extend_from_slice(&second);
+        blob.extend_from_slice(b"trailing data");
+
+        let found = scan_embedded_modules(&mut Cursor::new(blob)).unwrap();
+        assert_eq!(found.len(), 2);
+
+        assert_eq!(found[0].module.name(), "first");
+        assert_eq!(
+            found[0].range,
+            first_start..first_start + first.len() as u64
+        );
+        assert_eq!(found[1].module.name(), "second");
+        assert_eq!(
+            found[1].range,
+            second_start..second_start + second.len() as u64
+        );
+
+        let smp = &found[1].module.samples()[0];
+        assert_eq!(found[1].module.pcm(smp).unwrap().as_ref(), [2u8; 16]);
+    }
+}
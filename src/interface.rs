@@ -6,12 +6,21 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 pub mod audio;
+pub mod duration;
 pub mod errors;
+pub mod instrument;
 pub mod module;
 pub mod name;
+pub mod pattern;
 pub mod ripper;
 pub mod sample;
+pub mod song;
+pub mod tracker;
+pub mod usage;
 
 pub use errors::Error;
 pub use module::Module;
+pub use pattern::Pattern;
 pub use sample::Sample;
+pub use song::Song;
+pub use tracker::TrackerInfo;
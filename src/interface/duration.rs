@@ -0,0 +1,360 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::interface::pattern::{Cell, Effects, Pattern, ORDER_END, ORDER_SEPARATOR};
+use crate::interface::song::Song;
+
+/// Patterns that are missing from the module are played as 64 empty rows
+const MISSING_PATTERN_ROWS: u16 = 64;
+
+/// Give up if the song somehow plays more rows than this
+const MAX_ROWS: usize = 1 << 20;
+
+/// How long a song plays for
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct SongDuration {
+    /// Time until the song ends, or until it starts repeating itself
+    pub length: Duration,
+    /// The song jumps back to a position it has already played
+    pub loops: bool,
+}
+
+/// Effects that change the timing of the song
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Timing {
+    Speed(u8),
+    Tempo(u8),
+    /// Change the tempo on every tick except the first of the row
+    TempoSlide(i8),
+    /// Continue from this position in the order list
+    Jump(u8),
+    /// Continue from this row of the next pattern
+    Break(u16),
+    /// Set the start of a pattern loop with 0, or repeat it ``n`` times
+    Loop(u8),
+    /// Repeat the row ``n`` times
+    Delay(u8),
+    /// Extend the row by ``n`` ticks
+    FineDelay(u8),
+    Stop,
+}
+
+fn timing(effects: Effects, cell: &Cell) -> Option<Timing> {
+    let param = cell.param;
+    let (x, y) = (param >> 4, param & 0x0F);
+    let bcd = x as u16 * 10 + y as u16;
+    let it = effects == Effects::IT;
+
+    match effects {
+        Effects::IT | Effects::S3M => match (cell.effect, x) {
+            (1, _) if param > 0 => Some(Timing::Speed(param)),
+            (2, _) => Some(Timing::Jump(param)),
+            (3, _) if it => Some(Timing::Break(param as u16)),
+            (3, _) => Some(Timing::Break(bcd)),
+            (19, 0x6) if it => Some(Timing::FineDelay(y)),
+            (19, 0xB) => Some(Timing::Loop(y)),
+            (19, 0xE) => Some(Timing::Delay(y)),
+            (20, _) if param >= 0x20 => Some(Timing::Tempo(param)),
+            (20, 0x0) if it && y > 0 => Some(Timing::TempoSlide(-(y as i8))),
+            (20, 0x1) if it => Some(Timing::TempoSlide(y as i8)),
+            _ => None,
+        },
+        Effects::XM | Effects::MOD => match (cell.effect, x) {
+            (0xB, _) => Some(Timing::Jump(param)),
+            (0xD, _) => Some(Timing::Break(bcd)),
+            (0xE, 0x6) => Some(Timing::Loop(y)),
+            (0xE, 0xE) => Some(Timing::Delay(y)),
+            (0xF, _) if param == 0 => Some(Timing::Stop),
+            (0xF, _) if param < 0x20 => Some(Timing::Speed(param)),
+            (0xF, _) => Some(Timing::Tempo(param)),
+            _ => None,
+        },
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct PatternLoop {
+    start: u16,
+    remaining: u8,
+}
+
+/// A row that is about to be played
+pub(crate) struct Row<'a> {
+    /// One cell per channel, empty if the pattern is missing
+    pub cells: &'a [Cell],
+    /// Ticks per row
+    pub speed: u32,
+    /// Number of ticks, including pattern delays
+    pub ticks: u32,
+    /// Tempo change on every tick except the first of each row
+    tempo_slide: i8,
+}
+
+/// Follows the order list, and only simulates the effects that change the timing.
+///
+/// Playback stops when the song reaches the end of the order list,
+/// or a position that has already been played.
+pub(crate) struct Sequencer<'a> {
+    song: &'a Song,
+    patterns: &'a [Pattern],
+    order: usize,
+    row: u16,
+    speed: u32,
+    tempo: f64,
+    visited: HashSet<(usize, u16)>,
+    pattern_loops: Vec<PatternLoop>,
+    rows_played: usize,
+    stopped: bool,
+    /// The song jumped back to a position it has already played
+    pub loops: bool,
+}
+
+impl<'a> Sequencer<'a> {
+    pub fn new(song: &'a Song, patterns: &'a [Pattern]) -> Self {
+        Self {
+            song,
+            patterns,
+            order: 0,
+            row: 0,
+            speed: song.speed.max(1) as u32,
+            tempo: song.tempo.max(1) as f64,
+            visited: HashSet::new(),
+            pattern_loops: vec![PatternLoop::default(); song.channels.max(1) as usize],
+            rows_played: 0,
+            stopped: false,
+            loops: false,
+        }
+    }
+
+    /// Length of a tick in seconds. Call this for every tick of a row, in order.
+    pub fn tick_length(&mut self, row: &Row, tick: u32) -> f64 {
+        if !tick.is_multiple_of(row.speed) {
+            self.tempo = (self.tempo + row.tempo_slide as f64).clamp(32.0, 255.0);
+        }
+
+        // A tick lasts 2.5 / tempo seconds
+        2.5 / self.tempo
+    }
+
+    /// Apply the timing effects of the next row, and work out which row follows it.
+    pub fn next_row(&mut self) -> Option<Row<'a>> {
+        loop {
+            if self.stopped || self.rows_played >= MAX_ROWS {
+                return None;
+            }
+
+            let (order, index) = self.resolve(self.order)?;
+            self.order = order;
+
+            let pattern = self.patterns.get(index as usize);
+            let rows = pattern.map_or(MISSING_PATTERN_ROWS, Pattern::rows);
+
+            if self.row >= rows {
+                self.order += 1;
+                self.row = 0;
+                self.pattern_loops.fill(PatternLoop::default());
+                continue;
+            }
+
+            if !self.visited.insert((self.order, self.row)) {
+                self.loops = true;
+                return None;
+            }
+
+            self.rows_played += 1;
+
+            let cells = pattern.map(|pattern| pattern.row(self.row)).unwrap_or_default();
+            return Some(self.play(cells));
+        }
+    }
+
+    fn play(&mut self, cells: &'a [Cell]) -> Row<'a> {
+        let mut jump = None;
+        let mut break_row = None;
+        let mut loop_to = None;
+        let mut delay = None;
+        let mut fine_delay = 0;
+        let mut tempo_slide = 0;
+
+        for (channel, cell) in cells.iter().enumerate() {
+            let Some(timing) = timing(self.song.effects, cell) else {
+                continue;
+            };
+
+            match timing {
+                Timing::Speed(value) => self.speed = value as u32,
+                Timing::Tempo(value) => self.tempo = value as f64,
+                Timing::TempoSlide(value) => tempo_slide = value,
+                Timing::Jump(position) => jump = Some(position as usize),
+                Timing::Break(value) => break_row = Some(value),
+                Timing::Delay(value) => delay = delay.or(Some(value as u32)),
+                Timing::FineDelay(value) => fine_delay += value as u32,
+                Timing::Stop => self.stopped = true,
+                Timing::Loop(count) => {
+                    let Some(state) = self.pattern_loops.get_mut(channel) else {
+                        continue;
+                    };
+
+                    match (count, state.remaining) {
+                        (0, _) => state.start = self.row,
+                        (_, 0) => {
+                            state.remaining = count;
+                            loop_to = Some(state.start);
+                        }
+                        _ => {
+                            state.remaining -= 1;
+                            if state.remaining > 0 {
+                                loop_to = Some(state.start);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        match (loop_to, jump, break_row) {
+            (Some(start), _, _) => {
+                // Rows inside a pattern loop are meant to be played again
+                (start..=self.row).for_each(|row| {
+                    self.visited.remove(&(self.order, row));
+                });
+                self.row = start;
+            }
+            (None, None, None) => self.row += 1,
+            (None, jump, break_row) => {
+                let next = jump.unwrap_or(self.order + 1);
+
+                // Breaking to a row that doesn't exist starts from the beginning
+                let next_rows = self
+                    .resolve(next)
+                    .and_then(|(_, index)| self.patterns.get(index as usize))
+                    .map_or(MISSING_PATTERN_ROWS, Pattern::rows);
+
+                self.order = next;
+                self.row = break_row.filter(|row| *row < next_rows).unwrap_or(0);
+                self.pattern_loops.fill(PatternLoop::default());
+            }
+        }
+
+        Row {
+            cells,
+            speed: self.speed,
+            ticks: self.speed * (1 + delay.unwrap_or(0)) + fine_delay,
+            tempo_slide,
+        }
+    }
+
+    /// Find the first pattern from a position in the order list, skipping separators.
+    ///
+    /// Returns its position and index, or ``None`` if the song ends before it.
+    fn resolve(&self, mut order: usize) -> Option<(usize, u8)> {
+        loop {
+            match *self.song.orders.get(order)? {
+                ORDER_END => return None,
+                ORDER_SEPARATOR => order += 1,
+                index => return Some((order, index)),
+            }
+        }
+    }
+}
+
+/// Estimate how long the song plays for
+pub(crate) fn song_duration(song: &Song, patterns: &[Pattern]) -> SongDuration {
+    let mut sequencer = Sequencer::new(song, patterns);
+    let mut seconds = 0.0;
+
+    while let Some(row) = sequencer.next_row() {
+        for tick in 0..row.ticks {
+            seconds += sequencer.tick_length(&row, tick);
+        }
+    }
+
+    SongDuration {
+        length: Duration::from_secs_f64(seconds),
+        loops: sequencer.loops,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(orders: &[u8], effects: Effects) -> Song {
+        Song {
+            orders: orders.into(),
+            channels: 2,
+            effects,
+            ..Default::default()
+        }
+    }
+
+    fn millis(duration: SongDuration) -> u64 {
+        (duration.length.as_secs_f64() * 1000.0).round() as u64
+    }
+
+    fn set_effect(pattern: &mut Pattern, row: u16, channel: u8, effect: u8, param: u8) {
+        let cell = pattern.cell_mut(row, channel).unwrap();
+        cell.effect = effect;
+        cell.param = param;
+    }
+
+    #[test]
+    fn plain_rows() {
+        // 64 rows, 6 ticks at 125 bpm
+        let duration = song_duration(&song(&[0, 0], Effects::XM), &[Pattern::new(64, 2)]);
+
+        assert_eq!(millis(duration), 2 * 64 * 120);
+        assert!(!duration.loops);
+    }
+
+    #[test]
+    fn speed_break_and_jump() {
+        let mut pattern = Pattern::new(64, 2);
+        set_effect(&mut pattern, 0, 0, 0xF, 3); // speed 3
+        set_effect(&mut pattern, 0, 1, 0xF, 250); // tempo 250
+        set_effect(&mut pattern, 9, 0, 0xD, 0x10); // break to row 10
+        set_effect(&mut pattern, 19, 1, 0xB, 0); // jump to the first order
+
+        let duration = song_duration(&song(&[0, 0], Effects::MOD), &[pattern]);
+
+        // 10 rows in the first pattern, 10 in the second, 30ms each
+        assert_eq!(millis(duration), 20 * 30);
+        assert!(duration.loops);
+    }
+
+    #[test]
+    fn pattern_loop_and_delay() {
+        let mut pattern = Pattern::new(4, 2);
+        set_effect(&mut pattern, 1, 0, 19, 0xB0); // loop start
+        set_effect(&mut pattern, 2, 0, 19, 0xB2); // play rows 1-2 twice more
+        set_effect(&mut pattern, 3, 1, 19, 0xE1); // repeat row once
+        set_effect(&mut pattern, 3, 0, 3, 0); // break
+
+        let duration = song_duration(&song(&[0], Effects::IT), &[pattern]);
+
+        // 1 + 2 * 3 + 2 rows, 120ms each
+        assert_eq!(millis(duration), 9 * 120);
+        assert!(!duration.loops);
+    }
+
+    #[test]
+    fn separators() {
+        let mut first = Pattern::new(4, 2);
+        set_effect(&mut first, 0, 0, 2, 2); // jump to the third order
+
+        let patterns = [first, Pattern::new(8, 2), Pattern::new(16, 2)];
+        let song = song(&[0, ORDER_SEPARATOR, 1, ORDER_END, 2], Effects::IT);
+        let duration = song_duration(&song, &patterns);
+
+        // Position jumps count the separator, and the song ends before the last pattern
+        assert_eq!(millis(duration), (1 + 8) * 120);
+        assert!(!duration.loops);
+    }
+}
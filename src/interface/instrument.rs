@@ -0,0 +1,133 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::interface::pattern::NOTE_MAX;
+
+/// Instrument of an XM or IT module.
+///
+/// Instruments group samples together and control how they're played.
+#[derive(Default, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Instrument {
+    pub name: Box<str>,
+
+    /// Which sample each note plays
+    pub keymap: Keymap,
+
+    /// Values range from 0 to 64
+    pub volume_envelope: Envelope,
+
+    /// Values range from -32 (left) to 32 (right)
+    pub panning_envelope: Envelope,
+
+    /// Values range from -32 to 32 in half semitones.
+    ///
+    /// Only IT instruments have this.
+    pub pitch_envelope: Envelope,
+
+    /// The pitch envelope controls the filter cutoff instead (IT)
+    pub filter: bool,
+
+    /// How much volume is lost each tick after the note is released, out of 65536
+    pub fadeout: u16,
+
+    /// What happens to the playing note when a new note is played on the same channel
+    pub nna: NewNoteAction,
+}
+
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum NewNoteAction {
+    /// Stop the note
+    #[default]
+    Cut,
+    /// Keep playing the note
+    Continue,
+    /// Release the note
+    Off,
+    /// Fade out the note
+    Fade,
+}
+
+impl NewNoteAction {
+    pub(crate) fn from_it(nna: u8) -> Self {
+        match nna {
+            1 => Self::Continue,
+            2 => Self::Off,
+            3 => Self::Fade,
+            _ => Self::Cut,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Envelope {
+    pub enabled: bool,
+    pub points: Box<[EnvelopePoint]>,
+    /// Loops while the note is held, XM envelopes sustain on a single point.
+    pub sustain: Option<EnvelopeLoop>,
+    pub looping: Option<EnvelopeLoop>,
+}
+
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct EnvelopePoint {
+    pub tick: u16,
+    pub value: i8,
+}
+
+/// Indices of the first and last point of a loop
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct EnvelopeLoop {
+    pub start: u8,
+    pub end: u8,
+}
+
+impl EnvelopeLoop {
+    /// Returns ``None`` if the loop doesn't fit inside the envelope.
+    pub(crate) fn new(start: u8, end: u8, points: usize) -> Option<Self> {
+        (start <= end && (end as usize) < points).then_some(Self { start, end })
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct KeymapEntry {
+    /// Raw index of the sample, see [crate::interface::Sample::index_raw]
+    pub sample: Option<u16>,
+    /// The note the sample is played at
+    pub note: u8,
+}
+
+/// Maps each note (C-0 to B-9) of an instrument to a sample,
+/// and the note it is played at.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Keymap(Box<[KeymapEntry]>);
+
+impl Default for Keymap {
+    /// Every note is unmapped
+    fn default() -> Self {
+        Self::from_fn(|note| KeymapEntry { sample: None, note })
+    }
+}
+
+impl Keymap {
+    /// Build a keymap from a function that is called for every note
+    pub fn from_fn(f: impl FnMut(u8) -> KeymapEntry) -> Self {
+        Self((0..=NOTE_MAX).map(f).collect())
+    }
+
+    pub fn get(&self, note: u8) -> Option<&KeymapEntry> {
+        self.0.get(note as usize)
+    }
+
+    /// The raw sample index and note played by ``note``
+    pub fn sample(&self, note: u8) -> Option<(u16, u8)> {
+        self.get(note)
+            .and_then(|entry| Some((entry.sample?, entry.note)))
+    }
+
+    pub fn entries(&self) -> &[KeymapEntry] {
+        &self.0
+    }
+}
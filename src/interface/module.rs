@@ -5,7 +5,16 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::interface::{sample::Sample, Error};
+use crate::interface::{
+    duration::{song_duration, SongDuration},
+    instrument::Instrument,
+    pattern::Pattern,
+    sample::Sample,
+    song::Song,
+    tracker::TrackerInfo,
+    usage::SampleUsage,
+    Error,
+};
 use crate::parser::io::ReadSeek;
 use std::borrow::Cow;
 use std::path::{Path, PathBuf};
@@ -23,7 +32,45 @@ pub trait Module: Send + Sync {
     fn format(&self) -> &str;
 
     /// Display internal text
-    // fn comments(&self) -> Cow<str>;
+    ///
+    /// This can be a song message, or text hidden in the sample names.
+    fn comments(&self) -> Cow<'_, str> {
+        Cow::Borrowed("")
+    }
+
+    /// The tracker software that created the module, if it can be detected
+    fn created_with(&self) -> Option<TrackerInfo> {
+        None
+    }
+
+    /// Decoded pattern data, empty if the format doesn't store any
+    fn patterns(&self) -> &[Pattern] {
+        &[]
+    }
+
+    /// The order list and initial playback settings, if the format stores a song
+    fn song(&self) -> Option<&Song> {
+        None
+    }
+
+    /// Instruments, empty if the format only has samples
+    fn instruments(&self) -> &[Instrument] {
+        &[]
+    }
+
+    /// How each sample is used by the song, in the same order as [Module::samples].
+    ///
+    /// Returns ``None`` if the format doesn't store any pattern data.
+    fn sample_usage(&self) -> Option<Box<[SampleUsage]>> {
+        None
+    }
+
+    /// How long the song plays for, estimated from the timing effects in its patterns.
+    ///
+    /// Returns ``None`` if the format doesn't store a song.
+    fn duration(&self) -> Option<SongDuration> {
+        Some(song_duration(self.song()?, self.patterns()))
+    }
 
     fn matches_format(buf: &[u8]) -> bool
     where
@@ -89,6 +136,18 @@ impl GenericTracker {
             .ok_or_else(|| Error::bad_sample(smp))
     }
 
+    /// Obtain an arbitrary region belonging to a sample.
+    ///
+    /// Useful if a sample's pcm is split across multiple regions.
+    #[inline]
+    pub fn get_slice_from_range(
+        &self,
+        smp: &Sample,
+        range: std::ops::Range<usize>,
+    ) -> Result<&[u8], Error> {
+        self.buf.get(range).ok_or_else(|| Error::bad_sample(smp))
+    }
+
     #[inline]
     pub fn get_owned_slice(&self, smp: &Sample) -> Result<Vec<u8>, Error> {
         Ok(self.get_slice(smp)?.to_owned())
@@ -0,0 +1,195 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Highest note, B-9
+pub const NOTE_MAX: u8 = 119;
+
+/// The note that plays a sample at its sample rate, C-5
+pub const NOTE_MIDDLE_C: u8 = 60;
+
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Note {
+    #[default]
+    None,
+    /// A note from C-0 (0) to B-9 (119).
+    ///
+    /// Notes are normalized so that C-5 (60) plays a sample at its sample rate.
+    On(u8),
+    /// Release the note
+    Off,
+    /// Silence the note immediately
+    Cut,
+    /// Fade out the note
+    Fade,
+}
+
+impl Note {
+    pub fn value(&self) -> Option<u8> {
+        match self {
+            Self::On(note) => Some(*note),
+            _ => None,
+        }
+    }
+}
+
+/// How the effects of a [Cell] are numbered
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Effects {
+    #[default]
+    MOD,
+    S3M,
+    XM,
+    IT,
+}
+
+/// A single cell of a pattern.
+///
+/// The volume column and effects are stored as they are in the module,
+/// so they need to be interpreted according to its format:
+///
+/// * IT & S3M effects are letters, where ``A`` is 1.
+/// * XM & MOD effects are numbers, where ``0`` is arpeggio (if the parameter is non-zero).
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct Cell {
+    pub note: Note,
+    /// Instrument (or sample) number, 0 means none
+    pub instrument: u8,
+    pub volume: Option<u8>,
+    pub effect: u8,
+    pub param: u8,
+}
+
+impl Cell {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// A grid of cells, stored row by row.
+#[derive(Default, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Pattern {
+    rows: u16,
+    channels: u8,
+    cells: Box<[Cell]>,
+}
+
+impl Pattern {
+    /// Create an empty pattern
+    pub fn new(rows: u16, channels: u8) -> Self {
+        Self {
+            rows,
+            channels,
+            cells: vec![Cell::default(); rows as usize * channels as usize].into(),
+        }
+    }
+
+    pub fn rows(&self) -> u16 {
+        self.rows
+    }
+
+    pub fn channels(&self) -> u8 {
+        self.channels
+    }
+
+    /// Cells of a row, one per channel.
+    ///
+    /// # Panics
+    /// Will panic if the row is out of bounds
+    pub fn row(&self, row: u16) -> &[Cell] {
+        let start = row as usize * self.channels as usize;
+        &self.cells[start..start + self.channels as usize]
+    }
+
+    pub fn iter_rows(&self) -> impl Iterator<Item = &[Cell]> {
+        self.cells.chunks_exact(self.channels.max(1) as usize)
+    }
+
+    pub fn cell(&self, row: u16, channel: u8) -> Option<&Cell> {
+        self.index(row, channel).map(|i| &self.cells[i])
+    }
+
+    pub fn cell_mut(&mut self, row: u16, channel: u8) -> Option<&mut Cell> {
+        self.index(row, channel).map(|i| &mut self.cells[i])
+    }
+
+    /// Change the number of channels, keeping the cells of the remaining channels.
+    pub(crate) fn set_channels(&mut self, channels: u8) {
+        let mut pattern = Self::new(self.rows, channels);
+
+        for (old, new) in self
+            .iter_rows()
+            .zip(pattern.cells.chunks_exact_mut(channels as usize))
+        {
+            let shared = old.len().min(new.len());
+            new[..shared].copy_from_slice(&old[..shared]);
+        }
+
+        *self = pattern;
+    }
+
+    /// The highest channel (+1) that contains data
+    pub(crate) fn channels_used(&self) -> u8 {
+        self.iter_rows()
+            .filter_map(|row| row.iter().rposition(|cell| !cell.is_empty()))
+            .max()
+            .map_or(0, |channel| channel as u8 + 1)
+    }
+
+    fn index(&self, row: u16, channel: u8) -> Option<usize> {
+        (row < self.rows && channel < self.channels)
+            .then(|| row as usize * self.channels as usize + channel as usize)
+    }
+}
+
+/// Impulse Tracker & Scream Tracker mark the end of the song with this order
+pub const ORDER_END: u8 = 255;
+
+/// Impulse Tracker & Scream Tracker skip over this order
+pub const ORDER_SEPARATOR: u8 = 254;
+
+/// The patterns of an order list in the order they're played
+pub(crate) fn played_orders(orders: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    orders
+        .iter()
+        .copied()
+        .take_while(|order| *order != ORDER_END)
+        .filter(|order| *order != ORDER_SEPARATOR)
+}
+
+/// Trim unused channels so that every pattern has the same number of channels.
+pub(crate) fn trim_channels(patterns: &mut [Pattern]) {
+    let channels = patterns
+        .iter()
+        .map(Pattern::channels_used)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    patterns
+        .iter_mut()
+        .for_each(|pattern| pattern.set_channels(channels));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trim() {
+        let mut patterns = [Pattern::new(2, 64), Pattern::new(4, 64)];
+        patterns[1].cell_mut(3, 2).unwrap().instrument = 1;
+
+        trim_channels(&mut patterns);
+
+        assert_eq!(patterns[0].channels(), 3);
+        assert_eq!(patterns[1].row(3)[2].instrument, 1);
+        assert!(patterns[1].cell(3, 3).is_none());
+    }
+}
@@ -7,6 +7,7 @@
 
 #[cfg(feature = "rayon")]
 use rayon::prelude::*;
+use std::borrow::Cow;
 use std::io::{BufWriter, Write};
 // use std::io::{self, Write};
 use std::{fs, path::Path};
@@ -16,6 +17,7 @@ use crate::exporter::AudioFormat;
 use crate::interface::audio::{AudioTrait, DynAudioTrait};
 use crate::interface::name::{Context, DynSampleNamerTrait, SampleNamer, SampleNamerTrait};
 use crate::interface::{Error, Module, Sample};
+use crate::render::{render_stem, to_sample};
 
 use super::errors::ExtractionError;
 
@@ -32,6 +34,10 @@ pub struct Ripper {
     /// Process raw PCM to the implemented format  
     /// see [AudioTrait]
     format: Box<dyn AudioTrait>,
+
+    /// Don't rip samples that the song never plays.
+    /// See [Module::sample_usage]
+    skip_unused: bool,
 }
 
 impl Default for Ripper {
@@ -39,13 +45,18 @@ impl Default for Ripper {
         Self {
             namer_func: SampleNamer::default().into(),
             format: AudioFormat::WAV.into(),
+            skip_unused: false,
         }
     }
 }
 
 impl Ripper {
     pub fn new(namer_func: DynSampleNamerTrait, format: DynAudioTrait) -> Self {
-        Self { namer_func, format }
+        Self {
+            namer_func,
+            format,
+            skip_unused: false,
+        }
     }
 
     /// Change the sample format
@@ -58,6 +69,13 @@ impl Ripper {
         self.namer_func = namer;
     }
 
+    /// Skip samples that aren't used by the song.
+    ///
+    /// Has no effect on formats that can't report sample usage.
+    pub fn skip_unused_samples(&mut self, skip: bool) {
+        self.skip_unused = skip;
+    }
+
     /// Rip samples to a directory
     pub fn rip_to_dir(
         &self,
@@ -83,27 +101,26 @@ impl Ripper {
             // Only create the file AFTER we have obtained the pcm to prevent artifacts.
             let pcm = module.pcm(smp)?;
 
-            let mut file = fs::File::options()
-                .create_new(true)
-                .write(true)
-                .open(&sample_path)
-                .map(BufWriter::new)?;
-
-            let result = self.format.write(smp, pcm, &mut file);
-            file.flush()?;
+            self.write_file(&sample_path, smp, pcm)
+        };
 
-            // If we can't write the pcm in its specific format,
-            // delete the file so that it won't leave empty artifacts
-            if result.is_err() {
-                let _ = fs::remove_file(sample_path);
-            }
+        let usage = match self.skip_unused {
+            true => module.sample_usage(),
+            false => None,
+        };
 
-            result
+        let is_used = |index: usize| match &usage {
+            Some(usage) => usage[index].is_used(),
+            None => true,
         };
 
         let mut errors = Vec::new();
 
         for (index, sample) in module.samples().iter().enumerate() {
+            if !is_used(index) {
+                continue;
+            }
+
             if let Err(error) = extract_samples(index, sample) {
                 errors.push(ExtractionError::new(sample.index_raw(), error))
             }
@@ -115,6 +132,79 @@ impl Ripper {
             _ => Error::partial_extraction(errors),
         }
     }
+
+    /// Render every channel of the song to a directory, see [crate::render::render_stems].
+    ///
+    /// Stems are named like samples, where each stem is called "Channel n".
+    /// They're rendered and written one at a time, so only one is held in memory.
+    pub fn render_stems_to_dir(
+        &self,
+        directory: impl AsRef<Path>,
+        module: &dyn Module,
+        rate: u32,
+    ) -> Result<(), Error> {
+        let directory = directory.as_ref();
+
+        if !directory.is_dir() {
+            error!("Path is not a directory");
+            return Error::io_error("Path is not a directory");
+        }
+
+        let stems = module.song().map_or(0, |song| song.channels as usize);
+
+        if stems == 0 {
+            return Err(Error::EmptyModule);
+        }
+
+        let context = Context {
+            total: stems,
+            extension: self.format.extension(),
+            highest: stems,
+            source_path: module.source(),
+        };
+
+        let mut errors = Vec::new();
+
+        for index in 0..stems {
+            let name = format!("Channel {}", index + 1);
+            let stem = render_stem(module, rate, index)?;
+
+            let result = to_sample(&stem, &name, index as u16).and_then(|(smp, pcm)| {
+                let path = directory.join((self.namer_func)(&smp, &context, index));
+                self.write_file(&path, &smp, Cow::Owned(pcm))
+            });
+
+            if let Err(error) = result {
+                errors.push(ExtractionError::new(index + 1, error))
+            }
+        }
+
+        match errors.len() {
+            0 => Ok(()),
+            n if n == stems => Error::extraction_failure(errors),
+            _ => Error::partial_extraction(errors),
+        }
+    }
+
+    /// Write pcm to a new file in the current format
+    fn write_file(&self, path: &Path, smp: &Sample, pcm: Cow<[u8]>) -> Result<(), Error> {
+        let mut file = fs::File::options()
+            .create_new(true)
+            .write(true)
+            .open(path)
+            .map(BufWriter::new)?;
+
+        let result = self.format.write(smp, pcm, &mut file);
+        file.flush()?;
+
+        // If we can't write the pcm in its specific format,
+        // delete the file so that it won't leave empty artifacts
+        if result.is_err() {
+            let _ = fs::remove_file(path);
+        }
+
+        result
+    }
 }
 
 pub fn build_context<'a>(module: &'a dyn Module, audio_format: &'a DynAudioTrait) -> Context<'a> {
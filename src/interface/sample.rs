@@ -43,6 +43,15 @@ pub struct Sample {
 
     /// Looping information
     pub looping: Loop,
+
+    /// Sustain loop. The sample plays this loop while the note is held.
+    pub sustain: Option<Loop>,
+
+    /// Extra information stored by OpenMPT. Only IT & MPTM files have this.
+    pub mpt: Option<Box<MptInfo>>,
+
+    /// Default playback parameters
+    pub meta: SampleMeta,
 }
 
 impl Sample {
@@ -120,6 +129,107 @@ impl PartialEq for Sample {
     }
 }
 
+/// Playback parameters stored alongside a sample.
+///
+/// Formats that don't store a parameter leave it at its default.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct SampleMeta {
+    /// Default volume (0-64)
+    pub volume: u8,
+    /// Global volume (0-64)
+    pub global_volume: u8,
+    /// Default panning (0-255). ``None`` if the sample doesn't override the channel panning.
+    pub panning: Option<u8>,
+    /// Finetune in 1/128th of a semitone
+    ///
+    /// This is already applied to [Sample::rate], don't apply it again when playing the sample.
+    pub finetune: i8,
+    /// Relative note in semitones
+    ///
+    /// Like the finetune, this is already applied to [Sample::rate].
+    pub relative_note: i8,
+    /// Auto vibrato
+    pub vibrato: Vibrato,
+}
+
+impl Default for SampleMeta {
+    fn default() -> Self {
+        Self {
+            volume: 64,
+            global_volume: 64,
+            panning: None,
+            finetune: 0,
+            relative_note: 0,
+            vibrato: Vibrato::default(),
+        }
+    }
+}
+
+/// Auto vibrato applied to every note played with the sample
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct Vibrato {
+    pub waveform: Waveform,
+    pub speed: u8,
+    pub depth: u8,
+    /// How quickly the vibrato reaches its full depth.
+    /// This is the "rate" in Impulse Tracker and the "sweep" in Fast Tracker 2.
+    pub sweep: u8,
+}
+
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Sine,
+    RampDown,
+    RampUp,
+    Square,
+    Random,
+}
+
+impl Waveform {
+    /// Impulse Tracker's vibrato waveform
+    pub fn from_it(value: u8) -> Self {
+        match value {
+            1 => Self::RampDown,
+            2 => Self::Square,
+            3 => Self::Random,
+            _ => Self::Sine,
+        }
+    }
+
+    pub fn to_it(self) -> u8 {
+        match self {
+            Self::Sine => 0,
+            Self::RampDown | Self::RampUp => 1,
+            Self::Square => 2,
+            Self::Random => 3,
+        }
+    }
+
+    /// Fast Tracker 2's vibrato waveform
+    pub fn from_xm(value: u8) -> Self {
+        match value {
+            1 => Self::Square,
+            2 => Self::RampDown,
+            3 => Self::RampUp,
+            _ => Self::Sine,
+        }
+    }
+}
+
+/// Sample information found in OpenMPT's extension blocks
+#[derive(Default, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct MptInfo {
+    /// Sample cue points in frames
+    pub cue_points: Box<[u32]>,
+    /// Name of the tuning the sample is played with, ``None`` if it uses the default tuning.
+    pub tuning: Option<Box<str>>,
+    /// OpenMPT's extended compatibility flags. These are set for the whole module.
+    pub compat_flags: Option<u32>,
+    /// Version of the trailing MPTM data. ``None`` for IT files saved with OpenMPT.
+    pub mptm_version: Option<u8>,
+}
+
 /// Sample looping information
 #[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct Loop {
@@ -236,6 +346,10 @@ pub enum Depth {
     I16,
     /// Unsigned 16 bit
     U16,
+    /// Signed 24 bit, packed little endian
+    I24,
+    /// Signed 32 bit
+    I32,
 }
 
 impl Depth {
@@ -256,6 +370,8 @@ impl Depth {
         match self {
             Self::I8 | Self::U8 => 8,
             Self::I16 | Self::U16 => 16,
+            Self::I24 => 24,
+            Self::I32 => 32,
         }
     }
 
@@ -268,7 +384,7 @@ impl Depth {
     }
 
     pub fn is_signed(self) -> bool {
-        matches!(self, Self::I8 | Self::I16)
+        matches!(self, Self::I8 | Self::I16 | Self::I24 | Self::I32)
     }
 }
 
@@ -350,12 +466,31 @@ pub enum PcmType {
     IT214,
     /// Sample is compressed with Impulse Tracker v2.15
     IT215,
+    /// Sample is stored as a FLAC stream
+    FLAC,
+    /// Sample is compressed with ModPlug's 4 bit ADPCM
+    ADPCM4,
+    /// Sample is stored as an Ogg Vorbis stream
+    VORBIS,
+    /// Sample is compressed with MO3's delta encoding
+    MO3DELTA,
+    /// Sample is compressed with MO3's delta prediction encoding
+    MO3PREDICT,
+    /// Sample is stored as an MPEG audio stream. This can't be decoded.
+    MP3,
 }
 
 impl PcmType {
     pub fn is_compressed(&self) -> bool {
         match self {
-            Self::IT214 | Self::IT215 => true,
+            Self::IT214
+            | Self::IT215
+            | Self::FLAC
+            | Self::ADPCM4
+            | Self::VORBIS
+            | Self::MO3DELTA
+            | Self::MO3PREDICT
+            | Self::MP3 => true,
             _ => false,
         }
     }
@@ -0,0 +1,73 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::interface::pattern::Effects;
+
+/// How the song is arranged and its initial playback settings.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Song {
+    /// Patterns to play, in order.
+    ///
+    /// This is the order list as it's stored, so position jumps can index it directly.
+    /// Separators ([crate::interface::pattern::ORDER_SEPARATOR]) are skipped,
+    /// and [crate::interface::pattern::ORDER_END] ends the song.
+    pub orders: Box<[u8]>,
+
+    /// Position in the order list to jump to once the song ends
+    pub restart: u8,
+
+    /// Initial ticks per row
+    pub speed: u8,
+
+    /// Initial beats per minute
+    pub tempo: u8,
+
+    /// Ranges from 0 to 128
+    pub global_volume: u8,
+
+    /// Number of channels the patterns use
+    pub channels: u8,
+
+    /// Initial panning of each channel, from 0 (left) to 255 (right)
+    pub panning: Box<[u8]>,
+
+    /// How note frequencies and pitch slides are calculated
+    pub frequencies: FrequencyMode,
+
+    /// How the effects in the patterns are numbered
+    pub effects: Effects,
+}
+
+impl Default for Song {
+    fn default() -> Self {
+        Self {
+            orders: Default::default(),
+            restart: 0,
+            speed: 6,
+            tempo: 125,
+            global_volume: 128,
+            channels: 0,
+            panning: Default::default(),
+            frequencies: FrequencyMode::Amiga,
+            effects: Effects::default(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum FrequencyMode {
+    /// Pitch slides change the period, so they sound faster at higher notes
+    #[default]
+    Amiga,
+    /// Pitch slides change the note linearly
+    Linear,
+}
@@ -0,0 +1,147 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fmt::Display;
+
+/// The tracker software (and version) that created a module.
+///
+/// This is a best guess, many trackers pretend to be another for compatibility.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TrackerInfo {
+    pub name: Box<str>,
+    pub version: Option<Box<str>>,
+}
+
+impl TrackerInfo {
+    pub fn new(name: impl Into<Box<str>>, version: Option<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: version.map(Into::into),
+        }
+    }
+
+    /// Tracker versions that are stored as binary coded decimal, e.g. ``0x0214`` is "2.14"
+    pub fn bcd(name: impl Into<Box<str>>, major: u16, minor: u16) -> Self {
+        Self::new(name, Some(format!("{major:X}.{minor:02X}")))
+    }
+
+    /// Parse a name such as "MilkyTracker 1.00.00" or "FastTracker v 2.00"
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+
+        if text.is_empty() {
+            return None;
+        }
+
+        let version = text
+            .rsplit_once(' ')
+            .filter(|(_, version)| version.contains(|c: char| c.is_ascii_digit()));
+
+        let tracker = match version {
+            Some((name, version)) => Self::new(
+                name.trim().trim_end_matches(" v"),
+                Some(version.trim_start_matches('v').to_owned()),
+            ),
+            None => Self::new(text, None),
+        };
+
+        Some(tracker)
+    }
+
+    /// Since 2009-10-31, Schism Tracker stores the build date as the number of days after it.
+    ///
+    /// ``version`` is the lower 12 bits of the tracker version,
+    /// ``extended`` is used if they overflow (0xFFF).
+    pub fn schism(version: u16, extended: Option<u32>) -> Self {
+        const EPOCH: u16 = 0x050;
+        const OVERFLOW: u16 = 0xFFF;
+
+        let version = match version {
+            v if v < EPOCH => Some(format!("0.{v:x}")),
+            OVERFLOW => extended.map(schism_date),
+            v => Some(schism_date((v - EPOCH) as u32)),
+        };
+
+        Self::new("Schism Tracker", version)
+    }
+}
+
+impl Display for TrackerInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{} {}", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Format the date that is ``days`` after 2009-10-31.
+fn schism_date(days: u32) -> String {
+    // Days between 0000-03-01 and 2009-10-31
+    const EPOCH: i64 = 734_016;
+
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = EPOCH + days as i64;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrackerInfo;
+
+    #[test]
+    fn schism_dates() {
+        assert_eq!(
+            TrackerInfo::schism(0x050, None).to_string(),
+            "Schism Tracker 2009-10-31"
+        );
+        assert_eq!(
+            TrackerInfo::schism(0x051, None).to_string(),
+            "Schism Tracker 2009-11-01"
+        );
+        assert_eq!(
+            TrackerInfo::schism(0x0FE, None).to_string(),
+            "Schism Tracker 2010-04-23"
+        );
+        assert_eq!(
+            TrackerInfo::schism(0x020, None).to_string(),
+            "Schism Tracker 0.20"
+        );
+        assert_eq!(
+            TrackerInfo::schism(0xFFF, None).to_string(),
+            "Schism Tracker"
+        );
+    }
+
+    #[test]
+    fn parse_names() {
+        let milky = TrackerInfo::parse("MilkyTracker 1.00.00  ").unwrap();
+        assert_eq!(milky.name.as_ref(), "MilkyTracker");
+        assert_eq!(milky.version.as_deref(), Some("1.00.00"));
+
+        assert_eq!(
+            TrackerInfo::parse("FastTracker v 2.00")
+                .unwrap()
+                .to_string(),
+            "FastTracker 2.00"
+        );
+        assert_eq!(
+            TrackerInfo::parse("MadTracker").unwrap().to_string(),
+            "MadTracker"
+        );
+        assert!(TrackerInfo::parse("   ").is_none());
+    }
+}
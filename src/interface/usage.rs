@@ -0,0 +1,162 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::interface::instrument::Instrument;
+use crate::interface::pattern::{played_orders, Note, Pattern};
+use crate::interface::Sample;
+
+/// How a sample is used by the song
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct SampleUsage {
+    /// How many times the sample is triggered by the patterns in the order list
+    pub references: usize,
+    /// Notes the sample is played at, see [crate::interface::pattern::Note]
+    pub notes: BTreeSet<u8>,
+}
+
+impl SampleUsage {
+    pub fn is_used(&self) -> bool {
+        self.references > 0
+    }
+}
+
+/// Resolve instrument numbers from the pattern data to samples.
+pub(crate) enum Instruments<'a> {
+    /// Instrument numbers refer to samples directly
+    Samples,
+    /// Instrument numbers refer to instruments with a keymap
+    Keymaps(&'a [Instrument]),
+}
+
+impl Instruments<'_> {
+    /// Returns the raw sample index and the note it's played at
+    fn resolve(&self, instrument: u8, note: u8) -> Option<(u16, u8)> {
+        let index = instrument.checked_sub(1)?;
+
+        match self {
+            Self::Samples => Some((index as u16, note)),
+            Self::Keymaps(keymaps) => keymaps.get(index as usize)?.keymap.sample(note),
+        }
+    }
+}
+
+/// Walk through the patterns in the order list and count every note each sample plays.
+///
+/// Flow control effects (jumps, breaks, loops) aren't followed.
+pub(crate) fn sample_usage(
+    samples: &[Sample],
+    patterns: &[Pattern],
+    orders: &[u8],
+    instruments: Instruments,
+) -> Box<[SampleUsage]> {
+    let mut usage = vec![SampleUsage::default(); samples.len()];
+
+    let position: HashMap<u16, usize> = samples
+        .iter()
+        .enumerate()
+        .map(|(position, smp)| (smp.index_raw, position))
+        .collect();
+
+    let channels = patterns.iter().map(Pattern::channels).max().unwrap_or(0);
+
+    // A note without an instrument uses the previous instrument on that channel
+    let mut last_instrument = vec![0u8; channels as usize];
+
+    for pattern in played_orders(orders).filter_map(|order| patterns.get(order as usize))
+    {
+        for row in pattern.iter_rows() {
+            for (cell, last_instrument) in row.iter().zip(last_instrument.iter_mut()) {
+                if cell.instrument != 0 {
+                    *last_instrument = cell.instrument;
+                }
+
+                let Note::On(note) = cell.note else {
+                    continue;
+                };
+
+                let Some((index_raw, note)) = instruments.resolve(*last_instrument, note) else {
+                    continue;
+                };
+
+                if let Some(usage) = position.get(&index_raw).map(|i| &mut usage[*i]) {
+                    usage.references += 1;
+                    usage.notes.insert(note);
+                }
+            }
+        }
+    }
+
+    usage.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::instrument::{Keymap, KeymapEntry};
+
+    fn sample(index_raw: u16) -> Sample {
+        Sample {
+            index_raw,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn count_references() {
+        let mut pattern = Pattern::new(4, 2);
+        let cell = pattern.cell_mut(0, 0).unwrap();
+        cell.note = Note::On(60);
+        cell.instrument = 1;
+
+        // Reuses the instrument
+        pattern.cell_mut(1, 0).unwrap().note = Note::On(62);
+
+        let cell = pattern.cell_mut(2, 1).unwrap();
+        cell.note = Note::On(48);
+        cell.instrument = 3;
+
+        let samples = [sample(0), sample(1), sample(2)];
+        let usage = sample_usage(&samples, &[pattern], &[0, 0], Instruments::Samples);
+
+        assert_eq!(usage[0].references, 4);
+        assert_eq!(usage[0].notes, BTreeSet::from([60, 62]));
+        assert!(!usage[1].is_used());
+        assert_eq!(usage[2].references, 2);
+    }
+
+    #[test]
+    fn resolve_keymaps() {
+        let mut pattern = Pattern::new(2, 1);
+        let cell = pattern.cell_mut(0, 0).unwrap();
+        cell.note = Note::On(40);
+        cell.instrument = 1;
+        pattern.cell_mut(1, 0).unwrap().note = Note::On(70);
+
+        // Notes below C-5 play the first sample an octave higher
+        let keymap = Keymap::from_fn(|note| match note {
+            0..=59 => KeymapEntry {
+                sample: Some(0),
+                note: note + 12,
+            },
+            _ => KeymapEntry {
+                sample: Some(1),
+                note,
+            },
+        });
+        let samples = [sample(0), sample(1)];
+        let keymaps = [Instrument {
+            keymap,
+            ..Default::default()
+        }];
+        let usage = sample_usage(&samples, &[pattern], &[0], Instruments::Keymaps(&keymaps));
+
+        assert_eq!(usage[0].notes, BTreeSet::from([52]));
+        assert_eq!(usage[1].notes, BTreeSet::from([70]));
+    }
+}
@@ -12,9 +12,14 @@ pub mod exporter;
 pub mod fmt;
 pub mod interface;
 pub(crate) mod log;
+pub mod midi;
 pub mod parser;
+pub mod render;
 
-pub use crate::fmt::loader::{identify_module, load_module, Format};
+pub use crate::fmt::loader::{
+    identify_compression, identify_module, load_module, Compression, Format,
+};
+pub use crate::fmt::scan::{scan_embedded_modules, EmbeddedModule};
 pub use crate::interface::name::{SampleNamer, SampleNamerTrait};
 pub use interface::{Error, Module, Sample};
 
@@ -0,0 +1,407 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Export the patterns of a module as a Standard MIDI File.
+//!
+//! Each pattern channel that plays a note becomes a MIDI track.
+//! Rows are placed on a grid of 4 rows per beat,
+//! so changes to the speed and tempo are written as MIDI tempo events.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::interface::duration::Sequencer;
+use crate::interface::pattern::{Cell, Note};
+use crate::interface::{Error, Module};
+use crate::render::effects::{commands, Command, Memory};
+
+/// MIDI ticks per quarter note
+const DIVISION: u16 = 96;
+
+/// Rows per quarter note
+const ROWS_PER_BEAT: u32 = 4;
+
+/// MIDI ticks per row
+const TICKS_PER_ROW: f64 = DIVISION as f64 / ROWS_PER_BEAT as f64;
+
+/// Velocity of notes when the module doesn't store a volume
+const DEFAULT_VELOCITY: u8 = 127;
+
+/// The General MIDI percussion channel, which is skipped
+const PERCUSSION: u8 = 9;
+
+/// Struct to export the patterns of a module as a Standard MIDI File (format 1).
+///
+/// Instruments (or samples, if the module doesn't have instruments)
+/// are mapped to MIDI programs, which can be changed at runtime.
+#[derive(Default)]
+pub struct MidiExporter {
+    /// Program of each instrument number, as it appears in the patterns
+    programs: HashMap<u8, u8>,
+
+    /// Program of instruments that aren't mapped
+    default_program: u8,
+}
+
+impl MidiExporter {
+    /// Play ``instrument`` with a General MIDI ``program`` (0 - 127)
+    pub fn map_program(&mut self, instrument: u8, program: u8) {
+        self.programs.insert(instrument, program.min(127));
+    }
+
+    /// Change the program of instruments that aren't mapped
+    pub fn change_default_program(&mut self, program: u8) {
+        self.default_program = program.min(127);
+    }
+
+    fn program(&self, instrument: u8) -> u8 {
+        self.programs
+            .get(&instrument)
+            .copied()
+            .unwrap_or(self.default_program)
+    }
+
+    /// Export to a new file
+    pub fn export_to_file(&self, module: &dyn Module, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut file = fs::File::options()
+            .create_new(true)
+            .write(true)
+            .open(path.as_ref())
+            .map(BufWriter::new)?;
+
+        self.export(module, &mut file)?;
+        Ok(file.flush()?)
+    }
+
+    /// Write the module's song as a Standard MIDI File.
+    ///
+    /// Returns an error if the module doesn't have any pattern data.
+    pub fn export(&self, module: &dyn Module, writer: &mut dyn Write) -> Result<(), Error> {
+        let Some(song) = module.song() else {
+            return Err(Error::unsupported(
+                "Module doesn't have any pattern data to export",
+            ));
+        };
+
+        let mut sequencer = Sequencer::new(song, module.patterns());
+        let mut conductor = Track::new(module.name());
+        let mut channels: Vec<Channel> = (0..song.channels).map(|_| Channel::default()).collect();
+        let mut position = 0.0;
+        let mut tempo = 0;
+
+        while let Some(row) = sequencer.next_row() {
+            let ticks_per_tick = TICKS_PER_ROW / row.speed as f64;
+            let time = |tick: u32| (position + tick as f64 * ticks_per_tick).round() as u64;
+
+            let cells: Vec<Cell> = (0..channels.len())
+                .map(|index| row.cells.get(index).copied().unwrap_or_default())
+                .collect();
+
+            for (channel, cell) in channels.iter_mut().zip(&cells) {
+                channel.commands = commands(song.effects, cell, &mut channel.memory);
+            }
+
+            for tick in 0..row.ticks {
+                let seconds = sequencer.tick_length(&row, tick);
+                let micros = (seconds * (row.speed * ROWS_PER_BEAT) as f64 * 1e6).round() as u32;
+                let micros = micros.min(0xFF_FFFF);
+
+                if micros != tempo {
+                    tempo = micros;
+                    conductor.tempo(time(tick), tempo);
+                }
+
+                for (index, (channel, cell)) in channels.iter_mut().zip(&cells).enumerate() {
+                    let time = time(tick);
+                    let trigger = channel.trigger();
+
+                    if tick == trigger {
+                        match cell.note {
+                            Note::On(note) => {
+                                let velocity = channel.velocity(module, cell.instrument, note);
+                                let program = self.program(channel.instrument);
+                                channel.note_on(time, midi_channel(index), note, velocity, program);
+                            }
+                            Note::Off | Note::Cut | Note::Fade => channel.note_off(time),
+                            Note::None => (),
+                        }
+                    }
+
+                    let stop = channel.commands.iter().any(|command| match command {
+                        Command::NoteCut(at) | Command::KeyOff(at) => tick == *at as u32,
+                        _ => false,
+                    });
+
+                    if stop && tick >= trigger {
+                        channel.note_off(time);
+                    }
+                }
+            }
+
+            position += row.ticks as f64 * ticks_per_tick;
+        }
+
+        let end = position.round() as u64;
+
+        let tracks: Vec<Track> = channels
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, mut channel)| {
+                channel.note_off(end);
+                let mut track = channel.track?;
+                track.name(&format!("Channel {}", index + 1));
+                Some(track)
+            })
+            .collect();
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"MThd");
+        buf.extend_from_slice(&6u32.to_be_bytes());
+        buf.extend_from_slice(&1u16.to_be_bytes());
+        buf.extend_from_slice(&(tracks.len() as u16 + 1).to_be_bytes());
+        buf.extend_from_slice(&DIVISION.to_be_bytes());
+
+        for track in std::iter::once(conductor).chain(tracks) {
+            track.write(end, &mut buf);
+        }
+
+        Ok(writer.write_all(&buf)?)
+    }
+}
+
+/// MIDI channel of a pattern channel, skipping the percussion channel
+fn midi_channel(index: usize) -> u8 {
+    match (index % 15) as u8 {
+        channel if channel >= PERCUSSION => channel + 1,
+        channel => channel,
+    }
+}
+
+/// Conversion state of a pattern channel
+#[derive(Default)]
+struct Channel {
+    /// Created once the channel plays its first note
+    track: Option<Track>,
+    memory: Memory,
+    /// Commands of the current row
+    commands: Vec<Command>,
+    instrument: u8,
+    /// Program playing on the MIDI channel
+    program: Option<u8>,
+    /// 0 - 64
+    volume: Option<u8>,
+    /// The note that is playing, and its MIDI channel
+    playing: Option<(u8, u8)>,
+}
+
+impl Channel {
+    /// Tick of the row where the note is played
+    fn trigger(&self) -> u32 {
+        self.commands
+            .iter()
+            .find_map(|command| match command {
+                Command::NoteDelay(delay) => Some(*delay as u32),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    /// Velocity from the volume column, or the default volume of the sample
+    fn velocity(&mut self, module: &dyn Module, instrument: u8, note: u8) -> u8 {
+        if instrument != 0 {
+            self.instrument = instrument;
+            self.volume = default_volume(module, instrument, note);
+        }
+
+        if let Some(volume) = self.commands.iter().find_map(|command| match command {
+            Command::Volume(volume) => Some(*volume),
+            _ => None,
+        }) {
+            self.volume = Some(volume);
+        }
+
+        self.volume.map_or(DEFAULT_VELOCITY, |volume| {
+            ((volume.min(64) as u32 * 127 + 32) / 64) as u8
+        })
+    }
+
+    fn note_on(&mut self, time: u64, channel: u8, note: u8, velocity: u8, program: u8) {
+        self.note_off(time);
+
+        // A velocity of 0 is a note off
+        if velocity == 0 {
+            return;
+        }
+
+        let track = self.track.get_or_insert_with(Track::default);
+
+        if self.program != Some(program) {
+            self.program = Some(program);
+            track.event(time, &[0xC0 | channel, program]);
+        }
+
+        track.event(time, &[0x90 | channel, note, velocity]);
+        self.playing = Some((note, channel));
+    }
+
+    fn note_off(&mut self, time: u64) {
+        let (Some((note, channel)), Some(track)) = (self.playing.take(), &mut self.track) else {
+            return;
+        };
+
+        track.event(time, &[0x80 | channel, note, 0x40]);
+    }
+}
+
+/// Default volume of the sample an instrument plays at ``note``
+fn default_volume(module: &dyn Module, instrument: u8, note: u8) -> Option<u8> {
+    let index = instrument.checked_sub(1)?;
+
+    let index_raw = match module.instruments() {
+        [] => index as u16,
+        instruments => instruments.get(index as usize)?.keymap.sample(note)?.0,
+    };
+
+    module
+        .samples()
+        .iter()
+        .find(|smp| smp.index_raw == index_raw)
+        .map(|smp| smp.meta.volume)
+}
+
+/// Events of a MIDI track, sorted by time
+#[derive(Default)]
+struct Track {
+    events: Vec<(u64, Vec<u8>)>,
+}
+
+impl Track {
+    fn new(name: &str) -> Self {
+        let mut track = Self::default();
+        track.name(name);
+        track
+    }
+
+    fn event(&mut self, time: u64, data: &[u8]) {
+        self.events.push((time, data.to_vec()));
+    }
+
+    /// The name comes before every other event
+    fn name(&mut self, name: &str) {
+        self.events
+            .insert(0, (0, meta_event(0x03, name.trim().as_bytes())));
+    }
+
+    /// Microseconds per quarter note
+    fn tempo(&mut self, time: u64, micros: u32) {
+        self.events
+            .push((time, meta_event(0x51, &micros.to_be_bytes()[1..])));
+    }
+
+    fn write(mut self, end: u64, buf: &mut Vec<u8>) {
+        self.events.push((end, meta_event(0x2F, &[])));
+
+        // Events are added in order, apart from note offs that end the song
+        self.events.sort_by_key(|(time, _)| *time);
+
+        let mut data = Vec::new();
+        let mut last = 0;
+
+        for (time, event) in self.events {
+            write_vlq(time - last, &mut data);
+            data.extend_from_slice(&event);
+            last = time;
+        }
+
+        buf.extend_from_slice(b"MTrk");
+        buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&data);
+    }
+}
+
+fn meta_event(kind: u8, data: &[u8]) -> Vec<u8> {
+    let mut event = vec![0xFF, kind];
+    write_vlq(data.len() as u64, &mut event);
+    event.extend_from_slice(data);
+    event
+}
+
+/// Variable length quantity, 7 bits per byte, most significant first
+fn write_vlq(value: u64, buf: &mut Vec<u8>) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut value = value >> 7;
+
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+
+    buf.extend(bytes.iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::fmt::fmt_mod::MOD;
+
+    #[test]
+    fn vlq() {
+        let encode = |value| {
+            let mut buf = Vec::new();
+            write_vlq(value, &mut buf);
+            buf
+        };
+
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(0x7F), [0x7F]);
+        assert_eq!(encode(0x80), [0x81, 0x00]);
+        assert_eq!(encode(0x0FFF_FFFF), [0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn export_mod() {
+        let mut module = b"midi".to_vec();
+        module.resize(1084, 0);
+        module[20 + 22..20 + 24].copy_from_slice(&1u16.to_be_bytes()); // 2 byte sample
+        module[20 + 25] = 64;
+        module[950] = 1;
+        module[1080..1084].copy_from_slice(b"M.K.");
+
+        // C-2 (middle C) with sample 1 at half volume, cut on the 3rd tick of the next row
+        let mut pattern = [0u8; 1024];
+        pattern[..4].copy_from_slice(&[0x01, 0xAC, 0x1C, 0x20]);
+        pattern[16..20].copy_from_slice(&[0x00, 0x00, 0x0E, 0xC2]);
+        module.extend_from_slice(&pattern);
+        module.extend_from_slice(&[0u8; 2]);
+
+        let module = MOD::load(&mut Cursor::new(module)).unwrap();
+
+        let mut exporter = MidiExporter::default();
+        exporter.map_program(1, 33);
+
+        let mut midi = Vec::new();
+        exporter.export(module.as_ref(), &mut midi).unwrap();
+
+        // A conductor track, and one track for the first channel
+        assert_eq!(&midi[..4], b"MThd");
+        assert_eq!(&midi[10..14], &[0, 2, 0, 96]);
+
+        let find = |event: &[u8]| midi.windows(event.len()).position(|w| w == event);
+
+        // 4 rows of 6 ticks at 125 bpm last 480000 microseconds
+        assert!(find(&[0xFF, 0x51, 0x03, 0x07, 0x53, 0x00]).is_some());
+
+        // The note is played for a row and 2 ticks, which is 24 + 8 MIDI ticks
+        let program = find(&[0xC0, 33]).unwrap();
+        assert_eq!(midi[program + 2..program + 6], [0x00, 0x90, 60, 64]);
+        assert_eq!(midi[program + 6..program + 10], [32, 0x80, 60, 0x40]);
+    }
+}
@@ -7,6 +7,8 @@
 
 pub mod bitflag;
 pub mod bytes;
+pub mod gzip;
 pub mod io;
 pub mod string;
+pub mod zip;
 pub use string::to_str_os;
@@ -0,0 +1,142 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Gzip stream reader.
+//!
+//! Used to load gzip compressed modules (MDGZ, S3GZ, XMGZ, ITGZ).
+
+use miniz_oxide::inflate::stream::{inflate, InflateState};
+use miniz_oxide::{DataFormat, MZFlush};
+
+use crate::interface::Error;
+use crate::parser::bitflag::BitFlag;
+use crate::parser::bytes::magic_header;
+use crate::parser::io::{is_magic, ByteReader, ReadSeek};
+
+/// Gzip magic followed by the "deflate" compression method
+pub const MAGIC_GZIP: [u8; 3] = [0x1F, 0x8B, 0x08];
+
+/* Header flags */
+const FLAG_HCRC: u8 = 1 << 1;
+const FLAG_EXTRA: u8 = 1 << 2;
+const FLAG_NAME: u8 = 1 << 3;
+const FLAG_COMMENT: u8 = 1 << 4;
+
+/// crc32 & uncompressed size
+const TRAILER_SIZE: usize = 8;
+
+pub fn is_gzip(buf: &[u8]) -> bool {
+    magic_header(&MAGIC_GZIP, buf)
+}
+
+/// Decompress a gzip stream in memory.
+///
+/// Fails if the decompressed data would be larger than ``limit``.
+pub fn inflate_gzip(file: &mut impl ReadSeek, limit: usize) -> Result<Vec<u8>, Error> {
+    let data = read_deflate_stream(file)?;
+
+    miniz_oxide::inflate::decompress_to_vec_with_limit(&data, limit).map_err(|e| match e.status {
+        miniz_oxide::inflate::TINFLStatus::HasMoreOutput => {
+            Error::unsupported("Gzip stream is too large")
+        }
+        _ => Error::invalid("Gzip stream could not be inflated"),
+    })
+}
+
+/// Decompress just enough of a gzip stream to fill ``buf``.
+///
+/// Returns the number of bytes written.
+pub fn inflate_gzip_head(file: &mut impl ReadSeek, buf: &mut [u8]) -> Result<usize, Error> {
+    let data = read_deflate_stream(file)?;
+    let mut state = InflateState::new_boxed(DataFormat::Raw);
+    let result = inflate(&mut state, &data, buf, MZFlush::None);
+
+    if result.status.is_err() && result.bytes_written == 0 {
+        return Err(Error::invalid("Gzip stream could not be inflated"));
+    }
+
+    Ok(result.bytes_written)
+}
+
+/// Skip the gzip header and read the raw deflate stream
+fn read_deflate_stream(file: &mut impl ReadSeek) -> Result<Vec<u8>, Error> {
+    file.set_seek_pos(0)?;
+
+    if !is_magic(file, &MAGIC_GZIP)? {
+        return Err(Error::invalid("Not a valid gzip stream"));
+    }
+
+    let flags = file.read_u8()?;
+    file.skip_bytes(4 + 1 + 1)?; // modification time, extra flags, os
+
+    if flags.contains(FLAG_EXTRA) {
+        let len = file.read_u16_le()?;
+        file.skip_bytes(len as i64)?;
+    }
+
+    // Original filename & comment are null terminated
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags.contains(flag) {
+            while file.read_u8()? != 0 {}
+        }
+    }
+
+    if flags.contains(FLAG_HCRC) {
+        file.skip_bytes(2)?;
+    }
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    data.truncate(data.len().saturating_sub(TRAILER_SIZE));
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::fmt::loader::{identify_compression, Compression};
+    use crate::fmt::Format;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut gz = MAGIC_GZIP.to_vec();
+        gz.push(FLAG_NAME);
+        gz.extend_from_slice(&[0u8; 6]);
+        gz.extend_from_slice(b"song.it\0");
+        gz.extend_from_slice(&miniz_oxide::deflate::compress_to_vec(data, 6));
+        gz.extend_from_slice(&[0u8; 4]); // crc32
+        gz.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        gz
+    }
+
+    #[test]
+    fn inflate() {
+        let data: Vec<u8> = (0..=255).cycle().take(4096).collect();
+        let mut file = Cursor::new(gzip(&data));
+
+        assert_eq!(inflate_gzip(&mut file, 4096).unwrap(), data);
+        assert!(inflate_gzip(&mut file, 4095).is_err());
+
+        let mut head = [0u8; 16];
+        assert_eq!(inflate_gzip_head(&mut file, &mut head).unwrap(), 16);
+        assert_eq!(head, data[..16]);
+    }
+
+    #[test]
+    fn identify_wrapped() {
+        let mut module = b"IMPM".to_vec();
+        module.resize(512, 0);
+        let mut file = Cursor::new(gzip(&module));
+
+        assert!(matches!(
+            identify_compression(&mut file).unwrap(),
+            (Compression::Gzip, Format::IT)
+        ));
+    }
+}
@@ -238,7 +238,7 @@ impl<R: Read + Seek> io::Read for Container<R> {
         if let Some(data_size) = self.size {
             if (cursor + buf_len as u64) > data_size {
                 // Make sure end index doesn't overflow...
-                let end = data_size.min(buf_len as u64) as usize;
+                let end = data_size.saturating_sub(cursor).min(buf_len as u64) as usize;
                 buf = &mut buf[..end];
             }
         }
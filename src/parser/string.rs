@@ -24,6 +24,19 @@ pub fn replace_carriage_return(mut buf: Box<[u8]>) -> Box<[u8]> {
     buf
 }
 
+/// Returns an owned string slice from a message that uses carriage returns as line breaks
+pub fn read_message(buf: &[u8]) -> Box<str> {
+    read_string(&replace_carriage_return(buf.into()))
+}
+
+/// Join lines of text, dropping any trailing empty lines.
+///
+/// Some formats don't have a song message, so authors hide text in their sample names.
+pub fn join_lines<T: AsRef<str>>(lines: &[T]) -> Box<str> {
+    let lines: Vec<&str> = lines.iter().map(|line| line.as_ref().trim_end()).collect();
+    lines.join("\n").trim_end().into()
+}
+
 /// Returns an owned string slice from a known size
 pub fn read_str<const N: usize>(data: &mut impl ReadSeek) -> io::Result<Box<str>> {
     Ok(read_string(&read_exact_const::<N>(data)?))
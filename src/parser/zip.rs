@@ -0,0 +1,251 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Minimal zip archive reader.
+//!
+//! Only "stored" and "deflate" entries are supported, which is all that
+//! OpenMPT (MDZ/S3Z/XMZ/ITZ) and Renoise (XRNS/XRNI) ever write.
+
+use crate::interface::Error;
+use crate::parser::io::{is_magic, ByteReader, ReadSeek};
+use crate::parser::string::read_string;
+
+pub const MAGIC_LOCAL_FILE: [u8; 4] = *b"PK\x03\x04";
+const MAGIC_CENTRAL_FILE: [u8; 4] = *b"PK\x01\x02";
+const MAGIC_END_OF_CENTRAL_DIR: [u8; 4] = *b"PK\x05\x06";
+
+/// Size of the end of central directory record, excluding the comment
+const EOCD_SIZE: u64 = 22;
+/// The archive comment can be at most 64KiB
+const EOCD_SEARCH: u64 = EOCD_SIZE + u16::MAX as u64;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+
+/// An entry listed in the central directory
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    /// Full path of the entry inside the archive
+    pub name: Box<str>,
+    pub method: u16,
+    pub compressed_size: u32,
+    pub uncompressed_size: u32,
+    /// Offset to the entry's local file header
+    pub offset: u32,
+}
+
+impl ZipEntry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    /// The entry's name without its parent directories
+    pub fn file_name(&self) -> &str {
+        self.name.rsplit('/').next().unwrap_or_default()
+    }
+
+    /// The entry's file extension in lowercase
+    pub fn extension(&self) -> Option<String> {
+        let (_, ext) = self.file_name().rsplit_once('.')?;
+        Some(ext.to_ascii_lowercase())
+    }
+}
+
+/// List the entries of a zip archive using its central directory.
+pub fn read_entries(file: &mut impl ReadSeek) -> Result<Vec<ZipEntry>, Error> {
+    let size = file
+        .size()
+        .ok_or_else(|| Error::invalid("Zip archive has an unknown size"))?;
+
+    if size < EOCD_SIZE {
+        return Err(Error::invalid("Not a valid zip archive"));
+    }
+
+    // Search backwards for the end of central directory record
+    let search_start = size.saturating_sub(EOCD_SEARCH);
+    file.set_seek_pos(search_start)?;
+    let tail = file.read_bytes((size - search_start) as usize)?;
+
+    let eocd = tail
+        .windows(4)
+        .rposition(|w| w == MAGIC_END_OF_CENTRAL_DIR)
+        .ok_or_else(|| Error::invalid("Zip archive is missing its central directory"))?;
+
+    file.set_seek_pos(search_start + eocd as u64 + 10)?;
+    let total_entries = file.read_u16_le()?;
+    file.skip_bytes(4)?; // central directory size
+    let central_dir_offset = file.read_u32_le()?;
+
+    file.set_seek_pos(central_dir_offset as u64)?;
+    let mut entries: Vec<ZipEntry> = Vec::with_capacity(total_entries as usize);
+
+    for _ in 0..total_entries {
+        if !is_magic(file, &MAGIC_CENTRAL_FILE)? {
+            return Err(Error::invalid(
+                "Zip archive has a corrupted central directory",
+            ));
+        }
+
+        file.skip_bytes(2 + 2 + 2)?; // version made by, version needed, flags
+        let method = file.read_u16_le()?;
+        file.skip_bytes(2 + 2 + 4)?; // time, date, crc32
+        let compressed_size = file.read_u32_le()?;
+        let uncompressed_size = file.read_u32_le()?;
+        let name_len = file.read_u16_le()?;
+        let extra_len = file.read_u16_le()?;
+        let comment_len = file.read_u16_le()?;
+        file.skip_bytes(2 + 2 + 4)?; // disk number, internal & external attributes
+        let offset = file.read_u32_le()?;
+
+        let name = read_string(&file.read_bytes(name_len as usize)?);
+        file.skip_bytes(extra_len as i64 + comment_len as i64)?;
+
+        entries.push(ZipEntry {
+            name,
+            method,
+            compressed_size,
+            uncompressed_size,
+            offset,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Read and decompress an entry.
+///
+/// Fails if the compressed or decompressed data would be larger than ``limit``.
+pub fn read_entry(
+    file: &mut impl ReadSeek,
+    entry: &ZipEntry,
+    limit: usize,
+) -> Result<Vec<u8>, Error> {
+    if entry.uncompressed_size as usize > limit || entry.compressed_size as usize > limit {
+        return Err(Error::unsupported("Zip entry is too large"));
+    }
+
+    // Stored entries are read as they are, so both sizes must agree
+    if entry.method == METHOD_STORED && entry.compressed_size != entry.uncompressed_size {
+        return Err(Error::invalid("Stored zip entry has mismatched sizes"));
+    }
+
+    file.set_seek_pos(entry.offset as u64)?;
+
+    if !is_magic(file, &MAGIC_LOCAL_FILE)? {
+        return Err(Error::invalid(
+            "Zip entry points to an invalid local header",
+        ));
+    }
+
+    // The local header can have a different extra field from the central directory
+    file.skip_bytes(22)?;
+    let name_len = file.read_u16_le()?;
+    let extra_len = file.read_u16_le()?;
+    file.skip_bytes(name_len as i64 + extra_len as i64)?;
+
+    let data = file.read_bytes(entry.compressed_size as usize)?;
+
+    match entry.method {
+        METHOD_STORED => Ok(data),
+        METHOD_DEFLATE => miniz_oxide::inflate::decompress_to_vec_with_limit(&data, limit)
+            .map_err(|_| Error::invalid("Zip entry could not be inflated")),
+        _ => Err(Error::unsupported(
+            "Zip entry uses an unsupported compression method",
+        )),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Build a zip archive where every entry is stored with deflate.
+    pub fn build_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip: Vec<u8> = Vec::new();
+        let mut central: Vec<u8> = Vec::new();
+
+        for (name, data) in files {
+            let offset = zip.len() as u32;
+            let deflated = miniz_oxide::deflate::compress_to_vec(data, 6);
+
+            let header = |buf: &mut Vec<u8>| {
+                buf.extend_from_slice(&METHOD_DEFLATE.to_le_bytes());
+                buf.extend_from_slice(&[0u8; 8]); // time, date, crc32
+                buf.extend_from_slice(&(deflated.len() as u32).to_le_bytes());
+                buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+                buf.extend_from_slice(&0u16.to_le_bytes()); // extra
+            };
+
+            zip.extend_from_slice(&MAGIC_LOCAL_FILE);
+            zip.extend_from_slice(&[20, 0, 0, 0]); // version, flags
+            header(&mut zip);
+            zip.extend_from_slice(name.as_bytes());
+            zip.extend_from_slice(&deflated);
+
+            central.extend_from_slice(&MAGIC_CENTRAL_FILE);
+            central.extend_from_slice(&[20, 0, 20, 0, 0, 0]); // versions, flags
+            header(&mut central);
+            central.extend_from_slice(&[0u8; 6]); // comment, disk, internal attributes
+            central.extend_from_slice(&[0u8; 4]); // external attributes
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+
+        let central_offset = zip.len() as u32;
+        zip.extend_from_slice(&central);
+        zip.extend_from_slice(&MAGIC_END_OF_CENTRAL_DIR);
+        zip.extend_from_slice(&[0u8; 4]); // disk numbers
+        zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&central_offset.to_le_bytes());
+        zip.extend_from_slice(&0u16.to_le_bytes()); // comment
+        zip
+    }
+
+    #[test]
+    fn read_deflated_entries() {
+        let first = b"hello hello hello hello".as_slice();
+        let second = [7u8; 300];
+        let zip = build_zip(&[("a/first.txt", first), ("second.bin", &second)]);
+        let mut file = Cursor::new(zip);
+
+        let entries = read_entries(&mut file).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].file_name(), "first.txt");
+        assert_eq!(entries[1].extension().as_deref(), Some("bin"));
+
+        assert_eq!(read_entry(&mut file, &entries[0], 1024).unwrap(), first);
+        assert_eq!(read_entry(&mut file, &entries[1], 1024).unwrap(), second);
+        assert!(read_entry(&mut file, &entries[1], 100).is_err());
+    }
+
+    #[test]
+    fn reject_oversized_entries() {
+        let zip = build_zip(&[("stored.bin", &[1u8; 16])]);
+        let mut file = Cursor::new(zip);
+        let entry = &read_entries(&mut file).unwrap()[0];
+
+        // The central directory claims the stored data is huge
+        let stored = ZipEntry {
+            method: METHOD_STORED,
+            compressed_size: u32::MAX,
+            uncompressed_size: 16,
+            ..entry.clone()
+        };
+        assert!(read_entry(&mut file, &stored, 1024).is_err());
+
+        let stored = ZipEntry {
+            compressed_size: 32,
+            ..stored
+        };
+        assert!(read_entry(&mut file, &stored, 1024).is_err());
+    }
+}
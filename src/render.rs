@@ -0,0 +1,267 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Play back the patterns of a module.
+//!
+//! The replayer covers the core effects of each format (slides, vibrato, tremolo, arpeggio,
+//! offsets, retriggers, note cuts & delays), instrument envelopes and new note actions.
+//! Songs stop at the end of the order list, or when they start repeating themselves.
+
+pub(crate) mod effects;
+mod frequency;
+mod player;
+mod voice;
+
+use std::borrow::Cow;
+use std::io::Write;
+
+use crate::dsp::sample::convert_to_planar;
+use crate::dsp::SampleBuffer;
+use crate::interface::audio::AudioTrait;
+use crate::interface::sample::{Channel, Depth};
+use crate::interface::{Error, Module, Sample};
+
+use player::Player;
+
+/// Voices are mixed at half volume to leave some headroom
+const MIX_GAIN: f32 = 0.5;
+
+/// Stop rendering songs that somehow play for longer than this
+const MAX_SECONDS: u32 = 30 * 60;
+
+/// Render the song of a module to a stereo buffer at ``rate`` Hz.
+///
+/// Returns an error if the module doesn't have any pattern data.
+pub fn render(module: &dyn Module, rate: u32) -> Result<SampleBuffer, Error> {
+    let mut buffers = mix(module, rate, Buses::Mix)?;
+    Ok(buffers.remove(0))
+}
+
+/// Render each channel of the song to its own stereo buffer at ``rate`` Hz.
+///
+/// The song is only played once, and every channel keeps its panning.
+/// Each stem is scaled and clamped on its own, so the stems only add up
+/// to the output of [render] when none of them clip.
+///
+/// Every stem is held in memory, use [render_stem] to render them one at a time.
+pub fn render_stems(module: &dyn Module, rate: u32) -> Result<Vec<SampleBuffer>, Error> {
+    mix(module, rate, Buses::Stems)
+}
+
+/// Render a single channel of the song to a stereo buffer at ``rate`` Hz, see [render_stems].
+///
+/// The whole song is played, so a channel that doesn't exist is silent.
+pub fn render_stem(module: &dyn Module, rate: u32, channel: usize) -> Result<SampleBuffer, Error> {
+    let mut buffers = mix(module, rate, Buses::Channel(channel))?;
+    Ok(buffers.remove(0))
+}
+
+/// Where the voices of each channel are mixed
+#[derive(Clone, Copy)]
+enum Buses {
+    /// Every channel shares a single bus
+    Mix,
+    /// Each channel has its own bus
+    Stems,
+    /// Only this channel is mixed
+    Channel(usize),
+}
+
+/// Play the song, and mix every voice into the bus of its channel.
+///
+/// A bus is only allocated once a voice plays on it,
+/// silent buses are filled in when the song ends.
+fn mix(module: &dyn Module, rate: u32, buses: Buses) -> Result<Vec<SampleBuffer>, Error> {
+    let mut player = Player::new(module, rate)?;
+    let max_frames = MAX_SECONDS as usize * rate as usize;
+
+    let bus_count = match buses {
+        Buses::Stems => player.channels(),
+        _ => 1,
+    };
+
+    let mut buffers: Vec<Option<[Vec<f32>; 2]>> = vec![None; bus_count];
+    let mut length = 0;
+
+    while let Some(frames) = player.tick() {
+        let start = length;
+        length = (start + frames).min(max_frames);
+
+        for [left, right] in buffers.iter_mut().flatten() {
+            left.resize(length, 0.0);
+            right.resize(length, 0.0);
+        }
+
+        for voice in player.voices() {
+            let bus = match buses {
+                Buses::Mix => 0,
+                Buses::Stems => voice.channel,
+                Buses::Channel(channel) if voice.channel == channel => 0,
+                Buses::Channel(_) => continue,
+            };
+
+            let [left, right] = buffers[bus].get_or_insert_with(|| silence(length));
+            voice.mix(&mut left[start..], &mut right[start..]);
+        }
+
+        if length == max_frames {
+            break;
+        }
+    }
+
+    let buffers = buffers
+        .into_iter()
+        .map(|buffer| {
+            let [mut left, mut right] = buffer.unwrap_or_else(|| silence(length));
+
+            for frame in left.iter_mut().chain(right.iter_mut()) {
+                *frame = (*frame * MIX_GAIN).clamp(-1.0, 1.0);
+            }
+
+            SampleBuffer::new(vec![left, right], rate)
+        })
+        .collect();
+
+    Ok(buffers)
+}
+
+/// A silent stereo bus
+fn silence(length: usize) -> [Vec<f32>; 2] {
+    [vec![0.0; length], vec![0.0; length]]
+}
+
+/// Write a rendered buffer as signed 16-bit audio through an exporter,
+/// e.g. [crate::exporter::AudioFormat::WAV]
+pub fn export(
+    buffer: &SampleBuffer,
+    format: &dyn AudioTrait,
+    writer: &mut dyn Write,
+) -> Result<(), Error> {
+    let (smp, pcm) = to_sample(buffer, "", 0)?;
+    format.write(&smp, Cow::Owned(pcm), writer)
+}
+
+/// Describe a rendered buffer as a signed 16-bit sample, and convert it to planar pcm
+pub(crate) fn to_sample(
+    buffer: &SampleBuffer,
+    name: &str,
+    index_raw: u16,
+) -> Result<(Sample, Vec<u8>), Error> {
+    let channel = match buffer.channels() {
+        1 => Channel::Mono,
+        2 => Channel::Stereo { interleaved: false },
+        _ => {
+            return Err(Error::audio_format(
+                "Only mono and stereo buffers can be exported",
+            ))
+        }
+    };
+
+    let pcm = convert_to_planar::<i16>(buffer);
+
+    let smp = Sample {
+        name: name.into(),
+        length: pcm.len() as u32,
+        rate: buffer.rate,
+        depth: Depth::I16,
+        channel,
+        index_raw,
+        ..Default::default()
+    };
+
+    Ok((smp, pcm))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::exporter::AudioFormat;
+    use crate::fmt::fmt_mod::MOD;
+
+    /// One pattern where the first channel plays a looping square wave at middle C
+    fn module() -> Box<dyn Module> {
+        let mut module = b"render".to_vec();
+        module.resize(1084, 0);
+        module[20 + 22..20 + 24].copy_from_slice(&16u16.to_be_bytes()); // 32 byte sample
+        module[20 + 25] = 64;
+        module[20 + 28..20 + 30].copy_from_slice(&16u16.to_be_bytes()); // loop all of it
+        module[950] = 1;
+        module[1080..1084].copy_from_slice(b"M.K.");
+
+        let mut pattern = [0u8; 1024];
+        pattern[..4].copy_from_slice(&[0x01, 0xAC, 0x10, 0x00]);
+        module.extend_from_slice(&pattern);
+        module.extend((0..32).map(|i| if i < 16 { 0x40u8 } else { 0xC0 }));
+
+        MOD::load(&mut Cursor::new(module)).unwrap()
+    }
+
+    /// Number of times ``buf`` goes from negative to positive
+    fn cycles(buf: &[f32]) -> usize {
+        buf.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count()
+    }
+
+    #[test]
+    fn render_mod() {
+        let buffer = render(module().as_ref(), 8000).unwrap();
+
+        // 64 rows of 6 ticks at 125 bpm
+        assert_eq!(buffer.channels(), 2);
+        assert_eq!(buffer.duration(), 64 * 6 * 160);
+
+        // The first channel is panned hard left
+        assert!(buffer.buf[0].iter().any(|frame| frame.abs() > 0.1));
+        assert!(buffer.buf[1].iter().all(|frame| *frame == 0.0));
+
+        let mut wav = Vec::new();
+        export(&buffer, AudioFormat::WAV.get_impl().as_ref(), &mut wav).unwrap();
+        assert_eq!(wav.len(), 44 + buffer.duration() * 4);
+    }
+
+    #[test]
+    fn loop_wraps() {
+        // The sample plays slower than the output rate, so its loop ends between two frames
+        let buffer = render(module().as_ref(), 44100).unwrap();
+        let (first, second) = buffer.buf[0].split_at(buffer.duration() / 2);
+
+        // One cycle of the square wave for every pass through the loop
+        assert!(cycles(first) > 100);
+        assert!(cycles(first).abs_diff(cycles(second)) <= 1);
+    }
+
+    #[test]
+    fn middle_c() {
+        let buffer = render(module().as_ref(), 44100).unwrap();
+        let seconds = buffer.duration() as f64 / 44100.0;
+
+        // The 32 frame square wave is played at 8363 Hz
+        let frequency = cycles(&buffer.buf[0]) as f64 / seconds;
+        assert!((frequency - 8363.0 / 32.0).abs() < 1.0, "{frequency} Hz");
+    }
+
+    #[test]
+    fn stems() {
+        let module = module();
+        let stems = render_stems(module.as_ref(), 8000).unwrap();
+        let mixed = render(module.as_ref(), 8000).unwrap();
+
+        assert_eq!(stems.len(), 4);
+        assert_eq!(stems[0].buf, mixed.buf);
+
+        for stem in &stems[1..] {
+            assert_eq!(stem.duration(), mixed.duration());
+            assert!(stem.buf.iter().flatten().all(|frame| *frame == 0.0));
+        }
+
+        for (channel, stem) in stems.iter().enumerate() {
+            let single = render_stem(module.as_ref(), 8000, channel).unwrap();
+            assert_eq!(single.buf, stem.buf);
+        }
+    }
+}
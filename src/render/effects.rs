@@ -0,0 +1,462 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::interface::pattern::{Cell, Effects};
+
+/// Effects that change how a channel sounds.
+///
+/// Effects that change the timing of the song are handled by the sequencer.
+/// Pitch slides are measured in fine units, see [super::frequency::Tuning].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Command {
+    Arpeggio(u8, u8),
+    /// Slide the pitch on every tick except the first, negative values slide down
+    Porta(f64),
+    /// Slide the pitch on the first tick
+    FinePorta(f64),
+    /// Slide towards the new note instead of playing it
+    TonePorta(f64),
+    /// ``None`` uses the previous speed or depth
+    Vibrato {
+        speed: Option<u8>,
+        depth: Option<u8>,
+        fine: bool,
+    },
+    Tremolo {
+        speed: Option<u8>,
+        depth: Option<u8>,
+    },
+    /// Change the volume on every tick except the first
+    VolumeSlide(i32),
+    /// Change the volume on the first tick
+    FineVolumeSlide(i32),
+    /// 0 - 64
+    Volume(u8),
+    /// 0 - 64
+    ChannelVolume(u8),
+    /// 0 - 255
+    Panning(u8),
+    PanSlide(i32),
+    /// Start the sample from this frame
+    Offset(u32),
+    /// Restart the sample every ``n`` ticks
+    Retrigger(u8),
+    NoteCut(u8),
+    NoteDelay(u8),
+    KeyOff(u8),
+    /// 0 - 128
+    GlobalVolume(u8),
+    GlobalVolumeSlide(i32),
+}
+
+/// Effect parameters of 0 reuse the previous parameter
+#[derive(Default, Debug, Clone, Copy)]
+pub(crate) struct Memory {
+    volume_slide: u8,
+    porta_up: u8,
+    porta_down: u8,
+    tone_porta: u8,
+    offset: u8,
+    retrigger: u8,
+    arpeggio: u8,
+    global_volume_slide: u8,
+    pan_slide: u8,
+}
+
+impl Memory {
+    fn recall(memory: &mut u8, param: u8) -> u8 {
+        if param != 0 {
+            *memory = param;
+        }
+        *memory
+    }
+}
+
+/// Convert a cell's effect and volume column.
+pub(crate) fn commands(effects: Effects, cell: &Cell, memory: &mut Memory) -> Vec<Command> {
+    let mut commands = Vec::with_capacity(2);
+
+    match effects {
+        Effects::IT | Effects::S3M => {
+            it_volume(effects, cell.volume, &mut commands);
+            it_effect(effects, cell.effect, cell.param, memory, &mut commands);
+        }
+        Effects::XM | Effects::MOD => {
+            xm_volume(cell.volume, &mut commands);
+            xm_effect(effects, cell.effect, cell.param, memory, &mut commands);
+        }
+    }
+
+    commands
+}
+
+/// ``xy`` slides up by x or down by y. ``xF`` and ``Fy`` are fine slides.
+fn it_volume_slide(param: u8, commands: &mut Vec<Command>) {
+    let (x, y) = ((param >> 4) as i32, (param & 0x0F) as i32);
+
+    let command = match (x, y) {
+        (0, y) => Command::VolumeSlide(-y),
+        (x, 0) => Command::VolumeSlide(x),
+        (x, 0xF) => Command::FineVolumeSlide(x),
+        (0xF, y) => Command::FineVolumeSlide(-y),
+        _ => return,
+    };
+
+    commands.push(command);
+}
+
+/// Impulse Tracker & Scream Tracker effects are letters, where ``A`` is 1.
+fn it_effect(
+    effects: Effects,
+    effect: u8,
+    param: u8,
+    memory: &mut Memory,
+    commands: &mut Vec<Command>,
+) {
+    let (x, y) = (param >> 4, param & 0x0F);
+    let nonzero = |value: u8| (value != 0).then_some(value);
+
+    // Pitch slides up and down share their memory
+    let porta = |memory: &mut Memory, param: u8| Memory::recall(&mut memory.porta_down, param);
+
+    let pitch_slide = |param: u8, sign: f64| match (param >> 4, param & 0x0F) {
+        (0xF, y) => Command::FinePorta(sign * 4.0 * y as f64),
+        (0xE, y) => Command::FinePorta(sign * y as f64),
+        _ => Command::Porta(sign * 4.0 * param as f64),
+    };
+
+    let command = match effect {
+        // D
+        4 => return it_volume_slide(Memory::recall(&mut memory.volume_slide, param), commands),
+        // E
+        5 => pitch_slide(porta(memory, param), -1.0),
+        // F
+        6 => pitch_slide(porta(memory, param), 1.0),
+        // G
+        7 => Command::TonePorta(4.0 * Memory::recall(&mut memory.tone_porta, param) as f64),
+        // H & U
+        8 | 21 => Command::Vibrato {
+            speed: nonzero(x),
+            depth: nonzero(y),
+            fine: effect == 21,
+        },
+        // J
+        10 => {
+            let param = Memory::recall(&mut memory.arpeggio, param);
+            Command::Arpeggio(param >> 4, param & 0x0F)
+        }
+        // K
+        11 => {
+            commands.push(Command::Vibrato {
+                speed: None,
+                depth: None,
+                fine: false,
+            });
+            return it_volume_slide(Memory::recall(&mut memory.volume_slide, param), commands);
+        }
+        // L
+        12 => {
+            let speed = memory.tone_porta;
+            commands.push(Command::TonePorta(4.0 * speed as f64));
+            return it_volume_slide(Memory::recall(&mut memory.volume_slide, param), commands);
+        }
+        // M
+        13 if effects == Effects::IT => Command::ChannelVolume(param.min(64)),
+        // O
+        15 => Command::Offset(Memory::recall(&mut memory.offset, param) as u32 * 256),
+        // P, ``x0`` pans left and ``0y`` pans right
+        16 if effects == Effects::IT => {
+            let param = Memory::recall(&mut memory.pan_slide, param);
+            match (param >> 4, param & 0x0F) {
+                (0, y) => Command::PanSlide(4 * y as i32),
+                (x, 0) => Command::PanSlide(-4 * x as i32),
+                _ => return,
+            }
+        }
+        // Q
+        17 => Command::Retrigger(Memory::recall(&mut memory.retrigger, param) & 0x0F),
+        // R
+        18 => Command::Tremolo {
+            speed: nonzero(x),
+            depth: nonzero(y),
+        },
+        // S
+        19 => match x {
+            0x8 => Command::Panning(y * 17),
+            // Impulse Tracker treats SC0 as SC1
+            0xC if y == 0 && effects == Effects::IT => Command::NoteCut(1),
+            0xC if y > 0 => Command::NoteCut(y),
+            0xD => Command::NoteDelay(y),
+            _ => return,
+        },
+        // V
+        22 => match effects {
+            Effects::IT => Command::GlobalVolume(param.min(128)),
+            _ => Command::GlobalVolume(param.min(64) * 2),
+        },
+        // W
+        23 if effects == Effects::IT => {
+            let param = Memory::recall(&mut memory.global_volume_slide, param);
+            match (param >> 4, param & 0x0F) {
+                (0, y) => Command::GlobalVolumeSlide(-(y as i32)),
+                (x, 0) => Command::GlobalVolumeSlide(x as i32),
+                _ => return,
+            }
+        }
+        // X
+        24 => match effects {
+            Effects::IT => Command::Panning(param),
+            _ => Command::Panning((param.min(0x80) as u16 * 255 / 0x80) as u8),
+        },
+        _ => return,
+    };
+
+    commands.push(command);
+}
+
+/// Scream Tracker only has volumes in its volume column.
+///
+/// Impulse Tracker packs several effects into ranges:
+///
+/// * 0 - 64: volume
+/// * 65 - 74, 75 - 84: fine volume slide up, down
+/// * 85 - 94, 95 - 104: volume slide up, down
+/// * 105 - 114, 115 - 124: pitch slide down, up
+/// * 128 - 192: panning
+/// * 193 - 202: tone portamento
+/// * 203 - 212: vibrato depth
+fn it_volume(effects: Effects, volume: Option<u8>, commands: &mut Vec<Command>) {
+    const TONE_PORTA: [u8; 10] = [0, 1, 4, 8, 16, 32, 64, 96, 128, 255];
+
+    let Some(volume) = volume else {
+        return;
+    };
+
+    if effects == Effects::S3M {
+        commands.push(Command::Volume(volume.min(64)));
+        return;
+    }
+
+    let command = match volume {
+        0..=64 => Command::Volume(volume),
+        65..=74 => Command::FineVolumeSlide((volume - 65) as i32),
+        75..=84 => Command::FineVolumeSlide(-((volume - 75) as i32)),
+        85..=94 => Command::VolumeSlide((volume - 85) as i32),
+        95..=104 => Command::VolumeSlide(-((volume - 95) as i32)),
+        105..=114 => Command::Porta(-16.0 * (volume - 105) as f64),
+        115..=124 => Command::Porta(16.0 * (volume - 115) as f64),
+        128..=192 => Command::Panning(((volume - 128) as u16 * 255 / 64) as u8),
+        193..=202 => Command::TonePorta(4.0 * TONE_PORTA[(volume - 193) as usize] as f64),
+        203..=212 => Command::Vibrato {
+            speed: None,
+            depth: Some(volume - 203),
+            fine: false,
+        },
+        _ => return,
+    };
+
+    commands.push(command);
+}
+
+/// ``xy`` slides up by x, otherwise down by y
+fn xm_volume_slide(param: u8) -> Command {
+    match (param >> 4, param & 0x0F) {
+        (0, y) => Command::VolumeSlide(-(y as i32)),
+        (x, _) => Command::VolumeSlide(x as i32),
+    }
+}
+
+/// Fast Tracker 2 & ProTracker effects are numbers.
+///
+/// Only Fast Tracker 2 reuses previous parameters for slides.
+fn xm_effect(
+    effects: Effects,
+    effect: u8,
+    param: u8,
+    memory: &mut Memory,
+    commands: &mut Vec<Command>,
+) {
+    let (x, y) = (param >> 4, param & 0x0F);
+    let nonzero = |value: u8| (value != 0).then_some(value);
+    let xm = effects == Effects::XM;
+    let recall = |memory: &mut u8, param: u8| match xm {
+        true => Memory::recall(memory, param),
+        false => param,
+    };
+
+    let command = match effect {
+        0x0 if param != 0 => Command::Arpeggio(x, y),
+        0x1 => Command::Porta(4.0 * recall(&mut memory.porta_up, param) as f64),
+        0x2 => Command::Porta(-4.0 * recall(&mut memory.porta_down, param) as f64),
+        0x3 => Command::TonePorta(4.0 * Memory::recall(&mut memory.tone_porta, param) as f64),
+        0x4 => Command::Vibrato {
+            speed: nonzero(x),
+            depth: nonzero(y),
+            fine: false,
+        },
+        0x5 | 0x6 => {
+            commands.push(match effect {
+                0x5 => Command::TonePorta(4.0 * memory.tone_porta as f64),
+                _ => Command::Vibrato {
+                    speed: None,
+                    depth: None,
+                    fine: false,
+                },
+            });
+            xm_volume_slide(recall(&mut memory.volume_slide, param))
+        }
+        0x7 => Command::Tremolo {
+            speed: nonzero(x),
+            depth: nonzero(y),
+        },
+        0x8 => Command::Panning(param),
+        0x9 => Command::Offset(Memory::recall(&mut memory.offset, param) as u32 * 256),
+        0xA => xm_volume_slide(recall(&mut memory.volume_slide, param)),
+        0xC => Command::Volume(param.min(64)),
+        0xE => match x {
+            0x1 => Command::FinePorta(4.0 * y as f64),
+            0x2 => Command::FinePorta(-4.0 * y as f64),
+            0x8 => Command::Panning(y * 17),
+            0x9 if y > 0 => Command::Retrigger(y),
+            0xA => Command::FineVolumeSlide(y as i32),
+            0xB => Command::FineVolumeSlide(-(y as i32)),
+            0xC => Command::NoteCut(y),
+            0xD => Command::NoteDelay(y),
+            _ => return,
+        },
+        // G
+        16 if xm => Command::GlobalVolume(param.min(64) * 2),
+        // H
+        17 if xm => match xm_volume_slide(recall(&mut memory.global_volume_slide, param)) {
+            Command::VolumeSlide(slide) => Command::GlobalVolumeSlide(slide * 2),
+            _ => return,
+        },
+        // K
+        20 if xm => Command::KeyOff(param),
+        // P, ``x0`` pans right and ``0y`` pans left
+        25 if xm => match recall(&mut memory.pan_slide, param) {
+            param if param >> 4 != 0 => Command::PanSlide((param >> 4) as i32),
+            param => Command::PanSlide(-((param & 0x0F) as i32)),
+        },
+        // R
+        27 if xm => Command::Retrigger(recall(&mut memory.retrigger, param) & 0x0F),
+        // X
+        33 if xm => match x {
+            0x1 => Command::FinePorta(y as f64),
+            0x2 => Command::FinePorta(-(y as f64)),
+            _ => return,
+        },
+        _ => return,
+    };
+
+    commands.push(command);
+}
+
+/// Fast Tracker 2's volume column, 0 is stored as ``None``:
+///
+/// * 0x10 - 0x50: volume
+/// * 0x6x, 0x7x: volume slide down, up
+/// * 0x8x, 0x9x: fine volume slide down, up
+/// * 0xAx, 0xBx: vibrato speed, depth
+/// * 0xCx: panning
+/// * 0xDx, 0xEx: panning slide left, right
+/// * 0xFx: tone portamento
+fn xm_volume(volume: Option<u8>, commands: &mut Vec<Command>) {
+    let Some(volume) = volume else {
+        return;
+    };
+
+    let value = volume & 0x0F;
+
+    let command = match volume >> 4 {
+        0x1..=0x4 => Command::Volume(volume - 0x10),
+        0x5 if volume == 0x50 => Command::Volume(64),
+        0x6 => Command::VolumeSlide(-(value as i32)),
+        0x7 => Command::VolumeSlide(value as i32),
+        0x8 => Command::FineVolumeSlide(-(value as i32)),
+        0x9 => Command::FineVolumeSlide(value as i32),
+        0xA => Command::Vibrato {
+            speed: Some(value),
+            depth: None,
+            fine: false,
+        },
+        0xB => Command::Vibrato {
+            speed: None,
+            depth: Some(value),
+            fine: false,
+        },
+        0xC => Command::Panning(value * 17),
+        0xD => Command::PanSlide(-(value as i32)),
+        0xE => Command::PanSlide(value as i32),
+        0xF => Command::TonePorta(4.0 * 16.0 * value as f64),
+        _ => return,
+    };
+
+    commands.push(command);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(effect: u8, param: u8, volume: Option<u8>) -> Cell {
+        Cell {
+            effect,
+            param,
+            volume,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_commands() {
+        let mut memory = Memory::default();
+
+        // D0F is a regular slide
+        assert_eq!(
+            commands(Effects::IT, &cell(4, 0x0F, None), &mut memory),
+            [Command::VolumeSlide(-15)]
+        );
+
+        // D00 reuses it, and the volume column comes first
+        assert_eq!(
+            commands(Effects::IT, &cell(4, 0x00, Some(130)), &mut memory),
+            [Command::Panning(7), Command::VolumeSlide(-15)]
+        );
+
+        // EF2 is a fine slide, and F00 reuses its parameter
+        assert_eq!(
+            commands(Effects::IT, &cell(5, 0xF2, None), &mut memory),
+            [Command::FinePorta(-8.0)]
+        );
+        assert_eq!(
+            commands(Effects::IT, &cell(6, 0x00, None), &mut memory),
+            [Command::FinePorta(8.0)]
+        );
+    }
+
+    #[test]
+    fn xm_commands() {
+        let mut memory = Memory::default();
+
+        assert_eq!(
+            commands(Effects::XM, &cell(0xA, 0x20, Some(0x50)), &mut memory),
+            [Command::Volume(64), Command::VolumeSlide(2)]
+        );
+        assert_eq!(
+            commands(Effects::XM, &cell(0xA, 0x00, None), &mut memory),
+            [Command::VolumeSlide(2)]
+        );
+
+        // ProTracker doesn't remember slides
+        assert_eq!(
+            commands(Effects::MOD, &cell(0xA, 0x00, None), &mut memory),
+            [Command::VolumeSlide(0)]
+        );
+        assert!(commands(Effects::MOD, &cell(20, 0x00, None), &mut memory).is_empty());
+    }
+}
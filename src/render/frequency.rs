@@ -0,0 +1,133 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::interface::pattern::{Effects, NOTE_MIDDLE_C};
+use crate::interface::song::{FrequencyMode, Song};
+
+/// Amiga period of the note that plays a sample at its sample rate
+const BASE_PERIOD: f64 = 428.0;
+
+/// Slides are measured in 1/64th of a semitone in linear mode.
+///
+/// A regular slide of ``xx`` moves the pitch by ``4 * xx`` units per tick,
+/// and extra fine slides move it by ``xx`` units.
+const UNITS_PER_SEMITONE: f64 = 64.0;
+
+/// Converts notes and pitch slides to frequencies.
+///
+/// A pitch is an Amiga period in Amiga mode, where higher values sound lower,
+/// or a note in 1/64th semitones in linear mode.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Tuning {
+    mode: FrequencyMode,
+    /// Sample rates are multiplied by this before they're played
+    rate_scale: f64,
+}
+
+impl Tuning {
+    pub fn new(song: &Song) -> Self {
+        Self {
+            mode: song.frequencies,
+            // MOD sample rates are those of period 214, an octave above middle C
+            rate_scale: match song.effects {
+                Effects::MOD => 0.5,
+                _ => 1.0,
+            },
+        }
+    }
+
+    pub fn pitch(&self, note: u8) -> f64 {
+        // Middle C plays a sample at its sample rate
+        let semitones = note as f64 - NOTE_MIDDLE_C as f64;
+
+        match self.mode {
+            FrequencyMode::Amiga => BASE_PERIOD * 2f64.powf(-semitones / 12.0),
+            FrequencyMode::Linear => semitones * UNITS_PER_SEMITONE,
+        }
+    }
+
+    /// Slide the pitch up by ``units``, or down if negative.
+    pub fn slide(&self, pitch: f64, units: f64) -> f64 {
+        match self.mode {
+            FrequencyMode::Amiga => (pitch - units / 4.0).max(1.0),
+            FrequencyMode::Linear => pitch + units,
+        }
+    }
+
+    /// Slide ``pitch`` towards ``target`` without going past it.
+    pub fn slide_towards(&self, pitch: f64, target: f64, units: f64) -> f64 {
+        let up = match self.mode {
+            FrequencyMode::Amiga => target < pitch,
+            FrequencyMode::Linear => target > pitch,
+        };
+
+        match up {
+            true => self.slide(pitch, units),
+            false => self.slide(pitch, -units),
+        }
+        .clamp(pitch.min(target), pitch.max(target))
+    }
+
+    /// Frequency of a sample played at ``pitch``.
+    ///
+    /// ``offset`` is a temporary slide (e.g. vibrato),
+    /// and ``semitones`` are added on top (e.g. arpeggio).
+    pub fn frequency(&self, rate: u32, pitch: f64, offset: f64, semitones: f64) -> f64 {
+        let rate = rate as f64 * self.rate_scale;
+        let transpose = 2f64.powf(semitones / 12.0);
+
+        match self.mode {
+            FrequencyMode::Amiga => rate * BASE_PERIOD / self.slide(pitch, offset) * transpose,
+            FrequencyMode::Linear => {
+                let pitch = pitch + offset;
+                rate * 2f64.powf(pitch / (12.0 * UNITS_PER_SEMITONE)) * transpose
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuning(frequencies: FrequencyMode, effects: Effects) -> Tuning {
+        Tuning::new(&Song {
+            frequencies,
+            effects,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn octaves() {
+        for mode in [FrequencyMode::Amiga, FrequencyMode::Linear] {
+            let tuning = tuning(mode, Effects::IT);
+            let frequency = |note| tuning.frequency(8363, tuning.pitch(note), 0.0, 0.0).round();
+
+            assert_eq!(frequency(60), 8363.0);
+            assert_eq!(frequency(72), 8363.0 * 2.0);
+            assert_eq!(frequency(48), (8363.0 / 2.0f64).round());
+        }
+
+        // ProTracker's C-2 is middle C, and plays a sample at half its rate
+        let tuning = tuning(FrequencyMode::Amiga, Effects::MOD);
+        assert_eq!(tuning.pitch(NOTE_MIDDLE_C), BASE_PERIOD);
+        assert_eq!(tuning.frequency(16726, BASE_PERIOD, 0.0, 0.0), 8363.0);
+    }
+
+    #[test]
+    fn slides() {
+        // 16 fine units per tick is a regular slide of 4
+        let amiga = tuning(FrequencyMode::Amiga, Effects::MOD);
+        assert_eq!(amiga.slide(428.0, 16.0), 424.0);
+        assert_eq!(amiga.slide_towards(428.0, 426.0, 16.0), 426.0);
+
+        let linear = tuning(FrequencyMode::Linear, Effects::XM);
+        assert_eq!(linear.slide(0.0, -16.0), -16.0);
+        assert_eq!(linear.slide_towards(0.0, -64.0, 16.0), -16.0);
+    }
+}
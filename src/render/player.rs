@@ -0,0 +1,464 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::sync::Arc;
+
+use super::effects::{commands, Command, Memory};
+use super::frequency::Tuning;
+use super::voice::{Decoded, Voice};
+use crate::interface::duration::{Row, Sequencer};
+use crate::interface::instrument::{Instrument, NewNoteAction};
+use crate::interface::pattern::{Cell, Effects, Note};
+use crate::interface::song::Song;
+use crate::interface::{Error, Module};
+use crate::warn;
+
+/// Background voices left behind by new note actions, the oldest are cut first
+const MAX_BACKGROUND_VOICES: usize = 64;
+
+/// Vibrato and tremolo waveforms repeat every 64 steps
+#[derive(Default, Clone, Copy)]
+struct Oscillator {
+    speed: u8,
+    depth: u8,
+    phase: u8,
+}
+
+impl Oscillator {
+    fn set(&mut self, speed: Option<u8>, depth: Option<u8>) {
+        self.speed = speed.unwrap_or(self.speed);
+        self.depth = depth.unwrap_or(self.depth);
+    }
+
+    /// Sine wave from -1.0 to 1.0 scaled by the depth
+    fn value(&self) -> f64 {
+        (TAU * self.phase as f64 / 64.0).sin() * self.depth as f64
+    }
+
+    fn advance(&mut self) {
+        self.phase = (self.phase + self.speed) % 64;
+    }
+}
+
+/// Playback state of a pattern channel
+#[derive(Default)]
+struct Channel {
+    voice: Option<Voice>,
+    memory: Memory,
+    /// Commands of the current row
+    commands: Vec<Command>,
+    cell: Cell,
+    /// Tick of the row where the note is triggered
+    trigger: u32,
+    instrument: u8,
+    /// See [Tuning] for how pitches are measured
+    pitch: f64,
+    /// Tone portamento target and speed
+    target: Option<f64>,
+    porta_speed: f64,
+    /// 0 - 64
+    volume: i32,
+    /// 0 - 64
+    channel_volume: i32,
+    /// 0 - 255
+    panning: i32,
+    vibrato: Oscillator,
+    tremolo: Oscillator,
+    /// Changes that only last for the current tick
+    vibrato_offset: f64,
+    tremolo_offset: i32,
+    arpeggio: u8,
+}
+
+/// Plays a song tick by tick
+pub(super) struct Player<'a> {
+    song: &'a Song,
+    sequencer: Sequencer<'a>,
+    row: Option<Row<'a>>,
+    tick: u32,
+    tuning: Tuning,
+    rate: u32,
+    /// Decoded samples, keyed by their raw index
+    samples: HashMap<u16, Arc<Decoded>>,
+    instruments: Box<[Arc<Instrument>]>,
+    channels: Vec<Channel>,
+    background: Vec<Voice>,
+    /// 0 - 128
+    global_volume: i32,
+    /// Fraction of a frame carried over to the next tick
+    remainder: f64,
+}
+
+impl<'a> Player<'a> {
+    pub fn new(module: &'a dyn Module, rate: u32) -> Result<Self, Error> {
+        let Some(song) = module.song() else {
+            return Err(Error::unsupported(
+                "Module doesn't have any pattern data to render",
+            ));
+        };
+
+        if rate == 0 {
+            return Err(Error::invalid("Sample rate cannot be zero"));
+        }
+
+        let mut samples = HashMap::new();
+
+        for smp in module.samples() {
+            let decoded = match module.pcm(smp) {
+                Ok(pcm) => Decoded::new(smp, &pcm),
+                Err(_e) => {
+                    warn!("Could not decode sample {}: {}", smp.index_raw(), _e);
+                    continue;
+                }
+            };
+
+            if let Some(decoded) = decoded {
+                samples.insert(smp.index_raw, Arc::new(decoded));
+            }
+        }
+
+        let channels = (0..song.channels as usize)
+            .map(|channel| Channel {
+                channel_volume: 64,
+                panning: song.panning.get(channel).copied().unwrap_or(128) as i32,
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(Self {
+            song,
+            sequencer: Sequencer::new(song, module.patterns()),
+            row: None,
+            tick: 0,
+            tuning: Tuning::new(song),
+            rate,
+            samples,
+            instruments: module.instruments().iter().cloned().map(Arc::new).collect(),
+            channels,
+            background: Vec::new(),
+            global_volume: song.global_volume.min(128) as i32,
+            remainder: 0.0,
+        })
+    }
+
+    /// Number of pattern channels
+    pub fn channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Every voice that can be heard
+    pub fn voices(&mut self) -> impl Iterator<Item = &mut Voice> {
+        self.channels
+            .iter_mut()
+            .filter_map(|channel| channel.voice.as_mut())
+            .chain(self.background.iter_mut())
+            .filter(|voice| voice.active)
+    }
+
+    /// Play the next tick, and return how many frames it lasts.
+    ///
+    /// Returns ``None`` once the song has ended.
+    pub fn tick(&mut self) -> Option<usize> {
+        if self.row.as_ref().is_none_or(|row| self.tick >= row.ticks) {
+            self.row = Some(self.sequencer.next_row()?);
+            self.tick = 0;
+            self.start_row();
+        }
+
+        let row = self.row.as_ref()?;
+        let seconds = self.sequencer.tick_length(row, self.tick);
+        let (speed, tick) = (row.speed, self.tick);
+
+        for channel in 0..self.channels.len() {
+            self.process(channel, tick, speed);
+        }
+
+        self.background.retain(|voice| voice.active);
+        self.background.iter_mut().for_each(Voice::tick);
+        self.tick += 1;
+
+        let frames = seconds * self.rate as f64 + self.remainder;
+        self.remainder = frames.fract();
+
+        Some(frames as usize)
+    }
+
+    /// Read the cells of a new row
+    fn start_row(&mut self) {
+        let Some(row) = &self.row else {
+            return;
+        };
+
+        let effects = self.song.effects;
+
+        for (index, state) in self.channels.iter_mut().enumerate() {
+            let cell = row.cells.get(index).copied().unwrap_or_default();
+
+            state.commands = commands(effects, &cell, &mut state.memory);
+            state.cell = cell;
+            state.trigger = state
+                .commands
+                .iter()
+                .find_map(|command| match command {
+                    Command::NoteDelay(delay) => Some(*delay as u32),
+                    _ => None,
+                })
+                .unwrap_or(0);
+        }
+    }
+
+    fn process(&mut self, index: usize, tick: u32, speed: u32) {
+        if tick == self.channels[index].trigger {
+            self.trigger(index);
+        }
+
+        let tuning = self.tuning;
+        let mut global_volume = self.global_volume;
+        let state = &mut self.channels[index];
+
+        state.vibrato_offset = 0.0;
+        state.tremolo_offset = 0;
+        state.arpeggio = 0;
+
+        // Fine effects run once, on the tick the note is triggered
+        let first = tick == state.trigger;
+        let regular = !tick.is_multiple_of(speed);
+
+        for command in state.commands.clone() {
+            match command {
+                Command::Arpeggio(x, y) => {
+                    state.arpeggio = [0, x, y][tick as usize % 3];
+                }
+                Command::Porta(units) if regular => {
+                    state.pitch = tuning.slide(state.pitch, units);
+                }
+                Command::FinePorta(units) if first => {
+                    state.pitch = tuning.slide(state.pitch, units);
+                }
+                Command::TonePorta(speed) => {
+                    if speed > 0.0 {
+                        state.porta_speed = speed;
+                    }
+
+                    if let (Some(target), true) = (state.target, regular) {
+                        state.pitch = tuning.slide_towards(state.pitch, target, state.porta_speed);
+                    }
+                }
+                Command::Vibrato { speed, depth, fine } => {
+                    state.vibrato.set(speed, depth);
+
+                    let scale = if fine { 2.0 } else { 8.0 };
+                    state.vibrato_offset = state.vibrato.value() * scale;
+
+                    if regular {
+                        state.vibrato.advance();
+                    }
+                }
+                Command::Tremolo { speed, depth } => {
+                    state.tremolo.set(speed, depth);
+                    state.tremolo_offset = (state.tremolo.value() * 4.0) as i32;
+
+                    if regular {
+                        state.tremolo.advance();
+                    }
+                }
+                Command::VolumeSlide(slide) if regular => {
+                    state.volume = (state.volume + slide).clamp(0, 64);
+                }
+                Command::FineVolumeSlide(slide) if first => {
+                    state.volume = (state.volume + slide).clamp(0, 64);
+                }
+                Command::Volume(volume) if first => state.volume = volume as i32,
+                Command::ChannelVolume(volume) if first => state.channel_volume = volume as i32,
+                Command::Panning(panning) if first => state.panning = panning as i32,
+                Command::PanSlide(slide) if regular => {
+                    state.panning = (state.panning + slide).clamp(0, 255);
+                }
+                Command::Retrigger(every) if tick > 0 && tick.is_multiple_of(every as u32) => {
+                    if let Some(voice) = &mut state.voice {
+                        voice.retrigger();
+                    }
+                }
+                Command::NoteCut(at) if tick == at as u32 => state.volume = 0,
+                Command::KeyOff(at) if tick == at as u32 => {
+                    if let Some(voice) = &mut state.voice {
+                        release(voice, self.song.effects);
+                    }
+                }
+                Command::GlobalVolume(volume) if first => global_volume = volume as i32,
+                Command::GlobalVolumeSlide(slide) if regular => {
+                    global_volume = (global_volume + slide).clamp(0, 128);
+                }
+                _ => (),
+            }
+        }
+
+        self.global_volume = global_volume;
+        self.update(index);
+    }
+
+    /// Play the note, instrument and volume of the cell
+    fn trigger(&mut self, index: usize) {
+        let effects = self.song.effects;
+        let state = &mut self.channels[index];
+        let cell = state.cell;
+
+        if cell.instrument != 0 {
+            state.instrument = cell.instrument;
+        }
+
+        match cell.note {
+            Note::On(note) => {
+                let Some((sample, note, instrument)) =
+                    resolve(&self.instruments, state.instrument, note)
+                else {
+                    return;
+                };
+
+                let Some(sample) = self.samples.get(&sample).cloned() else {
+                    return;
+                };
+
+                let pitch = self.tuning.pitch(note);
+                let porta = state
+                    .commands
+                    .iter()
+                    .any(|command| matches!(command, Command::TonePorta(_)));
+
+                if porta && state.voice.as_ref().is_some_and(|voice| voice.active) {
+                    state.target = Some(pitch);
+                } else {
+                    let offset = state
+                        .commands
+                        .iter()
+                        .find_map(|command| match command {
+                            Command::Offset(offset) => Some(*offset as usize),
+                            _ => None,
+                        })
+                        .unwrap_or(0);
+
+                    if let Some(voice) = state.voice.take() {
+                        background(&mut self.background, voice, effects);
+                    }
+
+                    if let Some(panning) = sample.meta.panning {
+                        state.panning = panning as i32;
+                    }
+
+                    if cell.instrument != 0 {
+                        state.volume = sample.meta.volume.min(64) as i32;
+                    }
+
+                    state.voice = Some(Voice::new(sample, instrument, index, offset));
+                    state.pitch = pitch;
+                    state.target = None;
+                    state.vibrato.phase = 0;
+                    state.tremolo.phase = 0;
+                }
+            }
+            Note::Off => {
+                if let Some(voice) = &mut state.voice {
+                    release(voice, effects);
+                }
+            }
+            Note::Cut => state.voice = None,
+            Note::Fade => {
+                if let Some(voice) = &mut state.voice {
+                    voice.fading = true;
+                }
+            }
+            Note::None => {
+                // An instrument on its own resets the volume
+                if let (Some(voice), true) = (&state.voice, cell.instrument != 0) {
+                    state.volume = voice.sample.meta.volume.min(64) as i32;
+                }
+            }
+        }
+    }
+
+    /// Pass the channel's state to its voice
+    fn update(&mut self, index: usize) {
+        let global_volume = self.global_volume as f32 / 128.0;
+        let state = &mut self.channels[index];
+
+        let Some(voice) = &mut state.voice else {
+            return;
+        };
+
+        voice.tick();
+
+        let semitones = state.arpeggio as f64 + voice.pitch_offset();
+        let frequency = self.tuning.frequency(
+            voice.sample.rate,
+            state.pitch,
+            state.vibrato_offset,
+            semitones,
+        );
+
+        let volume = (state.volume + state.tremolo_offset).clamp(0, 64) as f32 / 64.0;
+        let channel_volume = state.channel_volume as f32 / 64.0;
+        let sample_volume = voice.sample.meta.global_volume.min(64) as f32 / 64.0;
+
+        voice.step = frequency / self.rate as f64;
+        voice.volume = volume * channel_volume * sample_volume * global_volume;
+        voice.panning = state.panning as f32;
+    }
+}
+
+/// Find the raw sample index and note played by an instrument.
+///
+/// Instrument numbers refer to samples directly if the module doesn't have instruments.
+fn resolve(
+    instruments: &[Arc<Instrument>],
+    instrument: u8,
+    note: u8,
+) -> Option<(u16, u8, Option<Arc<Instrument>>)> {
+    let index = instrument.checked_sub(1)?;
+
+    if instruments.is_empty() {
+        return Some((index as u16, note, None));
+    }
+
+    let instrument = instruments.get(index as usize)?;
+    let (sample, note) = instrument.keymap.sample(note)?;
+
+    Some((sample, note, Some(instrument.clone())))
+}
+
+/// Release a note, notes without a volume envelope stop or fade out
+fn release(voice: &mut Voice, effects: Effects) {
+    voice.released = true;
+
+    if !voice.has_volume_envelope() {
+        match (effects, &voice.instrument) {
+            (Effects::IT, Some(_)) => voice.fading = true,
+            _ => voice.active = false,
+        }
+    }
+}
+
+/// Keep a voice playing in the background according to its new note action
+fn background(voices: &mut Vec<Voice>, mut voice: Voice, effects: Effects) {
+    if effects != Effects::IT || !voice.active {
+        return;
+    }
+
+    match voice.nna() {
+        NewNoteAction::Cut => return,
+        NewNoteAction::Continue => (),
+        NewNoteAction::Off => release(&mut voice, effects),
+        NewNoteAction::Fade => voice.fading = true,
+    }
+
+    if voices.len() >= MAX_BACKGROUND_VOICES {
+        voices.remove(0);
+    }
+
+    voices.push(voice);
+}
@@ -0,0 +1,364 @@
+// xmodits core library
+// Copyright (c) 2023 B0ney
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::sync::Arc;
+
+use crate::dsp::{RawSample, SampleBuffer};
+use crate::interface::instrument::{Envelope, Instrument, NewNoteAction};
+use crate::interface::sample::{Loop, LoopType, Sample, SampleMeta};
+
+/// Sample data converted to floating point, ready to be played.
+pub(super) struct Decoded {
+    /// Frames of the left and right channel. Both are the same for mono samples.
+    left: Box<[f32]>,
+    right: Box<[f32]>,
+    pub rate: u32,
+    looping: Loop,
+    sustain: Option<Loop>,
+    pub meta: SampleMeta,
+}
+
+impl Decoded {
+    pub fn new(smp: &Sample, pcm: &[u8]) -> Option<Self> {
+        if pcm.len() < smp.bytes() as usize * smp.channels() as usize {
+            return None;
+        }
+
+        let buffer: SampleBuffer = RawSample::new(smp, pcm).into();
+
+        let (left, right): (Box<[f32]>, Box<[f32]>) = match smp.is_interleaved() {
+            // The buffer is split in half, so put the frames back together first
+            true => {
+                let frames = buffer.buf.concat();
+                let left = frames.iter().step_by(2).copied().collect();
+                let right = frames.iter().skip(1).step_by(2).copied().collect();
+                (left, right)
+            }
+            false => {
+                let mut channels = buffer.buf.into_iter();
+                let left: Box<[f32]> = channels.next().unwrap_or_default().into();
+                let right = channels.next().map_or_else(|| left.clone(), Into::into);
+                (left, right)
+            }
+        };
+
+        if left.is_empty() || right.len() < left.len() {
+            return None;
+        }
+
+        Some(Self {
+            left,
+            right,
+            rate: smp.rate,
+            looping: smp.looping,
+            sustain: smp.sustain,
+            meta: smp.meta,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.left.len()
+    }
+
+    /// The loop that applies, the sustain loop is used until the note is released
+    fn active_loop(&self, released: bool) -> Option<Loop> {
+        let looping = match (self.sustain, released) {
+            (Some(sustain), false) if !sustain.is_disabled() => sustain,
+            _ => self.looping,
+        };
+
+        let end = looping.end() as usize;
+        (!looping.is_disabled() && end <= self.len() && looping.start() < looping.end())
+            .then_some(looping)
+    }
+
+    /// Linearly interpolated frame at ``position``
+    fn frame(&self, position: f64) -> (f32, f32) {
+        let index = (position as usize).min(self.len() - 1);
+        let next = (index + 1).min(self.len() - 1);
+        let fraction = (position - index as f64) as f32;
+        let lerp = |buf: &[f32]| buf[index] + (buf[next] - buf[index]) * fraction;
+
+        (lerp(&self.left), lerp(&self.right))
+    }
+}
+
+/// Progress through an envelope
+#[derive(Default, Clone, Copy)]
+struct EnvelopeState {
+    tick: u16,
+}
+
+impl EnvelopeState {
+    /// Interpolated value of the envelope at the current tick
+    fn value(&self, envelope: &Envelope) -> Option<f32> {
+        let points = &envelope.points;
+
+        if !envelope.enabled || points.is_empty() {
+            return None;
+        }
+
+        let next = points
+            .iter()
+            .position(|point| point.tick > self.tick)
+            .unwrap_or(points.len());
+
+        let value = match next {
+            0 => points[0].value as f32,
+            n if n == points.len() => points[n - 1].value as f32,
+            n => {
+                let (a, b) = (points[n - 1], points[n]);
+                let fraction = (self.tick - a.tick) as f32 / (b.tick - a.tick) as f32;
+                a.value as f32 + (b.value as f32 - a.value as f32) * fraction
+            }
+        };
+
+        Some(value)
+    }
+
+    /// Move to the next tick, following the sustain loop until the note is released
+    fn advance(&mut self, envelope: &Envelope, released: bool) {
+        let points = &envelope.points;
+
+        if !envelope.enabled || points.is_empty() {
+            return;
+        }
+
+        let looping = match (envelope.sustain, released) {
+            (Some(sustain), false) => Some(sustain),
+            _ => envelope.looping,
+        };
+
+        match looping {
+            Some(looping) if self.tick >= points[looping.end as usize].tick => {
+                self.tick = points[looping.start as usize].tick;
+            }
+            _ => self.tick = self.tick.saturating_add(1),
+        }
+    }
+
+    fn finished(&self, envelope: &Envelope) -> bool {
+        envelope
+            .points
+            .last()
+            .is_none_or(|last| self.tick >= last.tick)
+    }
+}
+
+/// A sample being played
+pub(super) struct Voice {
+    pub sample: Arc<Decoded>,
+    pub instrument: Option<Arc<Instrument>>,
+    /// Pattern channel that started the voice
+    pub channel: usize,
+    position: f64,
+    backwards: bool,
+    /// Frames to advance for every output frame
+    pub step: f64,
+    /// Volume before envelopes and fadeout are applied (0.0 - 1.0)
+    pub volume: f32,
+    /// 0 (left) to 255 (right)
+    pub panning: f32,
+    volume_envelope: EnvelopeState,
+    panning_envelope: EnvelopeState,
+    pitch_envelope: EnvelopeState,
+    pub released: bool,
+    pub fading: bool,
+    fade: f32,
+    pub active: bool,
+}
+
+impl Voice {
+    pub fn new(
+        sample: Arc<Decoded>,
+        instrument: Option<Arc<Instrument>>,
+        channel: usize,
+        offset: usize,
+    ) -> Self {
+        let active = offset < sample.len();
+
+        Self {
+            sample,
+            instrument,
+            channel,
+            position: offset as f64,
+            backwards: false,
+            step: 0.0,
+            volume: 0.0,
+            panning: 128.0,
+            volume_envelope: Default::default(),
+            panning_envelope: Default::default(),
+            pitch_envelope: Default::default(),
+            released: false,
+            fading: false,
+            fade: 1.0,
+            active,
+        }
+    }
+
+    /// Restart the sample and envelopes
+    pub fn retrigger(&mut self) {
+        *self = Self::new(
+            self.sample.clone(),
+            self.instrument.clone(),
+            self.channel,
+            0,
+        );
+    }
+
+    pub fn nna(&self) -> NewNoteAction {
+        self.instrument
+            .as_ref()
+            .map_or(NewNoteAction::Cut, |instrument| instrument.nna)
+    }
+
+    pub fn has_volume_envelope(&self) -> bool {
+        self.instrument
+            .as_ref()
+            .is_some_and(|instrument| instrument.volume_envelope.enabled)
+    }
+
+    /// Pitch envelope in semitones
+    pub fn pitch_offset(&self) -> f64 {
+        match &self.instrument {
+            Some(instrument) if !instrument.filter => self
+                .pitch_envelope
+                .value(&instrument.pitch_envelope)
+                .map_or(0.0, |value| value as f64 / 2.0),
+            _ => 0.0,
+        }
+    }
+
+    /// Advance the envelopes and fadeout by a tick
+    pub fn tick(&mut self) {
+        let Some(instrument) = self.instrument.clone() else {
+            return;
+        };
+
+        let volume = &instrument.volume_envelope;
+
+        self.volume_envelope.advance(volume, self.released);
+        self.panning_envelope
+            .advance(&instrument.panning_envelope, self.released);
+        self.pitch_envelope
+            .advance(&instrument.pitch_envelope, self.released);
+
+        if volume.enabled && self.volume_envelope.finished(volume) {
+            // The note can never be heard again
+            if volume.points.last().is_some_and(|last| last.value == 0) {
+                self.active = false;
+            }
+
+            if self.released {
+                self.fading = true;
+            }
+        }
+
+        if self.released && volume.enabled && volume.looping.is_some() {
+            self.fading = true;
+        }
+
+        if self.fading {
+            self.fade -= instrument.fadeout as f32 / 65536.0;
+
+            if self.fade <= 0.0 {
+                self.fade = 0.0;
+                self.active = false;
+            }
+        }
+    }
+
+    /// Gain of the left and right channel, after envelopes and fadeout
+    fn gain(&self) -> (f32, f32) {
+        let mut volume = self.volume * self.fade;
+        let mut panning = self.panning;
+
+        if let Some(instrument) = &self.instrument {
+            if let Some(envelope) = self.volume_envelope.value(&instrument.volume_envelope) {
+                volume *= envelope / 64.0;
+            }
+
+            if let Some(envelope) = self.panning_envelope.value(&instrument.panning_envelope) {
+                let range = 128.0 - (panning - 128.0).abs();
+                panning = (panning + envelope / 32.0 * range).clamp(0.0, 255.0);
+            }
+        }
+
+        let right = panning / 255.0;
+        let left = 1.0 - right;
+
+        (
+            volume * (left * 2.0).min(1.0),
+            volume * (right * 2.0).min(1.0),
+        )
+    }
+
+    /// Add the voice to ``left`` and ``right``
+    pub fn mix(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if !self.active || self.step <= 0.0 {
+            return;
+        }
+
+        let (gain_left, gain_right) = self.gain();
+        let sample = self.sample.clone();
+        let looping = sample.active_loop(self.released);
+
+        for (out_left, out_right) in left.iter_mut().zip(right.iter_mut()) {
+            let (l, r) = sample.frame(self.position);
+            *out_left += l * gain_left;
+            *out_right += r * gain_right;
+
+            if !self.advance(looping) {
+                self.active = false;
+                break;
+            }
+        }
+    }
+
+    /// Move to the next frame, returns false once the sample has finished
+    fn advance(&mut self, looping: Option<Loop>) -> bool {
+        let len = self.sample.len() as f64;
+
+        match self.backwards {
+            true => self.position -= self.step,
+            false => self.position += self.step,
+        }
+
+        let Some(looping) = looping else {
+            return (0.0..len).contains(&self.position);
+        };
+
+        let (start, end) = (looping.start() as f64, looping.end() as f64);
+
+        match looping.kind() {
+            LoopType::PingPong => {
+                if self.position >= end || (self.backwards && self.position < start) {
+                    // Unfold the loop into a forward loop that is twice as long
+                    let length = end - start;
+                    let unfolded = match self.backwards {
+                        false => self.position - start,
+                        true => 2.0 * length - (self.position - start),
+                    }
+                    .rem_euclid(2.0 * length);
+
+                    self.backwards = unfolded >= length;
+                    self.position = match self.backwards {
+                        false => start + unfolded,
+                        true => start + 2.0 * length - unfolded,
+                    };
+                }
+            }
+            _ => {
+                if self.position >= end {
+                    self.position = start + (self.position - end) % (end - start);
+                }
+            }
+        }
+
+        true
+    }
+}